@@ -0,0 +1,189 @@
+//! Request and response bodies of the REST and WebSocket APIs that don't map
+//! onto a stored model. Keeping them typed is what lets the specs be
+//! generated from the code.
+
+/*---------- Imports ----------*/
+use crate::models::{
+    chat::{ChatType, MessageStatus, MessageType},
+    deletion::{DeletionStatus, DeletionStep},
+    export::ExportStatus,
+    user::User,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/*---------- REST ----------*/
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorBody {
+    /// Stable identifier clients can branch on, e.g. `CHAT_NOT_FOUND`.
+    pub code: String,
+
+    pub message: String,
+}
+
+/// A page of results. `nextCursor` is absent on the last page.
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DataPage<T> {
+    pub data: Vec<T>,
+
+    pub next_cursor: Option<String>,
+}
+
+/// A user found by search. Only what's needed to show them and start a chat,
+/// emails are left out so searching can't be used to collect addresses.
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSearchResult {
+    pub sub: String,
+
+    pub name: String,
+
+    pub avatar_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileUpdateRequest {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteRequest {
+    /// Address of the person to write to. People without an account get an
+    /// invitation, the others the message in a regular chat.
+    pub email: String,
+
+    /// Delivered as the first message of the chat once they sign up.
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteSent {
+    /// The address the message went to, after normalization. Whether it
+    /// was queued for an invitee or delivered to an existing account isn't
+    /// disclosed.
+    pub email: String,
+}
+
+/// Partial update of a chat's settings, absent fields are left as they are.
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSettingsUpdate {
+    pub muted: Option<bool>,
+
+    pub archived: Option<bool>,
+
+    pub pinned: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatClearRequest {
+    /// Also take the chat out of the list until a new message arrives.
+    #[serde(default)]
+    pub hide: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatCleared {
+    pub cleared_before: String,
+
+    pub hidden: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDeletionResult {
+    pub status: DeletionStatus,
+
+    pub completed_steps: Vec<DeletionStep>,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportAccepted {
+    pub job_id: String,
+
+    pub status: ExportStatus,
+
+    pub requested_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AvatarUploadTicket {
+    /// Presigned URL the picture must be sent to.
+    pub upload_url: String,
+
+    pub method: String,
+
+    pub expires_in: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AvatarReset {
+    /// Key of the full size avatar in the public media bucket.
+    pub avatar_key: String,
+}
+
+/*---------- WebSocket ----------*/
+/// `receive-message`, pushed to the receiver of a private message.
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceivedMessage {
+    pub timestamp: String,
+
+    pub message_type: MessageType,
+
+    pub chat_type: ChatType,
+
+    pub content: String,
+
+    pub message_id: String,
+
+    pub sender: User,
+}
+
+/// `message-status`, sent back to the sender once a message is handled.
+/// Successful sends carry `timestamp` and `messageId`, failed ones `code`
+/// and `message`.
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageStatusUpdate {
+    pub status: MessageStatus,
+
+    pub temp_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// `data-export-status`, sent when an export finishes.
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DataExportStatus {
+    pub job_id: String,
+
+    pub status: ExportStatus,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
@@ -0,0 +1,159 @@
+/*---------- Imports ----------*/
+use super::dto::{ErrorBody, MessageStatusUpdate};
+use super::response::json_response;
+use crate::mail::MailError;
+use crate::models::{chat::MessageStatus, common::WebSocketEvent};
+use crate::repository::RepositoryError;
+use crate::utils::user::GetUserError;
+use lambda_http::{Body, Response};
+use serde_json::{json, Value};
+use tracing::error;
+
+/*---------- Constants ----------*/
+/// What clients get for failures they can't act on. The cause is logged
+/// instead, as it may describe the infrastructure.
+const INTERNAL_ERROR_MESSAGE: &str = "Internal error";
+
+/*---------- Enums ----------*/
+/// Every error a client can receive, over REST or WebSocket. The codes are
+/// part of the API contract: clients branch on them, so they must never be
+/// renamed. Messages are for humans and may change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    MissingToken,
+    InvalidToken,
+    InvalidBody,
+    Validation(String),
+    InvalidCursor,
+    RouteNotFound,
+    MethodNotAllowed,
+    UserNotFound,
+    ChatNotFound,
+    SelfMessage,
+    MessageNotSaved,
+    RateLimited,
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::MissingToken => "MISSING_TOKEN",
+            ApiError::InvalidToken => "INVALID_TOKEN",
+            ApiError::InvalidBody => "INVALID_BODY",
+            ApiError::Validation(_) => "VALIDATION_FAILED",
+            ApiError::InvalidCursor => "INVALID_CURSOR",
+            ApiError::RouteNotFound => "ROUTE_NOT_FOUND",
+            ApiError::MethodNotAllowed => "METHOD_NOT_ALLOWED",
+            ApiError::UserNotFound => "USER_NOT_FOUND",
+            ApiError::ChatNotFound => "CHAT_NOT_FOUND",
+            ApiError::SelfMessage => "SELF_MESSAGE",
+            ApiError::MessageNotSaved => "MESSAGE_NOT_SAVED",
+            ApiError::RateLimited => "RATE_LIMITED",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn status(&self) -> u16 {
+        match self {
+            ApiError::MissingToken | ApiError::InvalidToken => 401,
+            ApiError::InvalidBody
+            | ApiError::Validation(_)
+            | ApiError::InvalidCursor
+            | ApiError::SelfMessage => 400,
+            ApiError::RouteNotFound | ApiError::UserNotFound | ApiError::ChatNotFound => 404,
+            ApiError::MethodNotAllowed => 405,
+            ApiError::RateLimited => 429,
+            ApiError::MessageNotSaved | ApiError::Internal(_) => 500,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let message = match self {
+            ApiError::MissingToken => "Missing authentication token",
+            ApiError::InvalidToken => "Invalid user token",
+            ApiError::InvalidBody => "Request body must be valid JSON matching the schema",
+            ApiError::Validation(message) => message,
+            ApiError::InvalidCursor => "Invalid cursor",
+            ApiError::RouteNotFound => "Route not found",
+            ApiError::MethodNotAllowed => "Method not allowed",
+            ApiError::UserNotFound => "User not found",
+            ApiError::ChatNotFound => "Chat not found",
+            ApiError::SelfMessage => "You can't send a message to yourself",
+            ApiError::MessageNotSaved => "The message couldn't be saved",
+            ApiError::RateLimited => "Too many requests, try again later",
+            ApiError::Internal(message) => message,
+        };
+
+        message.to_owned()
+    }
+
+    /// `{"code": ..., "message": ...}`, the error body shared by REST and
+    /// WebSocket responses.
+    pub fn body(&self) -> Value {
+        json!(ErrorBody {
+            code: self.code().to_owned(),
+            message: self.message(),
+        })
+    }
+
+    pub fn into_response(self) -> Response<Body> {
+        json_response(self.status(), &self.body())
+    }
+
+    /// `message-status` frame for the WebSocket API. `temp_id` is echoed so
+    /// the client can tell which pending message failed.
+    pub fn to_message_status(&self, temp_id: Option<&str>) -> Value {
+        json!(WebSocketEvent {
+            action: "message-status".to_owned(),
+            data: MessageStatusUpdate {
+                status: MessageStatus::Error,
+                temp_id: temp_id.map(|temp_id| temp_id.to_owned()),
+                timestamp: None,
+                message_id: None,
+                code: Some(self.code().to_owned()),
+                message: Some(self.message()),
+            },
+        })
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<GetUserError> for ApiError {
+    fn from(error: GetUserError) -> Self {
+        match error {
+            GetUserError::NotFound => ApiError::UserNotFound,
+            GetUserError::InvalidCursor => ApiError::InvalidCursor,
+            other => {
+                error!("User lookup failed: {}", other);
+                ApiError::Internal(INTERNAL_ERROR_MESSAGE.to_owned())
+            }
+        }
+    }
+}
+
+impl From<RepositoryError> for ApiError {
+    fn from(error: RepositoryError) -> Self {
+        match error {
+            RepositoryError::InvalidCursor => ApiError::InvalidCursor,
+            other => {
+                error!("Repository request failed: {}", other);
+                ApiError::Internal(INTERNAL_ERROR_MESSAGE.to_owned())
+            }
+        }
+    }
+}
+
+impl From<MailError> for ApiError {
+    fn from(error: MailError) -> Self {
+        error!("Couldn't send an email: {}", error);
+        ApiError::Internal(INTERNAL_ERROR_MESSAGE.to_owned())
+    }
+}
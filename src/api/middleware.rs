@@ -0,0 +1,128 @@
+/*---------- Imports ----------*/
+use super::error::ApiError;
+use crate::{models::user::User, utils::jwt::Jwt};
+use lambda_http::{
+    http::{HeaderMap, HeaderValue},
+    Body, Request, Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use ulid::Ulid;
+
+/*---------- Constants ----------*/
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/*---------- Structs ----------*/
+/// CORS headers added to every response, including errors and preflights.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    pub allow_origin: String,
+    pub allow_headers: String,
+    pub allow_methods: String,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allow_origin: "*".to_owned(),
+            allow_headers: "Content-Type".to_owned(),
+            allow_methods: "*".to_owned(),
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Reads `CORS_ALLOW_ORIGIN`, `CORS_ALLOW_HEADERS` and
+    /// `CORS_ALLOW_METHODS`, falling back to the permissive defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        Self {
+            allow_origin: env::var("CORS_ALLOW_ORIGIN").unwrap_or(defaults.allow_origin),
+            allow_headers: env::var("CORS_ALLOW_HEADERS").unwrap_or(defaults.allow_headers),
+            allow_methods: env::var("CORS_ALLOW_METHODS").unwrap_or(defaults.allow_methods),
+        }
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        let cors_headers = [
+            ("Access-Control-Allow-Headers", &self.allow_headers),
+            ("Access-Control-Allow-Origin", &self.allow_origin),
+            ("Access-Control-Allow-Methods", &self.allow_methods),
+        ];
+
+        for (name, value) in cors_headers {
+            if let Ok(header_value) = HeaderValue::from_str(value) {
+                headers.insert(name, header_value);
+            }
+        }
+    }
+}
+
+/// Identity claims of the ID token. API Gateway has already verified the
+/// signature through the Cognito authorizer, so only the payload is read.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenClaims {
+    #[serde(rename = "cognito:username")]
+    pub username: String,
+
+    #[serde(flatten)]
+    pub user: User,
+}
+
+pub fn authenticate(request: &Request) -> Result<TokenClaims, ApiError> {
+    let id_token = match request.headers().get("authorization") {
+        Some(token) => token.to_str().map_err(|_| ApiError::InvalidToken)?,
+        None => return Err(ApiError::MissingToken),
+    };
+
+    let id_token = id_token.strip_prefix("Bearer ").unwrap_or(id_token);
+
+    Jwt::decode_payload(id_token).map_err(|_| ApiError::InvalidToken)
+}
+
+/// Reuses the caller's request id when there is one, so a request can be
+/// followed across services, and makes one up otherwise.
+pub fn request_id(request: &Request) -> String {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_owned())
+        .unwrap_or_else(|| Ulid::new().to_string())
+}
+
+/// Parses the body once for every route. Empty bodies are allowed, anything
+/// else must be JSON.
+pub fn parse_json_body(request: &Request) -> Result<Option<Value>, ApiError> {
+    let body_bytes: &[u8] = match request.body() {
+        Body::Empty => return Ok(None),
+        body => body.as_ref(),
+    };
+
+    if body_bytes.iter().all(|byte| byte.is_ascii_whitespace()) {
+        return Ok(None);
+    }
+
+    serde_json::from_slice(body_bytes)
+        .map(Some)
+        .map_err(|_| ApiError::InvalidBody)
+}
+
+pub fn finalize_response(
+    mut response: Response<Body>,
+    cors: &CorsConfig,
+    request_id: &str,
+) -> Response<Body> {
+    let headers = response.headers_mut();
+
+    cors.apply(headers);
+
+    if let Ok(header_value) = HeaderValue::from_str(request_id) {
+        headers.insert(REQUEST_ID_HEADER, header_value);
+    }
+
+    response
+}
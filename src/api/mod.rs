@@ -0,0 +1,10 @@
+//! Routing and shared middleware for the REST API, so every endpoint runs
+//! inside a single `lambda_http` binary.
+
+pub mod dto;
+pub mod error;
+pub mod middleware;
+pub mod response;
+pub mod router;
+pub mod routes;
+pub mod spec;
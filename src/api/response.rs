@@ -0,0 +1,20 @@
+/*---------- Imports ----------*/
+use lambda_http::{Body, Response};
+use serde::Serialize;
+
+/// Serializes `body` as the JSON response. CORS and request id headers are
+/// added by the router, handlers don't need to set them.
+pub fn json_response<T: Serialize>(status: u16, body: &T) -> Response<Body> {
+    let serialized_body = serde_json::to_string(body).unwrap_or_default();
+
+    let mut response = Response::new(Body::from(serialized_body));
+    *response.status_mut() = lambda_http::http::StatusCode::from_u16(status)
+        .unwrap_or(lambda_http::http::StatusCode::INTERNAL_SERVER_ERROR);
+
+    response.headers_mut().insert(
+        "Content-Type",
+        lambda_http::http::HeaderValue::from_static("application/json"),
+    );
+
+    response
+}
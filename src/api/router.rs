@@ -0,0 +1,489 @@
+/*---------- Imports ----------*/
+use super::error::ApiError;
+use super::middleware::{self, CorsConfig, TokenClaims};
+use lambda_http::{http::Method, Body, Request, RequestExt, Response};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+use tracing::error;
+
+/*---------- Types ----------*/
+pub type RouteResult = Result<Response<Body>, ApiError>;
+pub type RouteFuture = Pin<Box<dyn Future<Output = RouteResult> + Send>>;
+type BoxedHandler<S> = Box<dyn Fn(Arc<S>, RouteRequest) -> RouteFuture + Send + Sync>;
+
+/*---------- Enums ----------*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Public,
+    Authenticated,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+}
+
+enum RouteMatch<'a, S> {
+    Found(&'a Route<S>, HashMap<String, String>),
+    MethodNotAllowed,
+    NotFound,
+}
+
+/*---------- Structs ----------*/
+/// What a handler gets: the raw request plus everything the middleware
+/// already worked out.
+pub struct RouteRequest {
+    pub request: Request,
+    pub request_id: String,
+    pub path_params: HashMap<String, String>,
+    pub claims: Option<TokenClaims>,
+    pub body: Option<Value>,
+}
+
+impl RouteRequest {
+    /// Claims of the caller. Only missing on public routes.
+    pub fn claims(&self) -> Result<&TokenClaims, ApiError> {
+        self.claims.as_ref().ok_or(ApiError::MissingToken)
+    }
+
+    pub fn path_param(&self, name: &str) -> Option<&str> {
+        self.path_params.get(name).map(|value| value.as_str())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.request
+            .query_string_parameters()
+            .first(name)
+            .map(|value| value.to_owned())
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ApiError> {
+        let body = self.body.clone().unwrap_or(Value::Null);
+
+        serde_json::from_value(body).map_err(|_| ApiError::InvalidBody)
+    }
+}
+
+#[cfg(test)]
+impl RouteRequest {
+    /// What a handler gets when `user` calls it, so handlers can be run
+    /// without going through the router.
+    pub fn for_user(
+        user: &crate::models::user::User,
+        path_params: &[(&str, &str)],
+        query: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> Self {
+        let to_map = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+
+        let request = lambda_http::http::Request::builder()
+            .body(Body::Empty)
+            .unwrap()
+            .with_query_string_parameters(to_map(query));
+
+        Self {
+            request,
+            request_id: "request-1".to_owned(),
+            path_params: to_map(path_params),
+            claims: Some(TokenClaims {
+                username: user.sub.to_owned(),
+                user: user.clone(),
+            }),
+            body,
+        }
+    }
+}
+
+struct Route<S> {
+    method: Method,
+    segments: Vec<Segment>,
+    access: Access,
+    handler: BoxedHandler<S>,
+}
+
+/// Maps method and path to handlers. Paths use API Gateway's `{param}`
+/// syntax so they read the same as the SAM template.
+pub struct Router<S> {
+    state: Arc<S>,
+    cors: CorsConfig,
+    routes: Vec<Route<S>>,
+}
+
+fn parse_segments(path: &str) -> Vec<Segment> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            match segment
+                .strip_prefix('{')
+                .and_then(|rest| rest.strip_suffix('}'))
+            {
+                Some(name) => Segment::Param(name.to_owned()),
+                None => Segment::Literal(segment.to_owned()),
+            }
+        })
+        .collect()
+}
+
+fn match_segments(segments: &[Segment], path: &str) -> Option<HashMap<String, String>> {
+    let path_parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+
+    if path_parts.len() != segments.len() {
+        return None;
+    }
+
+    let mut params = HashMap::new();
+
+    for (segment, part) in segments.iter().zip(path_parts) {
+        match segment {
+            Segment::Literal(literal) if literal == part => {}
+            Segment::Literal(_) => return None,
+            Segment::Param(name) => {
+                params.insert(name.to_owned(), part.to_owned());
+            }
+        }
+    }
+
+    Some(params)
+}
+
+impl<S: Send + Sync + 'static> Router<S> {
+    pub fn new(state: S) -> Self {
+        Self {
+            state: Arc::new(state),
+            cors: CorsConfig::default(),
+            routes: vec![],
+        }
+    }
+
+    pub fn with_cors(mut self, cors: CorsConfig) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    pub fn route<F, Fut>(mut self, method: Method, path: &str, access: Access, handler: F) -> Self
+    where
+        F: Fn(Arc<S>, RouteRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RouteResult> + Send + 'static,
+    {
+        self.routes.push(Route {
+            method,
+            segments: parse_segments(path),
+            access,
+            handler: Box::new(move |state, request| Box::pin(handler(state, request))),
+        });
+
+        self
+    }
+
+    pub fn get<F, Fut>(self, path: &str, handler: F) -> Self
+    where
+        F: Fn(Arc<S>, RouteRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RouteResult> + Send + 'static,
+    {
+        self.route(Method::GET, path, Access::Authenticated, handler)
+    }
+
+    pub fn post<F, Fut>(self, path: &str, handler: F) -> Self
+    where
+        F: Fn(Arc<S>, RouteRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RouteResult> + Send + 'static,
+    {
+        self.route(Method::POST, path, Access::Authenticated, handler)
+    }
+
+    pub fn patch<F, Fut>(self, path: &str, handler: F) -> Self
+    where
+        F: Fn(Arc<S>, RouteRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RouteResult> + Send + 'static,
+    {
+        self.route(Method::PATCH, path, Access::Authenticated, handler)
+    }
+
+    pub fn delete<F, Fut>(self, path: &str, handler: F) -> Self
+    where
+        F: Fn(Arc<S>, RouteRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RouteResult> + Send + 'static,
+    {
+        self.route(Method::DELETE, path, Access::Authenticated, handler)
+    }
+
+    fn find(&self, method: &Method, path: &str) -> RouteMatch<'_, S> {
+        let mut path_matched = false;
+
+        for route in self.routes.iter() {
+            if let Some(params) = match_segments(&route.segments, path) {
+                if route.method == method {
+                    return RouteMatch::Found(route, params);
+                }
+
+                path_matched = true;
+            }
+        }
+
+        if path_matched {
+            RouteMatch::MethodNotAllowed
+        } else {
+            RouteMatch::NotFound
+        }
+    }
+
+    /// Runs the request through the middleware and the matching handler.
+    /// Never fails: every error ends up as a JSON response.
+    pub async fn handle(&self, request: Request) -> Response<Body> {
+        let request_id = middleware::request_id(&request);
+        let response = self.dispatch(request, &request_id).await;
+
+        middleware::finalize_response(response, &self.cors, &request_id)
+    }
+
+    async fn dispatch(&self, request: Request, request_id: &str) -> Response<Body> {
+        if request.method() == Method::OPTIONS {
+            let mut response = Response::new(Body::Empty);
+            *response.status_mut() = lambda_http::http::StatusCode::NO_CONTENT;
+
+            return response;
+        }
+
+        // On a REST API stage the URI path starts with the stage name, the
+        // raw path is the one the routes are declared with. Requests built
+        // by hand, like the local server's, only have the URI
+        let path = match request.raw_http_path() {
+            raw_path if raw_path.is_empty() => request.uri().path().to_owned(),
+            raw_path => raw_path,
+        };
+
+        let (route, path_params) = match self.find(request.method(), &path) {
+            RouteMatch::Found(route, params) => (route, params),
+            RouteMatch::MethodNotAllowed => return ApiError::MethodNotAllowed.into_response(),
+            RouteMatch::NotFound => return ApiError::RouteNotFound.into_response(),
+        };
+
+        let claims = match route.access {
+            Access::Authenticated => match middleware::authenticate(&request) {
+                Ok(claims) => Some(claims),
+                Err(error) => return error.into_response(),
+            },
+            Access::Public => middleware::authenticate(&request).ok(),
+        };
+
+        let body = match middleware::parse_json_body(&request) {
+            Ok(body) => body,
+            Err(error) => return error.into_response(),
+        };
+
+        let route_request = RouteRequest {
+            request,
+            request_id: request_id.to_owned(),
+            path_params,
+            claims,
+            body,
+        };
+
+        // Handlers run on their own task so a panic only takes down the
+        // request, and the client still gets a proper 500
+        let handler_future = (route.handler)(self.state.clone(), route_request);
+
+        match tokio::spawn(handler_future).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(error)) => error.into_response(),
+            Err(join_error) => {
+                error!("Request {} failed: {}", request_id, join_error);
+
+                ApiError::Internal("Internal server error".to_owned()).into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::response::json_response;
+    use base64::{engine::general_purpose, Engine};
+    use serde_json::json;
+
+    /// What the Lambda runtime hands over for a call to the `Prod` stage of
+    /// a REST API.
+    fn proxy_event(method: &str, path: &str, token: Option<&str>, body: Option<&str>) -> Request {
+        let mut headers = json!({
+            "Host": "abc123.execute-api.eu-west-1.amazonaws.com",
+            "x-request-id": "request-1",
+        });
+
+        if let Some(token) = token {
+            headers["authorization"] = json!(format!("Bearer {}", token));
+        }
+
+        let event = json!({
+            "resource": path,
+            "path": path,
+            "httpMethod": method,
+            "headers": headers,
+            "multiValueHeaders": {},
+            "queryStringParameters": {},
+            "multiValueQueryStringParameters": {},
+            "pathParameters": {},
+            "stageVariables": {},
+            "requestContext": {
+                "accountId": "123456789012",
+                "resourceId": "abc123",
+                "stage": "Prod",
+                "requestId": "request-1",
+                "requestTimeEpoch": 1583798639428_i64,
+                "identity": { "sourceIp": "192.168.100.1" },
+                "resourcePath": path,
+                "httpMethod": method,
+                "apiId": "abc123",
+            },
+            "body": body,
+            "isBase64Encoded": false,
+        });
+
+        lambda_http::request::from_str(&event.to_string()).unwrap()
+    }
+
+    fn token() -> String {
+        let payload = json!({
+            "sub": "user-1",
+            "name": "Jane",
+            "email": "jane@example.com",
+            "cognito:username": "jane",
+        });
+
+        format!(
+            "header.{}.signature",
+            general_purpose::URL_SAFE_NO_PAD.encode(payload.to_string())
+        )
+    }
+
+    fn router() -> Router<()> {
+        Router::new(())
+            .get("/chats/{id}", |_, request: RouteRequest| async move {
+                Ok(json_response(
+                    200,
+                    &json!({
+                        "id": request.path_param("id"),
+                        "sub": request.claims()?.user.sub,
+                    }),
+                ))
+            })
+            .route(
+                Method::POST,
+                "/echo",
+                Access::Public,
+                |_, request: RouteRequest| async move { Ok(json_response(200, &request.body)) },
+            )
+            .get("/panic", |_, _| async move {
+                panic!("handler bug");
+            })
+    }
+
+    fn json_body(response: &Response<Body>) -> Value {
+        serde_json::from_slice(response.body().as_ref()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn stage_prefixed_requests_reach_their_route() {
+        let request = proxy_event("GET", "/chats/user-2", Some(&token()), None);
+
+        assert_eq!(request.uri().path(), "/Prod/chats/user-2");
+
+        let response = router().handle(request).await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            json_body(&response),
+            json!({ "id": "user-2", "sub": "user-1" })
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_raw_path_are_matched_on_the_uri() {
+        let request = lambda_http::http::Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost:3000/echo")
+            .body(Body::from(r#"{"hello":"world"}"#))
+            .unwrap();
+
+        let response = router().handle(request).await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(json_body(&response), json!({ "hello": "world" }));
+    }
+
+    #[tokio::test]
+    async fn unknown_paths_and_methods_are_told_apart() {
+        let not_found = router()
+            .handle(proxy_event("GET", "/nothing", Some(&token()), None))
+            .await;
+        let wrong_method = router()
+            .handle(proxy_event("DELETE", "/chats/user-2", Some(&token()), None))
+            .await;
+
+        assert_eq!(not_found.status(), 404);
+        assert_eq!(json_body(&not_found)["code"], "ROUTE_NOT_FOUND");
+        assert_eq!(wrong_method.status(), 405);
+        assert_eq!(json_body(&wrong_method)["code"], "METHOD_NOT_ALLOWED");
+    }
+
+    #[tokio::test]
+    async fn authenticated_routes_need_a_readable_token() {
+        let missing = router()
+            .handle(proxy_event("GET", "/chats/user-2", None, None))
+            .await;
+        let invalid = router()
+            .handle(proxy_event("GET", "/chats/user-2", Some("a.b.c"), None))
+            .await;
+
+        assert_eq!(missing.status(), 401);
+        assert_eq!(json_body(&missing)["code"], "MISSING_TOKEN");
+        assert_eq!(invalid.status(), 401);
+        assert_eq!(json_body(&invalid)["code"], "INVALID_TOKEN");
+    }
+
+    #[tokio::test]
+    async fn bodies_must_be_json() {
+        let response = router()
+            .handle(proxy_event("POST", "/echo", None, Some("{not json")))
+            .await;
+
+        assert_eq!(response.status(), 400);
+        assert_eq!(json_body(&response)["code"], "INVALID_BODY");
+    }
+
+    #[tokio::test]
+    async fn panics_become_internal_errors() {
+        let response = router()
+            .handle(proxy_event("GET", "/panic", Some(&token()), None))
+            .await;
+
+        assert_eq!(response.status(), 500);
+        assert_eq!(json_body(&response)["code"], "INTERNAL_ERROR");
+    }
+
+    #[tokio::test]
+    async fn every_response_carries_cors_and_the_request_id() {
+        let preflight = router()
+            .handle(proxy_event("OPTIONS", "/chats/user-2", None, None))
+            .await;
+        let not_found = router()
+            .handle(proxy_event("GET", "/nothing", None, None))
+            .await;
+
+        assert_eq!(preflight.status(), 204);
+
+        for response in [preflight, not_found] {
+            let headers = response.headers();
+
+            assert_eq!(headers["Access-Control-Allow-Origin"], "*");
+            assert_eq!(headers[middleware::REQUEST_ID_HEADER], "request-1");
+        }
+    }
+}
@@ -0,0 +1,437 @@
+/*---------- Imports ----------*/
+use super::ApiState;
+use crate::api::dto::{ChatClearRequest, ChatCleared, ChatSettingsUpdate, DataPage};
+use crate::api::error::ApiError;
+use crate::api::response::json_response;
+use crate::api::router::{RouteRequest, RouteResult};
+use crate::models::{
+    chat::{Chat, ChatSettings, ChatSummary, ChatType, ChatView},
+    user::User,
+};
+use crate::repository::{ChatListQuery, RepositoryError};
+use crate::utils::user::{GetUserError, User as UserService};
+use chrono::{DateTime, SecondsFormat};
+use std::{str::FromStr, sync::Arc};
+use ulid::Ulid;
+
+/*---------- Constants ----------*/
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 100;
+
+fn parse_list_query(request: &RouteRequest) -> Result<ChatListQuery, String> {
+    let limit = match request.query_param("limit") {
+        Some(value) => match value.parse::<u32>() {
+            Ok(parsed) if (1..=MAX_PAGE_SIZE).contains(&parsed) => parsed,
+            _ => {
+                return Err(format!(
+                    "limit must be a number between 1 and {}",
+                    MAX_PAGE_SIZE
+                ))
+            }
+        },
+        None => DEFAULT_PAGE_SIZE,
+    };
+
+    let view = match request.query_param("view") {
+        Some(value) => ChatView::from_str(&value)?,
+        None => ChatView::Inbox,
+    };
+
+    let chat_type = match request.query_param("type") {
+        Some(value) => Some(ChatType::from_str(&value)?),
+        None => None,
+    };
+
+    let unread_only = match request.query_param("unread").as_deref() {
+        Some("true") => true,
+        Some("false") | None => false,
+        Some(_) => return Err("unread must be either true or false".to_owned()),
+    };
+
+    // Normalized to the same format used by the stored timestamps, so the
+    // comparison against the sort key holds
+    let updated_since = match request.query_param("updatedSince") {
+        Some(value) => match DateTime::parse_from_rfc3339(&value) {
+            Ok(parsed) => Some(
+                parsed
+                    .with_timezone(&chrono::Utc)
+                    .to_rfc3339_opts(SecondsFormat::Millis, true),
+            ),
+            Err(_) => return Err("updatedSince must be an RFC 3339 timestamp".to_owned()),
+        },
+        None => None,
+    };
+
+    Ok(ChatListQuery {
+        limit: Some(limit),
+        cursor: request.query_param("cursor"),
+        chat_type,
+        unread_only,
+        updated_since,
+        view,
+    })
+}
+
+/// `GET /chats`
+pub async fn list_chats(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user = &request.claims()?.user;
+    let list_query = parse_list_query(&request).map_err(ApiError::Validation)?;
+
+    match state.repository.list_chats(&user.sub, &list_query).await {
+        Ok(page) => {
+            let chats: Vec<ChatSummary> = page.items.into_iter().map(ChatSummary::from).collect();
+
+            Ok(json_response(
+                200,
+                &DataPage {
+                    data: chats,
+                    next_cursor: page.next_cursor,
+                },
+            ))
+        }
+        Err(RepositoryError::InvalidCursor) => Err(ApiError::InvalidCursor),
+        Err(_) => Err(ApiError::Internal(
+            "An error ocurred while fetching the chats".to_owned(),
+        )),
+    }
+}
+
+/// `GET /chats/{id}`
+pub async fn get_chat_details(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user = &request.claims()?.user;
+
+    let chat_id = request
+        .path_param("id")
+        .ok_or_else(|| ApiError::Validation("Missing chat id".to_owned()))?;
+
+    // Summaries only exist on the participants' partitions, so finding one
+    // under the caller's own key is what proves they belong to the chat
+    let chat_item = match state.repository.get_private_chat(&user.sub, chat_id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(ApiError::ChatNotFound),
+        Err(_) => {
+            return Err(ApiError::Internal(
+                "An error ocurred while fetching the chat".to_owned(),
+            ))
+        }
+    };
+
+    let other_participant = match UserService::get_user_by_sub(
+        &state.cognito_client,
+        &state.userpool_id,
+        chat_id,
+    )
+    .await
+    {
+        Ok(profile) => profile,
+
+        // The other account is gone, fall back to what the summary holds
+        Err(GetUserError::NotFound) => User::new(chat_id, &chat_item.title, ""),
+
+        Err(_) => {
+            return Err(ApiError::Internal(
+                "An error ocurred while fetching the participants".to_owned(),
+            ))
+        }
+    };
+
+    let participants = vec![chat_item.user.clone(), other_participant];
+
+    Ok(json_response(
+        200,
+        &Chat::from_item(chat_item, participants),
+    ))
+}
+
+/// `PATCH /chats/{id}/settings`
+pub async fn update_chat_settings(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user = &request.claims()?.user;
+    let body: ChatSettingsUpdate = request.json()?;
+
+    let chat_id = request
+        .path_param("id")
+        .ok_or_else(|| ApiError::Validation("Missing chat id".to_owned()))?;
+
+    if body.muted.is_none() && body.archived.is_none() && body.pinned.is_none() {
+        return Err(ApiError::Validation(
+            "At least one of muted, archived or pinned must be set".to_owned(),
+        ));
+    }
+
+    let chat_item = match state.repository.get_private_chat(&user.sub, chat_id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(ApiError::ChatNotFound),
+        Err(_) => {
+            return Err(ApiError::Internal(
+                "An error ocurred while fetching the chat".to_owned(),
+            ))
+        }
+    };
+
+    let settings = ChatSettings {
+        muted: body.muted.unwrap_or(chat_item.settings.muted),
+        archived: body.archived.unwrap_or(chat_item.settings.archived),
+        pinned: body.pinned.unwrap_or(chat_item.settings.pinned),
+    };
+
+    state
+        .repository
+        .update_chat_settings(&user.sub, chat_id, &settings)
+        .await
+        .map_err(|_| {
+            ApiError::Internal("An error ocurred while updating the chat settings".to_owned())
+        })?;
+
+    Ok(json_response(200, &settings))
+}
+
+/// `POST /chats/{id}/clear`
+pub async fn clear_chat(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user = &request.claims()?.user;
+    let body: ChatClearRequest = request.json()?;
+
+    let chat_id = request
+        .path_param("id")
+        .ok_or_else(|| ApiError::Validation("Missing chat id".to_owned()))?;
+
+    match state.repository.get_private_chat(&user.sub, chat_id).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return Err(ApiError::ChatNotFound),
+        Err(_) => {
+            return Err(ApiError::Internal(
+                "An error ocurred while fetching the chat".to_owned(),
+            ))
+        }
+    };
+
+    // Message ids are ULIDs too, so a fresh one sorts after everything sent
+    // up to now
+    let cleared_before = Ulid::new().to_string();
+
+    state
+        .repository
+        .clear_chat_history(&user.sub, chat_id, &cleared_before, body.hide)
+        .await
+        .map_err(|_| ApiError::Internal("An error ocurred while clearing the chat".to_owned()))?;
+
+    Ok(json_response(
+        200,
+        &ChatCleared {
+            cleared_before,
+            hidden: body.hide,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::chat::{ChatItem, Message, MessageType};
+    use crate::repository::{memory::InMemoryChatRepository, ChatRepository};
+    use lambda_http::{Body, Response};
+    use serde_json::Value;
+
+    const USER_SUB: &str = "user-1";
+
+    fn owner() -> User {
+        User::new(USER_SUB, "Jane", "jane@example.com")
+    }
+
+    /// A chat with `other_sub` whose last message was sent on `day`.
+    async fn add_chat(
+        repository: &InMemoryChatRepository,
+        other_sub: &str,
+        day: u32,
+        unread_messages: u32,
+    ) {
+        let other = User::new(other_sub, other_sub, "");
+        let timestamp = format!("2023-01-{:02}T00:00:00.000Z", day);
+        let message = Message::private(
+            &other,
+            USER_SUB,
+            &Ulid::new().to_string(),
+            &timestamp,
+            "Hi",
+            MessageType::Text,
+        );
+        let mut chat = ChatItem::private(&owner(), &other, &message);
+
+        chat.unread_messages = unread_messages;
+        repository.upsert_chat_summary(&chat).await.unwrap();
+    }
+
+    /// Six chats, newest first: one hidden, one archived and one pinned in
+    /// between the regular ones.
+    async fn state() -> Arc<ApiState> {
+        let repository = Arc::new(InMemoryChatRepository::new());
+
+        add_chat(&repository, "user-a", 1, 0).await;
+        add_chat(&repository, "user-b", 2, 3).await;
+        add_chat(&repository, "user-c", 3, 0).await;
+        add_chat(&repository, "user-d", 4, 0).await;
+        add_chat(&repository, "user-e", 5, 0).await;
+        add_chat(&repository, "user-f", 6, 0).await;
+
+        let pinned = ChatSettings {
+            pinned: true,
+            ..ChatSettings::default()
+        };
+        let archived = ChatSettings {
+            archived: true,
+            ..ChatSettings::default()
+        };
+
+        repository
+            .update_chat_settings(USER_SUB, "user-c", &pinned)
+            .await
+            .unwrap();
+        repository
+            .update_chat_settings(USER_SUB, "user-d", &archived)
+            .await
+            .unwrap();
+        repository
+            .clear_chat_history(USER_SUB, "user-e", "2023-01-05T00:00:00.000Z", true)
+            .await
+            .unwrap();
+
+        Arc::new(ApiState::in_memory(repository))
+    }
+
+    fn json_body(response: &Response<Body>) -> Value {
+        serde_json::from_slice(response.body().as_ref()).unwrap()
+    }
+
+    async fn list(state: &Arc<ApiState>, query: &[(&str, &str)]) -> Value {
+        let request = RouteRequest::for_user(&owner(), &[], query, None);
+        let response = list_chats(state.clone(), request).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+
+        json_body(&response)
+    }
+
+    fn chat_ids(page: &Value) -> Vec<&str> {
+        page["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|chat| chat["chatId"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn the_inbox_starts_with_pinned_chats() {
+        let state = state().await;
+        let page = list(&state, &[]).await;
+
+        assert_eq!(
+            chat_ids(&page),
+            vec!["user-c", "user-f", "user-b", "user-a"]
+        );
+        assert!(page["nextCursor"].is_null());
+    }
+
+    #[tokio::test]
+    async fn cursors_walk_the_inbox_once() {
+        let state = state().await;
+        let mut pages: Vec<Vec<String>> = vec![];
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut query = vec![("limit", "2")];
+
+            if let Some(cursor) = cursor.as_deref() {
+                query.push(("cursor", cursor));
+            }
+
+            let page = list(&state, &query).await;
+
+            pages.push(chat_ids(&page).iter().map(|id| id.to_string()).collect());
+
+            match page["nextCursor"].as_str() {
+                Some(next_cursor) => cursor = Some(next_cursor.to_owned()),
+                None => break,
+            }
+        }
+
+        // Filters apply after the limit, like a DynamoDB filter expression,
+        // so the page holding only archived and pinned chats is empty but
+        // still leads on. Pinned chats only come with the first page
+        assert_eq!(
+            pages,
+            vec![
+                vec!["user-c".to_owned(), "user-f".to_owned()],
+                vec![],
+                vec!["user-b".to_owned(), "user-a".to_owned()],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn views_pick_their_chats() {
+        let state = state().await;
+
+        assert_eq!(
+            chat_ids(&list(&state, &[("view", "archived")]).await),
+            vec!["user-d"]
+        );
+        assert_eq!(
+            chat_ids(&list(&state, &[("view", "pinned")]).await),
+            vec!["user-c"]
+        );
+        assert_eq!(
+            chat_ids(&list(&state, &[("view", "all")]).await),
+            vec!["user-f", "user-e", "user-d", "user-c", "user-b", "user-a"]
+        );
+    }
+
+    #[tokio::test]
+    async fn filters_narrow_every_view() {
+        let state = state().await;
+
+        assert_eq!(
+            chat_ids(&list(&state, &[("unread", "true")]).await),
+            vec!["user-b"]
+        );
+        assert_eq!(
+            chat_ids(&list(&state, &[("type", "private")]).await),
+            vec!["user-c", "user-f", "user-b", "user-a"]
+        );
+        assert_eq!(
+            chat_ids(&list(&state, &[("updatedSince", "2023-01-02T01:00:00+01:00")]).await),
+            vec!["user-c", "user-f", "user-b"]
+        );
+        assert_eq!(
+            chat_ids(
+                &list(
+                    &state,
+                    &[("view", "all"), ("updatedSince", "2023-01-05T00:00:00Z")]
+                )
+                .await
+            ),
+            vec!["user-f", "user-e"]
+        );
+    }
+
+    #[tokio::test]
+    async fn bad_parameters_are_rejected() {
+        let state = state().await;
+
+        for query in [
+            vec![("limit", "0")],
+            vec![("view", "starred")],
+            vec![("unread", "yes")],
+            vec![("updatedSince", "yesterday")],
+        ] {
+            let request = RouteRequest::for_user(&owner(), &[], &query, None);
+            let error = list_chats(state.clone(), request).await.unwrap_err();
+
+            assert_eq!(error.status(), 400, "{:?}", query);
+        }
+
+        let request = RouteRequest::for_user(&owner(), &[], &[("cursor", "nope")], None);
+        let error = list_chats(state, request).await.unwrap_err();
+
+        assert_eq!(error.code(), "INVALID_CURSOR");
+    }
+}
@@ -0,0 +1,347 @@
+/*---------- Imports ----------*/
+use super::ApiState;
+use crate::api::dto::{InviteRequest, InviteSent};
+use crate::api::error::ApiError;
+use crate::api::response::json_response;
+use crate::api::router::{RouteRequest, RouteResult};
+use crate::mail::{MailError, OutgoingMail};
+use crate::models::{
+    chat::{Message, MessageType},
+    invite::{Invitation, InviteQuota, QueuedMessage},
+    user::User,
+};
+use crate::utils::{
+    avatar::Avatar,
+    email::{Email, EmailKind, EmailVariables, Locale},
+    signup::Signup,
+    user::{GetUserError, User as UserService},
+};
+use chrono::{SecondsFormat, Utc};
+use std::sync::Arc;
+use tracing::{error, warn};
+use ulid::Ulid;
+
+/*---------- Constants ----------*/
+/// Messages one inviter can leave before the invitee signs up.
+const MAX_QUEUED_MESSAGES: usize = 20;
+/// Invite requests one user can make per UTC day, whoever they write to.
+const MAX_DAILY_INVITES: u32 = 50;
+/// How long a day's count is kept, past the end of the day.
+const INVITE_QUOTA_TTL_SECS: i64 = 2 * 24 * 60 * 60;
+
+async fn send_invite_email(
+    state: &ApiState,
+    inviter: &User,
+    address: &str,
+    locale: Locale,
+) -> Result<(), MailError> {
+    let variables = EmailVariables {
+        app_name: state.app_name.to_owned(),
+        name: inviter.name.to_owned(),
+        avatar_url: Avatar::url(&state.public_bucket_domain, &inviter.sub),
+        code: String::new(),
+        username: address.to_owned(),
+        link: Some(state.app_url.to_owned()),
+    };
+
+    let rendered_email = Email::render(EmailKind::ChatInvite, locale, &variables);
+
+    state
+        .mail_sender
+        .send(&OutgoingMail {
+            to: address.to_owned(),
+            subject: rendered_email.subject,
+            html: rendered_email.html,
+            text: rendered_email.text,
+        })
+        .await
+}
+
+/// The account a normalized email belongs to. The claim covers every alias
+/// of the mailbox, Cognito is asked as well for accounts that don't have
+/// one.
+async fn find_registered_sub(state: &ApiState, email: &str) -> Result<Option<String>, ApiError> {
+    if let Some(owner) = state.repository.get_email_owner(email).await? {
+        return Ok(Some(owner.sub));
+    }
+
+    match UserService::get_user_by_email(&state.cognito_client, &state.userpool_id, email).await {
+        Ok(user) => Ok(Some(user.sub)),
+        Err(GetUserError::NotFound) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// `POST /invites`
+pub async fn invite_by_email(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let inviter = &request.claims()?.user;
+    let body: InviteRequest = request.json()?;
+    let address = body.email.trim();
+    let content = body.message.trim();
+
+    if content.is_empty() {
+        return Err(ApiError::Validation("message can't be empty".to_owned()));
+    }
+
+    let email = Signup::normalize_email(address)
+        .ok_or_else(|| ApiError::Validation("email must be a valid email address".to_owned()))?;
+
+    if Signup::normalize_email(&inviter.email).as_deref() == Some(email.as_str()) {
+        return Err(ApiError::SelfMessage);
+    }
+
+    let now = Utc::now();
+    let quota = InviteQuota::new(
+        &inviter.sub,
+        &now.format("%Y-%m-%d").to_string(),
+        now.timestamp() + INVITE_QUOTA_TTL_SECS,
+    );
+
+    if !state
+        .repository
+        .record_invite(&quota, MAX_DAILY_INVITES)
+        .await?
+    {
+        return Err(ApiError::RateLimited);
+    }
+
+    let timestamp = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let message_id = Ulid::new().to_string();
+
+    // People who already have an account get the message in a regular chat.
+    // Everything past this point answers the same whichever way it goes, so
+    // this can't be used to find out who is registered
+    if let Some(receiver_sub) = find_registered_sub(&state, &email).await? {
+        let message = Message::private(
+            inviter,
+            &receiver_sub,
+            &message_id,
+            &timestamp,
+            content,
+            MessageType::Text,
+        );
+
+        state.repository.save_message(&message).await?;
+
+        return Ok(json_response(201, &InviteSent { email }));
+    }
+
+    // The inviter may have changed their name since the first message, the
+    // latest one is stored with every message
+    let invitation = Invitation::new(&email, inviter, &timestamp);
+    let queued_message = QueuedMessage {
+        message_id,
+        content: content.to_owned(),
+        timestamp,
+    };
+
+    // A full queue drops the message, the invitee has plenty waiting already
+    let invitation = match state
+        .repository
+        .queue_invitation_message(&invitation, &queued_message, MAX_QUEUED_MESSAGES)
+        .await?
+    {
+        Some(invitation) => invitation,
+        None => {
+            warn!(
+                "Dropped a message to {}, {} are already queued",
+                email, MAX_QUEUED_MESSAGES
+            );
+
+            return Ok(json_response(201, &InviteSent { email }));
+        }
+    };
+
+    // Only the first message sends an email, and only once it's stored. If
+    // the email can't go out the invitation is dropped again, so the next
+    // attempt sends it. The inviter isn't told, registered addresses never
+    // fail this way
+    if invitation.messages.len() == 1 {
+        // Nobody knows the invitee's language yet, the inviter's is the best
+        // guess
+        let locale = request
+            .request
+            .headers()
+            .get("accept-language")
+            .and_then(|header| header.to_str().ok())
+            .map(Locale::from_tag)
+            .unwrap_or_default();
+
+        if let Err(error) = send_invite_email(&state, inviter, address, locale).await {
+            error!("Couldn't invite {}: {}", email, error);
+
+            state
+                .repository
+                .delete_invitation(&email, &inviter.sub)
+                .await?;
+        }
+    }
+
+    Ok(json_response(201, &InviteSent { email }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mail::memory::InMemoryMailSender;
+    use crate::models::signup::EmailOwner;
+    use crate::repository::{memory::InMemoryChatRepository, ChatRepository};
+    use serde_json::{json, Value};
+
+    const REGISTERED_EMAIL: &str = "john@example.com";
+
+    struct Fixture {
+        state: Arc<ApiState>,
+        repository: Arc<InMemoryChatRepository>,
+        mail_sender: Arc<InMemoryMailSender>,
+    }
+
+    fn inviter() -> User {
+        User::new("user-1", "Jane", "jane@example.com")
+    }
+
+    /// The registered address is found through its email claim. Cognito is
+    /// never reachable from the test state, so any other one counts as
+    /// unregistered.
+    async fn fixture() -> Fixture {
+        let repository = Arc::new(InMemoryChatRepository::new());
+        let mail_sender = Arc::new(InMemoryMailSender::new());
+
+        repository
+            .claim_email(&EmailOwner::new(REGISTERED_EMAIL, "user-2"))
+            .await
+            .unwrap();
+
+        Fixture {
+            state: Arc::new(ApiState {
+                mail_sender: mail_sender.clone(),
+                ..ApiState::in_memory(repository.clone())
+            }),
+            repository,
+            mail_sender,
+        }
+    }
+
+    async fn invite(fixture: &Fixture, email: &str, message: &str) -> RouteResult {
+        let request = RouteRequest::for_user(
+            &inviter(),
+            &[],
+            &[],
+            Some(json!({ "email": email, "message": message })),
+        );
+
+        invite_by_email(fixture.state.clone(), request).await
+    }
+
+    /// Status and body, which is all the inviter gets to see.
+    async fn answer(fixture: &Fixture, email: &str) -> (u16, Value) {
+        let response = invite(fixture, email, "Hi").await.unwrap();
+        let body = serde_json::from_slice(response.body().as_ref()).unwrap();
+
+        (response.status().as_u16(), body)
+    }
+
+    #[tokio::test]
+    async fn unregistered_addresses_get_one_email_and_queued_messages() {
+        let fixture = fixture().await;
+
+        for _ in 0..3 {
+            invite(&fixture, "Friend@Example.com", "Hi").await.unwrap();
+        }
+
+        let invitations = fixture
+            .repository
+            .list_invitations("friend@example.com")
+            .await
+            .unwrap();
+
+        assert_eq!(fixture.mail_sender.sent().len(), 1);
+        assert!(fixture
+            .mail_sender
+            .last_sent_to("friend@example.com")
+            .unwrap()
+            .text
+            .contains("https://chat.example.com"));
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].messages.len(), 3);
+        assert_eq!(
+            fixture
+                .repository
+                .list_sent_invitations("user-1")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn registered_addresses_get_a_chat_message() {
+        let fixture = fixture().await;
+
+        invite(&fixture, REGISTERED_EMAIL, "Hi").await.unwrap();
+
+        let messages = fixture
+            .repository
+            .list_private_messages("user-1", "user-2")
+            .await
+            .unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert!(fixture.mail_sender.sent().is_empty());
+        assert!(fixture
+            .repository
+            .list_invitations(REGISTERED_EMAIL)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn every_outcome_gets_the_same_answer() {
+        let fixture = fixture().await;
+
+        fixture.mail_sender.reject("bounced@example.com");
+
+        for _ in 0..MAX_QUEUED_MESSAGES {
+            invite(&fixture, "full@example.com", "Hi").await.unwrap();
+        }
+
+        for email in [
+            REGISTERED_EMAIL,
+            "friend@example.com",
+            "full@example.com",
+            "bounced@example.com",
+        ] {
+            assert_eq!(
+                answer(&fixture, email).await,
+                (201, json!({ "email": email })),
+                "inviting {}",
+                email
+            );
+        }
+
+        // The rejected invitation is dropped, nothing waits on it
+        assert!(fixture
+            .repository
+            .list_invitations("bounced@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_are_refused() {
+        let fixture = fixture().await;
+
+        for (email, message) in [
+            ("not-an-email", "Hi"),
+            ("friend@example.com", "   "),
+            ("Jane@Example.com", "Hi"),
+        ] {
+            assert!(invite(&fixture, email, message).await.is_err());
+        }
+
+        assert!(fixture.mail_sender.sent().is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+/*---------- Imports ----------*/
+use super::ApiState;
+use crate::api::dto::{
+    AccountDeletionResult, AvatarReset, AvatarUploadTicket, ExportAccepted, ProfileUpdateRequest,
+};
+use crate::api::error::ApiError;
+use crate::api::response::json_response;
+use crate::api::router::{RouteRequest, RouteResult};
+use crate::models::{
+    export::ExportJob,
+    user::{AvatarSource, ProfileUpdate, User},
+};
+use crate::utils::{
+    account::{Account, AccountDeletion},
+    avatar::Avatar,
+    user::User as UserService,
+};
+use aws_sdk_s3::presigning::config::PresigningConfig;
+use chrono::{SecondsFormat, Utc};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tracing::error;
+use ulid::Ulid;
+
+/*---------- Constants ----------*/
+const MAX_NAME_LENGTH: usize = 100;
+const UPLOAD_LINK_EXPIRATION_SECS: u64 = 60 * 5;
+
+/// `PATCH /me`
+pub async fn update_profile(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let claims = request.claims()?;
+    let body: ProfileUpdateRequest = request.json()?;
+    let new_name = body.name.trim().to_owned();
+
+    if new_name.is_empty() || new_name.chars().count() > MAX_NAME_LENGTH {
+        let message = format!(
+            "name must have between 1 and {} characters",
+            MAX_NAME_LENGTH
+        );
+
+        return Err(ApiError::Validation(message));
+    }
+
+    UserService::update_name(
+        &state.cognito_client,
+        &state.userpool_id,
+        &claims.username,
+        &new_name,
+    )
+    .await
+    .map_err(|_| ApiError::Internal("An error ocurred while updating the profile".to_owned()))?;
+
+    let updated_user = User {
+        name: new_name,
+        ..claims.user.clone()
+    };
+
+    // The name is already saved at this point, a stale avatar shouldn't fail
+    // the whole request. Uploaded avatars don't depend on the name, so only
+    // generated ones are redrawn.
+    let avatar_source = match state.repository.get_profile(&updated_user.sub).await {
+        Ok(profile) => profile.map(|profile| profile.avatar_source),
+        Err(error) => {
+            error!("Couldn't read profile: {}", error);
+            None
+        }
+    };
+
+    if avatar_source == Some(AvatarSource::Generated) {
+        if let Err(error) = Avatar::upload_initials(
+            &state.s3_client,
+            &state.public_bucket_name,
+            &state.avatar_config,
+            &updated_user.sub,
+            &updated_user.name,
+        )
+        .await
+        {
+            error!("Couldn't regenerate avatar: {}", error);
+        }
+    }
+
+    // Chat summaries keep their own copy of the name, the stream handler
+    // picks this item up and rewrites them
+    let requested_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let profile_update = ProfileUpdate::new(&updated_user, &Ulid::new().to_string(), &requested_at);
+
+    if let Err(error) = state.repository.save_profile_update(&profile_update).await {
+        error!("Couldn't schedule profile propagation: {}", error);
+    }
+
+    Ok(json_response(200, &updated_user))
+}
+
+/// `DELETE /me`
+pub async fn delete_account(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let claims = request.claims()?;
+
+    let deletion_options = AccountDeletion {
+        repository: state.repository.as_ref(),
+        object_store: state.object_store.as_ref(),
+        cognito_client: &state.cognito_client,
+        public_bucket_name: &state.public_bucket_name,
+        export_bucket_name: &state.export_bucket_name,
+        userpool_id: &state.userpool_id,
+        avatar_config: &state.avatar_config,
+    };
+
+    let deletion_result =
+        Account::delete(&deletion_options, &claims.user.sub, Some(&claims.username)).await;
+
+    match deletion_result {
+        Ok(progress) => Ok(json_response(
+            200,
+            &AccountDeletionResult {
+                status: progress.status,
+                completed_steps: progress.completed_steps,
+            },
+        )),
+
+        // Deletion is resumable, so the client gets told how far it got
+        Err((progress, error)) => {
+            let api_error = ApiError::Internal(error.to_string());
+            let mut body = api_error.body();
+
+            body["status"] = json!(progress.status);
+            body["completedSteps"] = json!(progress.completed_steps);
+
+            Ok(json_response(api_error.status(), &body))
+        }
+    }
+}
+
+/// `POST /me/export`
+pub async fn request_data_export(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user = &request.claims()?.user;
+
+    let job = ExportJob::new(
+        &user.sub,
+        &Ulid::new().to_string(),
+        &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+    );
+
+    // The export itself runs in the data export processor once this item lands
+    state.repository.save_export_job(&job).await?;
+
+    Ok(json_response(
+        202,
+        &ExportAccepted {
+            job_id: job.job_id,
+            status: job.status,
+            requested_at: job.requested_at,
+        },
+    ))
+}
+
+async fn presign_upload(state: &ApiState, upload_key: &str) -> Result<String, ()> {
+    let presigning_config =
+        PresigningConfig::expires_in(Duration::from_secs(UPLOAD_LINK_EXPIRATION_SECS))
+            .map_err(|_| ())?;
+
+    let presigned_request = state
+        .s3_client
+        .put_object()
+        .bucket(&state.upload_bucket_name)
+        .key(upload_key)
+        .presigned(presigning_config)
+        .await
+        .map_err(|_| ())?;
+
+    Ok(presigned_request.uri().to_string())
+}
+
+/// `POST /me/avatar`
+pub async fn request_avatar_upload(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user = &request.claims()?.user;
+
+    // The processor reads the owner back from the key, so the client never
+    // gets to choose whose avatar it replaces
+    let upload_key = format!("avatar/{}/{}", user.sub, Ulid::new());
+
+    let upload_url = presign_upload(&state, &upload_key).await.map_err(|_| {
+        ApiError::Internal("An error ocurred while preparing the upload".to_owned())
+    })?;
+
+    Ok(json_response(
+        200,
+        &AvatarUploadTicket {
+            upload_url,
+            method: "PUT".to_owned(),
+            expires_in: UPLOAD_LINK_EXPIRATION_SECS,
+        },
+    ))
+}
+
+/// `DELETE /me/avatar`
+pub async fn reset_avatar(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user = &request.claims()?.user;
+
+    // The token keeps the name it was issued with, the profile has the
+    // current one
+    let name = match state.repository.get_profile(&user.sub).await? {
+        Some(profile) => profile.name,
+        None => user.name.to_owned(),
+    };
+
+    Avatar::upload_initials(
+        &state.s3_client,
+        &state.public_bucket_name,
+        &state.avatar_config,
+        &user.sub,
+        &name,
+    )
+    .await
+    .map_err(|_| ApiError::Internal("An error ocurred while resetting the avatar".to_owned()))?;
+
+    state
+        .repository
+        .set_avatar_source(&user.sub, AvatarSource::Generated)
+        .await?;
+
+    Ok(json_response(
+        200,
+        &AvatarReset {
+            avatar_key: Avatar::key(&user.sub),
+        },
+    ))
+}
@@ -0,0 +1,343 @@
+/*---------- Imports ----------*/
+use super::dto::{
+    AccountDeletionResult, AvatarReset, AvatarUploadTicket, ChatClearRequest, ChatCleared,
+    ChatSettingsUpdate, DataPage, ExportAccepted, InviteRequest, InviteSent, ProfileUpdateRequest,
+    UserSearchResult,
+};
+use super::middleware::CorsConfig;
+use super::router::{Access, RouteFuture, RouteRequest, Router};
+use super::spec::{schema_for, QueryParam};
+use crate::mail::{ses::SesMailSender, MailSender};
+use crate::models::{
+    chat::{Chat, ChatSettings, ChatSummary},
+    user::User,
+};
+use crate::repository::{dynamodb::DynamoChatRepository, ChatRepository};
+use crate::storage::{s3::S3ObjectStore, ObjectStore};
+use crate::utils::{avatar::AvatarConfig, aws::AwsClients};
+use aws_config::SdkConfig;
+use lambda_http::http::Method;
+use schemars::{gen::SchemaGenerator, schema::Schema};
+use std::{env, sync::Arc};
+
+pub mod chats;
+pub mod invites;
+pub mod me;
+pub mod users;
+
+/*---------- Types ----------*/
+type SchemaBuilder = fn(&mut SchemaGenerator) -> Schema;
+type RouteHandler = fn(Arc<ApiState>, RouteRequest) -> RouteFuture;
+
+/*---------- Structs ----------*/
+/// A REST endpoint along with what the OpenAPI spec says about it. The
+/// router and the spec are both built from [`ROUTES`], so neither can list
+/// an endpoint the other doesn't.
+pub struct RouteDefinition {
+    pub method: Method,
+    pub path: &'static str,
+    pub operation_id: &'static str,
+    pub summary: &'static str,
+    pub query: &'static [QueryParam],
+    pub request: Option<SchemaBuilder>,
+    pub status: u16,
+    pub response: SchemaBuilder,
+    handler: RouteHandler,
+}
+
+/// Clients and resource names shared by every REST route.
+pub struct ApiState {
+    pub repository: Arc<dyn ChatRepository>,
+    pub cognito_client: aws_sdk_cognitoidentityprovider::Client,
+    pub s3_client: aws_sdk_s3::Client,
+    pub object_store: Arc<dyn ObjectStore>,
+    pub userpool_id: String,
+    pub public_bucket_name: String,
+    pub upload_bucket_name: String,
+    pub export_bucket_name: String,
+    pub avatar_config: AvatarConfig,
+    pub mail_sender: Arc<dyn MailSender>,
+    pub public_bucket_domain: String,
+    pub app_name: String,
+    /// Where invitation emails send people to sign up.
+    pub app_url: String,
+}
+
+impl ApiState {
+    pub fn from_env(config: &SdkConfig) -> Self {
+        let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+        let s3_client = aws_sdk_s3::Client::new(config);
+
+        Self {
+            repository: Arc::new(DynamoChatRepository::new(
+                AwsClients::dynamodb(config),
+                &table_name,
+            )),
+            cognito_client: aws_sdk_cognitoidentityprovider::Client::new(config),
+            object_store: Arc::new(S3ObjectStore::new(s3_client.clone())),
+            s3_client,
+            userpool_id: env::var("USERPOOL_ID").expect("USERPOOL_ID must be set"),
+            public_bucket_name: env::var("PUBLIC_BUCKET").expect("PUBLIC_BUCKET must be set"),
+            upload_bucket_name: env::var("UPLOAD_BUCKET").expect("UPLOAD_BUCKET must be set"),
+            export_bucket_name: env::var("EXPORT_BUCKET").expect("EXPORT_BUCKET must be set"),
+            avatar_config: AvatarConfig::from_env(),
+            mail_sender: Arc::new(SesMailSender::new(
+                aws_sdk_sesv2::Client::new(config),
+                &env::var("MAIL_FROM").expect("MAIL_FROM must be set"),
+            )),
+            public_bucket_domain: env::var("PUBLIC_BUCKET_DOMAIN")
+                .expect("PUBLIC_BUCKET_DOMAIN must be set"),
+            app_name: env::var("APP_NAME").unwrap_or("ChatApp".to_owned()),
+            app_url: env::var("APP_URL").expect("APP_URL must be set"),
+        }
+    }
+}
+
+#[cfg(test)]
+impl ApiState {
+    /// State backed by `repository`, an in-memory object store and an
+    /// in-memory mail sender. The AWS clients have no region, every call
+    /// through them fails.
+    pub fn in_memory(repository: Arc<crate::repository::memory::InMemoryChatRepository>) -> Self {
+        let sdk_config = SdkConfig::builder().build();
+
+        Self {
+            repository,
+            cognito_client: aws_sdk_cognitoidentityprovider::Client::new(&sdk_config),
+            s3_client: aws_sdk_s3::Client::new(&sdk_config),
+            object_store: Arc::new(crate::storage::memory::InMemoryObjectStore::new()),
+            userpool_id: "userpool".to_owned(),
+            public_bucket_name: "public".to_owned(),
+            upload_bucket_name: "uploads".to_owned(),
+            export_bucket_name: "exports".to_owned(),
+            avatar_config: AvatarConfig::default(),
+            mail_sender: Arc::new(crate::mail::memory::InMemoryMailSender::new()),
+            public_bucket_domain: "media.example.com".to_owned(),
+            app_name: "ChatApp".to_owned(),
+            app_url: "https://chat.example.com".to_owned(),
+        }
+    }
+}
+
+/*---------- Constants ----------*/
+const LIMIT_PARAM: QueryParam = QueryParam {
+    name: "limit",
+    description: "Page size",
+    required: false,
+};
+
+const CURSOR_PARAM: QueryParam = QueryParam {
+    name: "cursor",
+    description: "`nextCursor` of the previous page",
+    required: false,
+};
+
+const CHAT_LIST_PARAMS: [QueryParam; 6] = [
+    LIMIT_PARAM,
+    CURSOR_PARAM,
+    QueryParam {
+        name: "view",
+        description: "`inbox` (default, pinned chats first), `archived`, `pinned` or `all`",
+        required: false,
+    },
+    QueryParam {
+        name: "type",
+        description: "Only chats of this type, `private` or `group`",
+        required: false,
+    },
+    QueryParam {
+        name: "unread",
+        description: "Only chats with unread messages when `true`",
+        required: false,
+    },
+    QueryParam {
+        name: "updatedSince",
+        description: "RFC 3339 timestamp, only chats with activity since then",
+        required: false,
+    },
+];
+
+const USER_SEARCH_PARAMS: [QueryParam; 4] = [
+    QueryParam {
+        name: "q",
+        description: "Name or email prefix, at least 2 characters",
+        required: true,
+    },
+    LIMIT_PARAM,
+    CURSOR_PARAM,
+    QueryParam {
+        name: "includeBlocked",
+        description: "Also list users who blocked the caller when `true`",
+        required: false,
+    },
+];
+
+const USER_LOOKUP_PARAMS: [QueryParam; 2] = [
+    QueryParam {
+        name: "email",
+        description: "Email of the user, takes precedence over `sub`",
+        required: false,
+    },
+    QueryParam {
+        name: "sub",
+        description: "Sub of the user",
+        required: false,
+    },
+];
+
+macro_rules! handler {
+    ($handler:path) => {
+        |state, request| -> RouteFuture { Box::pin($handler(state, request)) }
+    };
+}
+
+/// Every REST endpoint of the app. New endpoints only need an entry here and
+/// an event on the API function in the SAM template.
+pub const ROUTES: &[RouteDefinition] = &[
+    RouteDefinition {
+        method: Method::GET,
+        path: "/user",
+        operation_id: "getUserInfo",
+        summary: "Looks up a user by email or sub",
+        query: &USER_LOOKUP_PARAMS,
+        request: None,
+        status: 200,
+        response: schema_for::<User>,
+        handler: handler!(users::get_user_info),
+    },
+    RouteDefinition {
+        method: Method::GET,
+        path: "/users/search",
+        operation_id: "searchUsers",
+        summary: "Searches users by name or email prefix",
+        query: &USER_SEARCH_PARAMS,
+        request: None,
+        status: 200,
+        response: schema_for::<DataPage<UserSearchResult>>,
+        handler: handler!(users::search_users),
+    },
+    RouteDefinition {
+        method: Method::GET,
+        path: "/chats",
+        operation_id: "listChats",
+        summary: "Lists the caller's chats, most recent first",
+        query: &CHAT_LIST_PARAMS,
+        request: None,
+        status: 200,
+        response: schema_for::<DataPage<ChatSummary>>,
+        handler: handler!(chats::list_chats),
+    },
+    RouteDefinition {
+        method: Method::GET,
+        path: "/chats/{id}",
+        operation_id: "getChatDetails",
+        summary: "Gets a chat the caller takes part in",
+        query: &[],
+        request: None,
+        status: 200,
+        response: schema_for::<Chat>,
+        handler: handler!(chats::get_chat_details),
+    },
+    RouteDefinition {
+        method: Method::PATCH,
+        path: "/chats/{id}/settings",
+        operation_id: "updateChatSettings",
+        summary: "Mutes, archives or pins a chat for the caller only",
+        query: &[],
+        request: Some(schema_for::<ChatSettingsUpdate>),
+        status: 200,
+        response: schema_for::<ChatSettings>,
+        handler: handler!(chats::update_chat_settings),
+    },
+    RouteDefinition {
+        method: Method::POST,
+        path: "/chats/{id}/clear",
+        operation_id: "clearChat",
+        summary: "Clears the chat history for the caller only, optionally hiding the chat",
+        query: &[],
+        request: Some(schema_for::<ChatClearRequest>),
+        status: 200,
+        response: schema_for::<ChatCleared>,
+        handler: handler!(chats::clear_chat),
+    },
+    RouteDefinition {
+        method: Method::POST,
+        path: "/invites",
+        operation_id: "inviteByEmail",
+        summary: "Writes to an email address, inviting people who don't have an account yet",
+        query: &[],
+        request: Some(schema_for::<InviteRequest>),
+        status: 201,
+        response: schema_for::<InviteSent>,
+        handler: handler!(invites::invite_by_email),
+    },
+    RouteDefinition {
+        method: Method::PATCH,
+        path: "/me",
+        operation_id: "updateProfile",
+        summary: "Changes the caller's display name",
+        query: &[],
+        request: Some(schema_for::<ProfileUpdateRequest>),
+        status: 200,
+        response: schema_for::<User>,
+        handler: handler!(me::update_profile),
+    },
+    RouteDefinition {
+        method: Method::DELETE,
+        path: "/me",
+        operation_id: "deleteAccount",
+        summary: "Deletes the caller's account and personal data",
+        query: &[],
+        request: None,
+        status: 200,
+        response: schema_for::<AccountDeletionResult>,
+        handler: handler!(me::delete_account),
+    },
+    RouteDefinition {
+        method: Method::POST,
+        path: "/me/export",
+        operation_id: "requestDataExport",
+        summary: "Starts an export of the caller's data",
+        query: &[],
+        request: None,
+        status: 202,
+        response: schema_for::<ExportAccepted>,
+        handler: handler!(me::request_data_export),
+    },
+    RouteDefinition {
+        method: Method::POST,
+        path: "/me/avatar",
+        operation_id: "requestAvatarUpload",
+        summary: "Returns a presigned URL to upload a custom avatar",
+        query: &[],
+        request: None,
+        status: 200,
+        response: schema_for::<AvatarUploadTicket>,
+        handler: handler!(me::request_avatar_upload),
+    },
+    RouteDefinition {
+        method: Method::DELETE,
+        path: "/me/avatar",
+        operation_id: "resetAvatar",
+        summary: "Goes back to the generated initials avatar",
+        query: &[],
+        request: None,
+        status: 200,
+        response: schema_for::<AvatarReset>,
+        handler: handler!(me::reset_avatar),
+    },
+];
+
+pub fn router(state: ApiState) -> Router<ApiState> {
+    ROUTES.iter().fold(
+        Router::new(state).with_cors(CorsConfig::from_env()),
+        |router, route| {
+            router.route(
+                route.method.clone(),
+                route.path,
+                Access::Authenticated,
+                route.handler,
+            )
+        },
+    )
+}
@@ -0,0 +1,203 @@
+/*---------- Imports ----------*/
+use super::ApiState;
+use crate::api::dto::{DataPage, UserSearchResult};
+use crate::api::error::ApiError;
+use crate::api::response::json_response;
+use crate::api::router::{RouteRequest, RouteResult};
+use crate::models::user::User;
+use crate::utils::{
+    avatar::Avatar,
+    user::{GetUserError, User as UserService},
+};
+use std::sync::Arc;
+
+/*---------- Constants ----------*/
+const MIN_QUERY_LENGTH: usize = 2;
+const DEFAULT_PAGE_SIZE: i32 = 20;
+// ListUsers refuses limits above 60
+const MAX_PAGE_SIZE: i32 = 60;
+
+/// `GET /user?email=` or `GET /user?sub=`
+pub async fn get_user_info(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user_info_req = match (request.query_param("email"), request.query_param("sub")) {
+        (Some(email_value), _) => {
+            UserService::get_user_by_email(&state.cognito_client, &state.userpool_id, &email_value)
+                .await
+        }
+        (None, Some(sub_value)) => {
+            UserService::get_user_by_sub(&state.cognito_client, &state.userpool_id, &sub_value)
+                .await
+        }
+        (None, None) => {
+            return Err(ApiError::Validation(
+                "You must specify either an email or sub value in the query parameters".to_owned(),
+            ))
+        }
+    };
+
+    match user_info_req {
+        Ok(user_info) => Ok(json_response(200, &user_info)),
+        Err(error) => Err(ApiError::from(error)),
+    }
+}
+
+/// The users found by a search as the caller gets them: without the caller
+/// themselves and, unless `include_blocked` is set, without the users who
+/// blocked them.
+async fn visible_results(
+    state: &ApiState,
+    caller_sub: &str,
+    users: Vec<User>,
+    include_blocked: bool,
+) -> Result<Vec<UserSearchResult>, ApiError> {
+    let blocked_by = if include_blocked {
+        vec![]
+    } else {
+        state.repository.list_blocked_by(caller_sub).await?
+    };
+
+    Ok(users
+        .into_iter()
+        .filter(|found_user| found_user.sub != caller_sub && !blocked_by.contains(&found_user.sub))
+        .map(|found_user| UserSearchResult {
+            avatar_url: Avatar::url(&state.public_bucket_domain, &found_user.sub),
+            sub: found_user.sub,
+            name: found_user.name,
+        })
+        .collect())
+}
+
+/// `GET /users/search?q=&limit=&cursor=&includeBlocked=`
+pub async fn search_users(state: Arc<ApiState>, request: RouteRequest) -> RouteResult {
+    let user = &request.claims()?.user;
+
+    let search_query = match request.query_param("q") {
+        Some(query) if query.trim().chars().count() >= MIN_QUERY_LENGTH => query.trim().to_owned(),
+        _ => {
+            let message = format!("q must have at least {} characters", MIN_QUERY_LENGTH);
+
+            return Err(ApiError::Validation(message));
+        }
+    };
+
+    let limit = match request
+        .query_param("limit")
+        .map(|limit| limit.parse::<i32>())
+    {
+        Some(Ok(parsed)) if (1..=MAX_PAGE_SIZE).contains(&parsed) => parsed,
+        None => DEFAULT_PAGE_SIZE,
+        _ => {
+            let message = format!("limit must be a number between 1 and {}", MAX_PAGE_SIZE);
+
+            return Err(ApiError::Validation(message));
+        }
+    };
+
+    let include_blocked = match request.query_param("includeBlocked").as_deref() {
+        Some("true") => true,
+        Some("false") | None => false,
+        Some(_) => {
+            return Err(ApiError::Validation(
+                "includeBlocked must be either true or false".to_owned(),
+            ))
+        }
+    };
+
+    let cursor = request.query_param("cursor");
+
+    let page = match UserService::search_users(
+        &state.cognito_client,
+        &state.userpool_id,
+        &search_query,
+        limit,
+        cursor.as_deref(),
+    )
+    .await
+    {
+        Ok(page) => page,
+        Err(GetUserError::InvalidCursor) => return Err(ApiError::InvalidCursor),
+        Err(_) => {
+            return Err(ApiError::Internal(
+                "An error ocurred while searching users".to_owned(),
+            ))
+        }
+    };
+
+    let results = visible_results(&state, &user.sub, page.users, include_blocked).await?;
+
+    Ok(json_response(
+        200,
+        &DataPage {
+            data: results,
+            next_cursor: page.next_cursor,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::block::Block;
+    use crate::repository::{memory::InMemoryChatRepository, ChatRepository};
+
+    const CALLER_SUB: &str = "user-1";
+
+    async fn state() -> ApiState {
+        let repository = Arc::new(InMemoryChatRepository::new());
+
+        repository
+            .save_block(&Block::new(
+                "user-2",
+                CALLER_SUB,
+                "2023-01-01T00:00:00.000Z",
+            ))
+            .await
+            .unwrap();
+        repository
+            .save_block(&Block::new(
+                CALLER_SUB,
+                "user-3",
+                "2023-01-01T00:00:00.000Z",
+            ))
+            .await
+            .unwrap();
+
+        ApiState::in_memory(repository)
+    }
+
+    fn found_users() -> Vec<User> {
+        ["user-1", "user-2", "user-3", "user-4"]
+            .iter()
+            .map(|sub| User::new(sub, sub, &format!("{}@example.com", sub)))
+            .collect()
+    }
+
+    fn subs(results: &[UserSearchResult]) -> Vec<&str> {
+        results.iter().map(|result| result.sub.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn users_who_blocked_the_caller_are_left_out() {
+        let state = state().await;
+        let results = visible_results(&state, CALLER_SUB, found_users(), false)
+            .await
+            .unwrap();
+
+        // Blocking someone doesn't hide them from the blocker
+        assert_eq!(subs(&results), vec!["user-3", "user-4"]);
+        assert_eq!(
+            results[0].avatar_url,
+            "https://media.example.com/user/user-3.png"
+        );
+    }
+
+    #[tokio::test]
+    async fn blocked_users_can_be_included() {
+        let state = state().await;
+        let results = visible_results(&state, CALLER_SUB, found_users(), true)
+            .await
+            .unwrap();
+
+        assert_eq!(subs(&results), vec!["user-2", "user-3", "user-4"]);
+    }
+}
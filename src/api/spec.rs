@@ -0,0 +1,270 @@
+//! OpenAPI and AsyncAPI documents built from the schemas derived on the
+//! models, so the published contract can't drift from the code.
+
+/*---------- Imports ----------*/
+use super::dto::{DataExportStatus, ErrorBody, MessageStatusUpdate, ReceivedMessage};
+use super::routes::ROUTES;
+use crate::models::{chat::MessagePayload, common::WebSocketEvent};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+/*---------- Constants ----------*/
+pub const OPENAPI_PATH: &str = "docs/openapi.json";
+pub const ASYNCAPI_PATH: &str = "docs/asyncapi.json";
+
+/*---------- Structs ----------*/
+/// A documented query string parameter of a REST route.
+#[derive(Clone, Copy)]
+pub struct QueryParam {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub required: bool,
+}
+
+struct Channel {
+    action: &'static str,
+    summary: &'static str,
+    /// Sent by the client, as opposed to pushed by the server.
+    from_client: bool,
+    payload: Schema,
+}
+
+pub fn schema_for<T: JsonSchema>(generator: &mut SchemaGenerator) -> Schema {
+    generator.subschema_for::<T>()
+}
+
+fn json_content(schema: &Schema) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+fn path_params(path: &str) -> Vec<Value> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| {
+            json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            })
+        })
+        .collect()
+}
+
+fn websocket_channels(generator: &mut SchemaGenerator) -> Vec<Channel> {
+    vec![
+        Channel {
+            action: "send-message",
+            summary: "Sends a message to another user",
+            from_client: true,
+            payload: schema_for::<WebSocketEvent<MessagePayload>>(generator),
+        },
+        Channel {
+            action: "receive-message",
+            summary: "A message sent to the connected user",
+            from_client: false,
+            payload: schema_for::<WebSocketEvent<ReceivedMessage>>(generator),
+        },
+        Channel {
+            action: "message-status",
+            summary: "Outcome of a `send-message`, matched through `tempId`",
+            from_client: false,
+            payload: schema_for::<WebSocketEvent<MessageStatusUpdate>>(generator),
+        },
+        Channel {
+            action: "data-export-status",
+            summary: "A requested data export finished",
+            from_client: false,
+            payload: schema_for::<WebSocketEvent<DataExportStatus>>(generator),
+        },
+    ]
+}
+
+fn definitions(mut generator: SchemaGenerator) -> Value {
+    json!(generator.take_definitions())
+}
+
+pub fn openapi() -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let error_schema = schema_for::<ErrorBody>(&mut generator);
+    let mut paths = Map::new();
+
+    for route in ROUTES {
+        let mut parameters = path_params(route.path);
+
+        for param in route.query {
+            parameters.push(json!({
+                "name": param.name,
+                "in": "query",
+                "required": param.required,
+                "description": param.description,
+                "schema": { "type": "string" },
+            }));
+        }
+
+        let mut responses = Map::new();
+
+        responses.insert(
+            route.status.to_string(),
+            json!({
+                "description": "Success",
+                "content": json_content(&(route.response)(&mut generator)),
+            }),
+        );
+
+        responses.insert(
+            "default".to_owned(),
+            json!({
+                "description": "Error, see `code` for the reason",
+                "content": json_content(&error_schema),
+            }),
+        );
+
+        let mut operation_object = json!({
+            "operationId": route.operation_id,
+            "summary": route.summary,
+            "parameters": parameters,
+            "responses": responses,
+        });
+
+        if let Some(request_schema) = route.request {
+            operation_object["requestBody"] = json!({
+                "required": true,
+                "content": json_content(&request_schema(&mut generator)),
+            });
+        }
+
+        let path_item = paths
+            .entry(route.path.to_owned())
+            .or_insert_with(|| json!({}));
+
+        path_item[route.method.as_str().to_lowercase()] = operation_object;
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Chat REST API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "security": [{ "CognitoAuthorizer": [] }],
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "CognitoAuthorizer": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "Authorization",
+                    "description": "Cognito ID token",
+                },
+            },
+            "schemas": definitions(generator),
+        },
+    })
+}
+
+pub fn asyncapi() -> Value {
+    let mut generator = SchemaSettings::draft07()
+        .with(|settings| settings.definitions_path = "#/components/schemas/".to_owned())
+        .into_generator();
+
+    let mut channels = Map::new();
+    let mut messages = Map::new();
+
+    for channel in websocket_channels(&mut generator) {
+        // AsyncAPI operations are named from the application's side: the
+        // server subscribes to what clients publish
+        let operation = if channel.from_client {
+            "publish"
+        } else {
+            "subscribe"
+        };
+
+        let mut channel_item = Map::new();
+
+        channel_item.insert(
+            operation.to_owned(),
+            json!({
+                "summary": channel.summary,
+                "message": { "$ref": format!("#/components/messages/{}", channel.action) },
+            }),
+        );
+
+        channels.insert(channel.action.to_owned(), Value::Object(channel_item));
+
+        messages.insert(
+            channel.action.to_owned(),
+            json!({
+                "name": channel.action,
+                "contentType": "application/json",
+                "payload": channel.payload,
+            }),
+        );
+    }
+
+    json!({
+        "asyncapi": "2.6.0",
+        "info": {
+            "title": "Chat WebSocket API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Frames are routed on their `action` field",
+        },
+        "defaultContentType": "application/json",
+        "channels": channels,
+        "components": {
+            "messages": messages,
+            "schemas": definitions(generator),
+        },
+    })
+}
+
+/// Pretty-prints a spec the way it's checked in under `docs/`.
+pub fn render(document: &Value) -> String {
+    let mut rendered = serde_json::to_string_pretty(document).expect("Specs are valid JSON");
+    rendered.push('\n');
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    fn assert_up_to_date(path: &str, document: &Value) {
+        let committed = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(path))
+            .unwrap_or_default();
+
+        assert!(
+            committed == render(document),
+            "{} is outdated. Run `make specs` and commit the result.",
+            path
+        );
+    }
+
+    #[test]
+    fn committed_openapi_is_up_to_date() {
+        assert_up_to_date(OPENAPI_PATH, &openapi());
+    }
+
+    #[test]
+    fn committed_asyncapi_is_up_to_date() {
+        assert_up_to_date(ASYNCAPI_PATH, &asyncapi());
+    }
+
+    #[test]
+    fn every_route_is_documented_once() {
+        let document = openapi();
+        let paths = document["paths"].as_object().unwrap();
+        let documented: usize = paths
+            .values()
+            .map(|item| item.as_object().unwrap().len())
+            .sum();
+
+        assert_eq!(documented, ROUTES.len());
+    }
+}
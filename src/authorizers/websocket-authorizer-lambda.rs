@@ -1,6 +1,6 @@
 /*---------- Imports ----------*/
-use aws_sdk_dynamodb::model::AttributeValue;
-use chat_test_infra::models::user::User;
+use chat_test_infra::models::{connection::Connection, user::User};
+use chat_test_infra::repository::{dynamodb::DynamoChatRepository, ChatRepository};
 use jsonwebtokens_cognito::KeySet;
 use lambda_runtime::{service_fn, Error, LambdaEvent};
 use serde::{Deserialize, Serialize};
@@ -60,12 +60,10 @@ fn generate_policy(principal_id: String, resource: String) -> ApiGatewayV2Custom
         statement: vec![api_statement],
     };
 
-    let response = ApiGatewayV2CustomAuthorizerResponse {
+    ApiGatewayV2CustomAuthorizerResponse {
         policy_document,
         principal_id,
-    };
-
-    response
+    }
 }
 
 #[tokio::main]
@@ -76,16 +74,9 @@ async fn main() -> Result<(), Error> {
     let client_id = env::var("CLIENT_ID").expect("CLIENT_ID must be set");
     let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
     let dynamodb_client = aws_sdk_dynamodb::Client::new(&config);
-    let handler = service_fn(|event| {
-        handler_fn(
-            &dynamodb_client,
-            &region,
-            &userpool_id,
-            &client_id,
-            &table_name,
-            event,
-        )
-    });
+    let repository = DynamoChatRepository::new(dynamodb_client, &table_name);
+    let handler =
+        service_fn(|event| handler_fn(&repository, &region, &userpool_id, &client_id, event));
 
     lambda_runtime::run(handler).await?;
 
@@ -93,11 +84,10 @@ async fn main() -> Result<(), Error> {
 }
 
 async fn handler_fn(
-    dynamodb_client: &aws_sdk_dynamodb::Client,
+    repository: &impl ChatRepository,
     region: &str,
     userpool_id: &str,
     client_id: &str,
-    table_name: &str,
     event: LambdaEvent<ApiGatewayV2CustomAuthorizerRequest>,
 ) -> Result<ApiGatewayV2CustomAuthorizerResponse, Error> {
     let id_token_option = event.payload.query_string_parameters.get("idToken");
@@ -108,35 +98,21 @@ async fn handler_fn(
         if let Ok(keyset) = keyset_result {
             let verifier = keyset.new_id_token_verifier(&[client_id]).build()?;
 
-            let verify_result = keyset.verify(&id_token, &verifier).await;
+            let verify_result = keyset.verify(id_token, &verifier).await;
 
-            match (
+            if let (Ok(unparsed_user_info), Some(method_arn), Some(connection_id)) = (
                 verify_result,
                 event.payload.method_arn,
                 event.payload.request_context.connection_id,
             ) {
-                (Ok(unparsed_user_info), Some(method_arn), Some(connection_id)) => {
-                    let user_info: User = serde_json::from_value(unparsed_user_info)?;
-                    let partition_key = format!("user#{}", user_info.sub);
-
-                    dynamodb_client
-                        .put_item()
-                        .table_name(table_name)
-                        .item("partitionKey", AttributeValue::S(partition_key.to_owned()))
-                        .item("sortKey", AttributeValue::S("connection".to_owned()))
-                        .item("gsi1PK", AttributeValue::S("connection".to_owned()))
-                        .item("gsi1SK", AttributeValue::S(partition_key.to_owned()))
-                        .item("connectionId", AttributeValue::S(connection_id.to_owned()))
-                        .item("entityType", AttributeValue::S("connection".to_owned()))
-                        .send()
-                        .await?;
-
-                    let response = generate_policy(id_token.to_owned(), method_arn.to_owned());
-
-                    return Ok(response);
-                }
-
-                _ => {}
+                let user_info: User = serde_json::from_value(unparsed_user_info)?;
+                let connection = Connection::new(&user_info.sub, &connection_id);
+
+                repository.save_connection(&connection).await?;
+
+                let response = generate_policy(id_token.to_owned(), method_arn.to_owned());
+
+                return Ok(response);
             }
         }
     }
@@ -0,0 +1,18 @@
+/*---------- Imports ----------*/
+use chat_test_infra::api::routes::{self, ApiState};
+use lambda_http::{service_fn, Error, Request};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let router = routes::router(ApiState::from_env(&config));
+    let handler = service_fn(|request: Request| {
+        let router = &router;
+
+        async move { Ok::<_, Error>(router.handle(request).await) }
+    });
+
+    lambda_http::run(handler).await?;
+
+    Ok(())
+}
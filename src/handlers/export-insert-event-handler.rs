@@ -0,0 +1,286 @@
+/*---------- Imports ----------*/
+use crate::api::dto::DataExportStatus;
+use crate::models::{
+    chat::{ChatItem, ChatView, Message},
+    common::{parse_event_item, WebSocketEvent},
+    connection::Connection,
+    export::{ExportJob, ExportStatus},
+    stream::StreamRecord,
+};
+use crate::notifier::{apigateway::ApiGatewayNotifier, ConnectionNotifier};
+use crate::repository::{
+    dynamodb::DynamoChatRepository, ChatListQuery, ChatRepository, RepositoryError,
+};
+use crate::utils::{avatar::Avatar, aws::AwsClients, user::User};
+use aws_config::SdkConfig;
+use aws_sdk_s3::{presigning::config::PresigningConfig, types::ByteStream};
+use chrono::{Duration as ChronoDuration, SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{env, io::Cursor, io::Write, time::Duration};
+use tracing::error;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/*---------- Constants ----------*/
+const DOWNLOAD_LINK_EXPIRATION_SECS: u64 = 60 * 60 * 24;
+
+/*---------- Structs ----------*/
+struct ExportContext {
+    repository: DynamoChatRepository,
+    cognito_client: aws_sdk_cognitoidentityprovider::Client,
+    s3_client: aws_sdk_s3::Client,
+    userpool_id: String,
+    public_bucket_name: String,
+    export_bucket_name: String,
+}
+
+/// Every chat summary the user has, hidden and archived ones included.
+async fn collect_chats(
+    repository: &impl ChatRepository,
+    user_sub: &str,
+) -> Result<Vec<ChatItem>, RepositoryError> {
+    let mut chats: Vec<ChatItem> = vec![];
+    let mut query = ChatListQuery {
+        view: ChatView::All,
+        ..ChatListQuery::default()
+    };
+
+    loop {
+        let page = repository.list_chats(user_sub, &query).await?;
+
+        chats.extend(page.items);
+
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => return Ok(chats),
+        }
+    }
+}
+
+/// Every message of the user's chats. History the user cleared is still
+/// held for the other participant, so it's part of the export too.
+async fn collect_messages(
+    repository: &impl ChatRepository,
+    user_sub: &str,
+    chats: &[ChatItem],
+) -> Result<Vec<Message>, RepositoryError> {
+    let mut messages: Vec<Message> = vec![];
+
+    for other_sub in chats.iter().filter_map(|chat| chat.other_sub()) {
+        messages.extend(
+            repository
+                .list_private_messages(user_sub, other_sub)
+                .await?,
+        );
+    }
+
+    Ok(messages)
+}
+
+async fn fetch_avatar(
+    s3_client: &aws_sdk_s3::Client,
+    public_bucket_name: &str,
+    user_sub: &str,
+) -> Option<Vec<u8>> {
+    let get_object_output = s3_client
+        .get_object()
+        .bucket(public_bucket_name)
+        .key(Avatar::key(user_sub))
+        .send()
+        .await
+        .ok()?;
+
+    let avatar_bytes = get_object_output.body.collect().await.ok()?;
+
+    Some(avatar_bytes.into_bytes().to_vec())
+}
+
+fn build_archive(files: Vec<(&str, Vec<u8>)>) -> Result<Vec<u8>, ()> {
+    let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let file_options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (file_name, file_contents) in files {
+        zip_writer
+            .start_file(file_name, file_options)
+            .map_err(|_| ())?;
+        zip_writer.write_all(&file_contents).map_err(|_| ())?;
+    }
+
+    let archive_cursor = zip_writer.finish().map_err(|_| ())?;
+
+    Ok(archive_cursor.into_inner())
+}
+
+fn to_json_bytes<T: Serialize>(items: &T) -> Result<Vec<u8>, ()> {
+    serde_json::to_vec_pretty(items).map_err(|_| ())
+}
+
+async fn notify_user(
+    repository: &impl ChatRepository,
+    notifier: &impl ConnectionNotifier,
+    user_sub: &str,
+    message: Value,
+) {
+    let connection = match repository.get_connection(user_sub).await {
+        Ok(Some(connection)) => connection,
+        Ok(None) => return,
+        Err(error) => {
+            error!("Couldn't read the connection: {}", error);
+            return;
+        }
+    };
+
+    notifier
+        .send(&connection.connection_id, &message)
+        .await
+        .ok();
+}
+
+async fn export_user_data(context: &ExportContext, job: &ExportJob) -> Result<String, ()> {
+    let repository = &context.repository;
+    let s3_client = &context.s3_client;
+    let export_bucket_name = &context.export_bucket_name;
+
+    let profile =
+        User::get_user_by_sub(&context.cognito_client, &context.userpool_id, &job.user_sub)
+            .await
+            .map_err(|_| ())?;
+
+    let chats = collect_chats(repository, &job.user_sub)
+        .await
+        .map_err(|_| ())?;
+    let messages = collect_messages(repository, &job.user_sub, &chats)
+        .await
+        .map_err(|_| ())?;
+    let connections: Vec<Connection> = repository
+        .get_connection(&job.user_sub)
+        .await
+        .map_err(|_| ())?
+        .into_iter()
+        .collect();
+
+    let mut files: Vec<(&str, Vec<u8>)> = vec![
+        (
+            "profile.json",
+            serde_json::to_vec_pretty(&profile).map_err(|_| ())?,
+        ),
+        ("chats.json", to_json_bytes(&chats)?),
+        ("messages.json", to_json_bytes(&messages)?),
+        ("connections.json", to_json_bytes(&connections)?),
+    ];
+
+    if let Some(avatar_bytes) =
+        fetch_avatar(s3_client, &context.public_bucket_name, &job.user_sub).await
+    {
+        files.push(("avatar.png", avatar_bytes));
+    }
+
+    let archive_key = job.archive_key();
+
+    s3_client
+        .put_object()
+        .bucket(export_bucket_name)
+        .key(&archive_key)
+        .content_type("application/zip")
+        .body(ByteStream::from(build_archive(files)?))
+        .send()
+        .await
+        .map_err(|_| ())?;
+
+    let presigning_config =
+        PresigningConfig::expires_in(Duration::from_secs(DOWNLOAD_LINK_EXPIRATION_SECS))
+            .map_err(|_| ())?;
+
+    let presigned_request = s3_client
+        .get_object()
+        .bucket(export_bucket_name)
+        .key(&archive_key)
+        .presigned(presigning_config)
+        .await
+        .map_err(|_| ())?;
+
+    Ok(presigned_request.uri().to_string())
+}
+
+async fn update_job_status(
+    repository: &impl ChatRepository,
+    job: &ExportJob,
+    status: ExportStatus,
+) {
+    let updated_job = ExportJob {
+        status,
+        ..job.clone()
+    };
+
+    if let Err(error) = repository.save_export_job(&updated_job).await {
+        error!("Couldn't update the export status: {}", error);
+    }
+}
+
+pub async fn handler(record: &StreamRecord, config: &SdkConfig) {
+    let websocket_mgmt_api =
+        env::var("WEBSOCKET_MGMT_API").expect("WEBSOCKET_MGMT_API must be set");
+
+    let context = ExportContext {
+        repository: DynamoChatRepository::new(
+            AwsClients::dynamodb(config),
+            &env::var("TABLE_NAME").expect("TABLE_NAME must be set"),
+        ),
+        cognito_client: aws_sdk_cognitoidentityprovider::Client::new(config),
+        s3_client: aws_sdk_s3::Client::new(config),
+        userpool_id: env::var("USERPOOL_ID").expect("USERPOOL_ID must be set"),
+        public_bucket_name: env::var("PUBLIC_BUCKET").expect("PUBLIC_BUCKET must be set"),
+        export_bucket_name: env::var("EXPORT_BUCKET").expect("EXPORT_BUCKET must be set"),
+    };
+
+    let notifier = ApiGatewayNotifier::new(AwsClients::websocket_management(
+        config,
+        &websocket_mgmt_api,
+    ));
+
+    let job: ExportJob = match parse_event_item(&record.change.new_image) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            error!("Couldn't parse export record: {}", error);
+            return;
+        }
+    };
+
+    let repository = &context.repository;
+    let export_result = export_user_data(&context, &job).await;
+
+    let notification = match export_result {
+        Ok(download_url) => {
+            update_job_status(repository, &job, ExportStatus::Completed).await;
+
+            let expires_at =
+                Utc::now() + ChronoDuration::seconds(DOWNLOAD_LINK_EXPIRATION_SECS as i64);
+
+            json!(WebSocketEvent {
+                action: "data-export-status".to_owned(),
+                data: DataExportStatus {
+                    job_id: job.job_id.to_owned(),
+                    status: ExportStatus::Completed,
+                    download_url: Some(download_url),
+                    expires_at: Some(expires_at.to_rfc3339_opts(SecondsFormat::Millis, true)),
+                },
+            })
+        }
+
+        Err(()) => {
+            update_job_status(repository, &job, ExportStatus::Failed).await;
+
+            json!(WebSocketEvent {
+                action: "data-export-status".to_owned(),
+                data: DataExportStatus {
+                    job_id: job.job_id.to_owned(),
+                    status: ExportStatus::Failed,
+                    download_url: None,
+                    expires_at: None,
+                },
+            })
+        }
+    };
+
+    notify_user(repository, &notifier, &job.user_sub, notification).await;
+}
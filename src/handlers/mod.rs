@@ -1,2 +1,63 @@
+/*---------- Imports ----------*/
+use crate::models::stream::{StreamAttributeValue, StreamRecord};
+use aws_config::SdkConfig;
+
+#[path = "./export-insert-event-handler.rs"]
+pub mod export_insert_event;
 #[path = "./message-insert-event-handler.rs"]
 pub mod message_insert_event;
+#[path = "./profile-update-event-handler.rs"]
+pub mod profile_update_event;
+
+fn get_entity_type(record: &StreamRecord, record_event_type: &str) -> Option<String> {
+    let record_entity_type_item = match record_event_type {
+        "INSERT" | "MODIFY" => {
+            let new_image = &record.change.new_image;
+            new_image.get("entityType")?
+        }
+        "REMOVE" => {
+            let old_image = &record.change.old_image;
+            old_image.get("entityType")?
+        }
+        _ => return None,
+    };
+
+    match record_entity_type_item {
+        StreamAttributeValue::S(value) => Some(value.to_owned()),
+        _ => None,
+    }
+}
+
+/// Runs the handler registered for the kind of change and entity in a
+/// stream record. Shared by the stream processor and the local server.
+/// Data exports are left to [`handle_export_record`].
+pub async fn handle_record(record: &StreamRecord, config: &SdkConfig) {
+    let record_event_type = &record.event_name;
+    let record_entity_type = match get_entity_type(record, record_event_type) {
+        Some(entity_type) => entity_type,
+        None => return,
+    };
+
+    match (record_event_type.as_str(), record_entity_type.as_str()) {
+        ("INSERT", "message") => {
+            message_insert_event::handler(record, config).await;
+        }
+        ("INSERT", "profileUpdate") => {
+            profile_update_event::handler(record, config).await;
+        }
+        _ => {}
+    }
+}
+
+/// Builds the archive of a newly requested data export. Exports are slow and
+/// memory hungry, so they run in their own consumer instead of holding up
+/// message delivery in the stream processor.
+pub async fn handle_export_record(record: &StreamRecord, config: &SdkConfig) {
+    let record_event_type = &record.event_name;
+
+    if record_event_type == "INSERT"
+        && get_entity_type(record, record_event_type).as_deref() == Some("export")
+    {
+        export_insert_event::handler(record, config).await;
+    }
+}
@@ -0,0 +1,67 @@
+/*---------- Imports ----------*/
+use crate::{
+    models::{chat::ChatView, common::parse_event_item, stream::StreamRecord, user::ProfileUpdate},
+    repository::{dynamodb::DynamoChatRepository, ChatListQuery, ChatRepository, RepositoryError},
+    utils::aws::AwsClients,
+};
+use aws_config::SdkConfig;
+use std::{env, future};
+use tracing::error;
+
+/// Refreshes the user's profile item, then walks every chat summary they own
+/// and rewrites the copies of their profile on both sides of each chat.
+async fn propagate_profile(
+    repository: &impl ChatRepository,
+    update: &ProfileUpdate,
+) -> Result<(), RepositoryError> {
+    let user_sub = &update.user.sub;
+    let mut list_query = ChatListQuery {
+        view: ChatView::All,
+        ..ChatListQuery::default()
+    };
+
+    repository.update_profile(&update.user).await?;
+
+    loop {
+        let page = repository.list_chats(user_sub, &list_query).await?;
+
+        for chat in page.items.iter() {
+            let other_sub = match chat.other_sub() {
+                Some(sub) => sub,
+                None => continue,
+            };
+
+            let (own_result, other_result) = future::join!(
+                repository.update_chat_profile(user_sub, other_sub, &update.user),
+                repository.update_chat_profile(other_sub, user_sub, &update.user)
+            )
+            .await;
+
+            own_result?;
+            other_result?;
+        }
+
+        match page.next_cursor {
+            Some(cursor) => list_query.cursor = Some(cursor),
+            None => return Ok(()),
+        }
+    }
+}
+
+pub async fn handler(record: &StreamRecord, config: &SdkConfig) {
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let dynamodb_client = AwsClients::dynamodb(config);
+    let repository = DynamoChatRepository::new(dynamodb_client, &table_name);
+
+    let parsed_record: ProfileUpdate = match parse_event_item(&record.change.new_image) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            error!("Couldn't parse profile update record: {}", error);
+            return;
+        }
+    };
+
+    if let Err(error) = propagate_profile(&repository, &parsed_record).await {
+        error!("Couldn't propagate profile update: {}", error);
+    }
+}
@@ -0,0 +1,177 @@
+/*---------- Imports ----------*/
+use super::{LegacyConversation, LegacyStoreError, LegacyUser, LegacyUserStore};
+use crate::utils::hash::Hash;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::Path;
+
+/*---------- Structs ----------*/
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExportedUser {
+    #[serde(flatten)]
+    user: LegacyUser,
+
+    password_salt: String,
+    /// Hex encoded SHA-256 of the salt followed by the password, the way the
+    /// old system stored them.
+    password_hash: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    #[serde(default)]
+    users: Vec<ExportedUser>,
+
+    #[serde(default)]
+    conversations: Vec<LegacyConversation>,
+}
+
+/// Legacy store backed by a JSON export of the old system's users and
+/// conversations, kept in memory. The Lambda reads the export from S3, local
+/// runs can point at a file.
+#[derive(Default)]
+pub struct JsonLegacyUserStore {
+    export: Export,
+}
+
+impl JsonLegacyUserStore {
+    pub fn from_slice(export_bytes: &[u8]) -> Result<Self, LegacyStoreError> {
+        let export = serde_json::from_slice(export_bytes)
+            .map_err(|error| LegacyStoreError::InvalidData(error.to_string()))?;
+
+        Ok(Self { export })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LegacyStoreError> {
+        let export_bytes = std::fs::read(path)
+            .map_err(|error| LegacyStoreError::Unavailable(error.to_string()))?;
+
+        Self::from_slice(&export_bytes)
+    }
+
+    fn find(&self, login: &str) -> Option<&ExportedUser> {
+        let login = login.trim().to_lowercase();
+
+        self.export.users.iter().find(|exported| {
+            exported.user.username.to_lowercase() == login
+                || exported.user.email.to_lowercase() == login
+        })
+    }
+}
+
+#[async_trait]
+impl LegacyUserStore for JsonLegacyUserStore {
+    async fn find_user(&self, login: &str) -> Result<Option<LegacyUser>, LegacyStoreError> {
+        Ok(self.find(login).map(|exported| exported.user.clone()))
+    }
+
+    async fn authenticate(
+        &self,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<LegacyUser>, LegacyStoreError> {
+        let exported = match self.find(login) {
+            Some(exported) => exported,
+            None => return Ok(None),
+        };
+
+        let password_hash = Hash::sha256_hex(&format!("{}{}", exported.password_salt, password));
+
+        match Hash::constant_time_eq(
+            password_hash.as_bytes(),
+            exported.password_hash.to_lowercase().as_bytes(),
+        ) {
+            true => Ok(Some(exported.user.clone())),
+            false => Ok(None),
+        }
+    }
+
+    async fn list_conversations(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<LegacyConversation>, LegacyStoreError> {
+        Ok(self
+            .export
+            .conversations
+            .iter()
+            .filter(|conversation| conversation.other_participant(user_id).is_some())
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn store() -> JsonLegacyUserStore {
+        let export = json!({
+            "users": [{
+                "id": "legacy-1",
+                "username": "JaneDoe",
+                "email": "Jane@Example.com",
+                "displayName": "Jane",
+                "emailVerified": true,
+                "passwordSalt": "pepper",
+                "passwordHash": Hash::sha256_hex("peppercorrect horse").to_uppercase(),
+            }],
+            "conversations": [{
+                "participants": ["legacy-1", "legacy-2"],
+                "messages": [],
+            }],
+        });
+
+        JsonLegacyUserStore::from_slice(export.to_string().as_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn authenticates_by_username_or_email_ignoring_case() {
+        let store = store();
+
+        for login in ["janedoe", "JANEDOE", " jane@example.com "] {
+            let user = store.authenticate(login, "correct horse").await.unwrap();
+
+            assert_eq!(user.unwrap().id, "legacy-1");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_a_wrong_password_or_an_unknown_login() {
+        let store = store();
+
+        assert!(store
+            .authenticate("janedoe", "Correct horse")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(store
+            .authenticate("johndoe", "correct horse")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn finds_users_and_their_conversations() {
+        let store = store();
+
+        assert!(store.find_user("Jane@example.com").await.unwrap().is_some());
+        assert_eq!(store.list_conversations("legacy-2").await.unwrap().len(), 1);
+        assert!(store
+            .list_conversations("legacy-3")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn rejects_an_invalid_export() {
+        assert!(matches!(
+            JsonLegacyUserStore::from_slice(b"{\"users\": 1}"),
+            Err(LegacyStoreError::InvalidData(_))
+        ));
+    }
+}
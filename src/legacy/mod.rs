@@ -0,0 +1,167 @@
+/*---------- Imports ----------*/
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub mod json;
+
+/*---------- Constants ----------*/
+/// Custom pool attribute linking a migrated account to its legacy user.
+pub const LEGACY_ID_ATTRIBUTE: &str = "custom:legacy_id";
+
+/*---------- Enums ----------*/
+#[derive(Debug)]
+pub enum LegacyStoreError {
+    Unavailable(String),
+    InvalidData(String),
+}
+
+impl std::fmt::Display for LegacyStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LegacyStoreError::Unavailable(message) => {
+                write!(f, "Legacy user store is unavailable: {}", message)
+            }
+            LegacyStoreError::InvalidData(message) => {
+                write!(f, "Legacy user store data is invalid: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LegacyStoreError {}
+
+/*---------- Structs ----------*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyUser {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+impl LegacyUser {
+    /// The name shown in the app, falling back to the legacy username.
+    pub fn name(&self) -> &str {
+        match &self.display_name {
+            Some(name) if !name.trim().is_empty() => name.trim(),
+            _ => &self.username,
+        }
+    }
+
+    /// Attributes the MigrateUser response creates the Cognito user with.
+    /// The email is only marked as verified when the old system had
+    /// verified it, otherwise Cognito asks for a code on the next sign-in.
+    pub fn pool_attributes(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("email".to_owned(), self.email.trim().to_lowercase()),
+            ("email_verified".to_owned(), self.email_verified.to_string()),
+            ("name".to_owned(), self.name().to_owned()),
+            (LEGACY_ID_ATTRIBUTE.to_owned(), self.id.to_owned()),
+        ])
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyMessage {
+    pub id: String,
+    pub sender_id: String,
+    pub text: String,
+    /// RFC 3339 timestamp.
+    pub sent_at: String,
+}
+
+/// A one-to-one conversation of the old system, messages oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyConversation {
+    pub participants: [String; 2],
+    pub messages: Vec<LegacyMessage>,
+}
+
+impl LegacyConversation {
+    /// The participant that isn't `user_id`.
+    pub fn other_participant(&self, user_id: &str) -> Option<&str> {
+        match &self.participants {
+            [first, second] if first == user_id => Some(second.as_str()),
+            [first, second] if second == user_id => Some(first.as_str()),
+            _ => None,
+        }
+    }
+}
+
+/*---------- Traits ----------*/
+/// Read access to the accounts of the chat system users are migrated from.
+#[async_trait]
+pub trait LegacyUserStore: Send + Sync {
+    /// The user `login` refers to, matching either their username or their
+    /// email, ignoring case.
+    async fn find_user(&self, login: &str) -> Result<Option<LegacyUser>, LegacyStoreError>;
+
+    /// The user `login` refers to, as long as `password` is theirs.
+    async fn authenticate(
+        &self,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<LegacyUser>, LegacyStoreError>;
+
+    async fn list_conversations(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<LegacyConversation>, LegacyStoreError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_user(display_name: Option<&str>, email_verified: bool) -> LegacyUser {
+        LegacyUser {
+            id: "legacy-1".to_owned(),
+            username: "janedoe".to_owned(),
+            email: " Jane@Example.com ".to_owned(),
+            display_name: display_name.map(str::to_owned),
+            email_verified,
+        }
+    }
+
+    #[test]
+    fn pool_attributes_carry_the_legacy_account() {
+        let attributes = legacy_user(Some(" Jane Doe "), true).pool_attributes();
+
+        assert_eq!(attributes["email"], "jane@example.com");
+        assert_eq!(attributes["email_verified"], "true");
+        assert_eq!(attributes["name"], "Jane Doe");
+        assert_eq!(attributes[LEGACY_ID_ATTRIBUTE], "legacy-1");
+        assert_eq!(attributes.len(), 4);
+    }
+
+    #[test]
+    fn pool_attributes_fall_back_to_the_username() {
+        for display_name in [None, Some("  ")] {
+            let attributes = legacy_user(display_name, false).pool_attributes();
+
+            assert_eq!(attributes["name"], "janedoe");
+            assert_eq!(attributes["email_verified"], "false");
+        }
+    }
+
+    #[test]
+    fn other_participant_requires_membership() {
+        let conversation = LegacyConversation {
+            participants: ["legacy-1".to_owned(), "legacy-2".to_owned()],
+            messages: vec![],
+        };
+
+        assert_eq!(conversation.other_participant("legacy-1"), Some("legacy-2"));
+        assert_eq!(conversation.other_participant("legacy-2"), Some("legacy-1"));
+        assert_eq!(conversation.other_participant("legacy-3"), None);
+    }
+}
@@ -1,5 +1,12 @@
 #![feature(future_join)]
 
+pub mod api;
 pub mod handlers;
+pub mod legacy;
+pub mod mail;
 pub mod models;
+pub mod notifier;
+pub mod repository;
+pub mod storage;
 pub mod utils;
+pub mod websocket;
@@ -0,0 +1,61 @@
+/*---------- Imports ----------*/
+use super::{MailError, MailSender, OutgoingMail};
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+/*---------- Structs ----------*/
+/// Keeps every email instead of sending it, so codes and links can be read
+/// back when running locally or in tests.
+#[derive(Default)]
+pub struct InMemoryMailSender {
+    sent: Mutex<Vec<OutgoingMail>>,
+    rejected_addresses: Mutex<Vec<String>>,
+}
+
+impl InMemoryMailSender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every email sent so far, oldest first.
+    pub fn sent(&self) -> Vec<OutgoingMail> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// Makes every later email to `address` fail as undeliverable.
+    pub fn reject(&self, address: &str) {
+        self.rejected_addresses
+            .lock()
+            .unwrap()
+            .push(address.to_lowercase());
+    }
+
+    /// The latest email sent to `to`.
+    pub fn last_sent_to(&self, to: &str) -> Option<OutgoingMail> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|mail| mail.to.eq_ignore_ascii_case(to))
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl MailSender for InMemoryMailSender {
+    async fn send(&self, mail: &OutgoingMail) -> Result<(), MailError> {
+        if self
+            .rejected_addresses
+            .lock()
+            .unwrap()
+            .contains(&mail.to.to_lowercase())
+        {
+            return Err(MailError::Rejected(mail.to.to_owned()));
+        }
+
+        self.sent.lock().unwrap().push(mail.to_owned());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+/*---------- Imports ----------*/
+use async_trait::async_trait;
+
+pub mod memory;
+pub mod ses;
+
+/*---------- Enums ----------*/
+#[derive(Debug)]
+pub enum MailError {
+    SendFailed(String),
+    /// The recipient can't be delivered to, retrying won't help.
+    Rejected(String),
+}
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::SendFailed(message) => write!(f, "Couldn't send the email: {}", message),
+            MailError::Rejected(message) => write!(f, "The email was rejected: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+/*---------- Structs ----------*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutgoingMail {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/*---------- Traits ----------*/
+/// Delivers the emails the app sends itself, as opposed to the ones Cognito
+/// sends on its own.
+#[async_trait]
+pub trait MailSender: Send + Sync {
+    async fn send(&self, mail: &OutgoingMail) -> Result<(), MailError>;
+}
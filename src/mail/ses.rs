@@ -0,0 +1,61 @@
+/*---------- Imports ----------*/
+use super::{MailError, MailSender, OutgoingMail};
+use async_trait::async_trait;
+use aws_sdk_sesv2::{
+    model::{Body, Content, Destination, EmailContent, Message},
+    types::SdkError,
+};
+
+/*---------- Structs ----------*/
+pub struct SesMailSender {
+    client: aws_sdk_sesv2::Client,
+    /// Verified SES identity the emails are sent from.
+    from_address: String,
+}
+
+impl SesMailSender {
+    pub fn new(client: aws_sdk_sesv2::Client, from_address: &str) -> Self {
+        Self {
+            client,
+            from_address: from_address.to_owned(),
+        }
+    }
+}
+
+fn content(data: &str) -> Content {
+    Content::builder().data(data).charset("UTF-8").build()
+}
+
+#[async_trait]
+impl MailSender for SesMailSender {
+    async fn send(&self, mail: &OutgoingMail) -> Result<(), MailError> {
+        let message = Message::builder()
+            .subject(content(&mail.subject))
+            .body(
+                Body::builder()
+                    .html(content(&mail.html))
+                    .text(content(&mail.text))
+                    .build(),
+            )
+            .build();
+
+        let send_result = self
+            .client
+            .send_email()
+            .from_email_address(&self.from_address)
+            .destination(Destination::builder().to_addresses(&mail.to).build())
+            .content(EmailContent::builder().simple(message).build())
+            .send()
+            .await;
+
+        match send_result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_message_rejected() || err.is_account_suspended_exception() =>
+            {
+                Err(MailError::Rejected(err.to_string()))
+            }
+            Err(error) => Err(MailError::SendFailed(error.to_string())),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+/*---------- Imports ----------*/
+use super::{common::DatabaseItem, keys};
+use serde::{Deserialize, Serialize};
+
+/// One user blocking another, stored as `user#<blocked>` /
+/// `blocked-by#<blocker>`. Keeping it under the blocked user's partition is
+/// what lets everyone who blocked them be read with a single query.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Block {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub blocker_sub: String,
+
+    pub blocked_sub: String,
+
+    pub blocked_at: String,
+}
+
+impl Block {
+    pub fn new(blocker_sub: &str, blocked_sub: &str, blocked_at: &str) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::user_key(blocked_sub),
+                keys::blocked_by_key(blocker_sub),
+                "block",
+            ),
+            blocker_sub: blocker_sub.to_owned(),
+            blocked_sub: blocked_sub.to_owned(),
+            blocked_at: blocked_at.to_owned(),
+        }
+    }
+}
@@ -1,15 +1,16 @@
 /*---------- Imports ----------*/
-use super::{common::DatabaseItem, user::User};
+use super::{common::DatabaseItem, keys, user::User};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum MessageStatus {
     Ok,
     Error,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum ChatType {
     Private,
@@ -27,19 +28,53 @@ impl std::fmt::Display for ChatType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl std::str::FromStr for ChatType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "private" => Ok(ChatType::Private),
+            "group" => Ok(ChatType::Group),
+            _ => Err(format!("Unknown chat type \"{}\"", value)),
+        }
+    }
+}
+
+/// Which of the user's chats a listing returns, based on their settings.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "camelCase")]
-pub enum MessageType {
-    Text,
-    Image,
+pub enum ChatView {
+    /// Everything not archived, pinned chats first.
+    #[default]
+    Inbox,
+    Archived,
+    Pinned,
+    /// Every chat regardless of settings, for jobs walking the whole list.
+    All,
 }
 
-impl Default for MessageType {
-    fn default() -> Self {
-        Self::Text
+impl std::str::FromStr for ChatView {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "inbox" => Ok(ChatView::Inbox),
+            "archived" => Ok(ChatView::Archived),
+            "pinned" => Ok(ChatView::Pinned),
+            "all" => Ok(ChatView::All),
+            _ => Err(format!("Unknown chat view \"{}\"", value)),
+        }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageType {
+    #[default]
+    Text,
+    Image,
+}
+
 impl std::fmt::Display for MessageType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let string_version = match self {
@@ -55,7 +90,7 @@ fn default_message_content() -> String {
     String::from("")
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MessagePayload {
     pub temp_id: String,
@@ -75,7 +110,7 @@ pub struct MessagePayload {
     pub group_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Message {
     #[serde(flatten)]
@@ -89,3 +124,225 @@ pub struct Message {
 
     pub user: User,
 }
+
+impl Message {
+    pub fn private(
+        sender: &User,
+        receiver_sub: &str,
+        message_id: &str,
+        timestamp: &str,
+        content: &str,
+        message_type: MessageType,
+    ) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::private_chat_key(receiver_sub, &sender.sub),
+                keys::message_key(message_id),
+                "message",
+            ),
+            content: content.to_owned(),
+            message_type,
+            timestamp: timestamp.to_owned(),
+            user: sender.clone(),
+        }
+    }
+
+    /// A message copied over from another system. It's stored under its own
+    /// entity type, which the stream processor leaves alone, so importing a
+    /// conversation doesn't rebuild the chat summaries once per message.
+    pub fn imported(
+        sender: &User,
+        receiver_sub: &str,
+        message_id: &str,
+        timestamp: &str,
+        content: &str,
+        message_type: MessageType,
+    ) -> Self {
+        let mut message = Self::private(
+            sender,
+            receiver_sub,
+            message_id,
+            timestamp,
+            content,
+            message_type,
+        );
+
+        message.db_item.entity_type = "importedMessage".to_owned();
+
+        message
+    }
+
+    pub fn message_id(&self) -> Option<&str> {
+        keys::parse_message_key(&self.db_item.sort_key)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LastMessage {
+    pub user_name: String,
+
+    pub user_sub: String,
+
+    pub timestamp: String,
+
+    pub preview: String,
+
+    pub message_type: MessageType,
+}
+
+/// Preferences a participant keeps on their own copy of a chat.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSettings {
+    #[serde(default)]
+    pub muted: bool,
+
+    #[serde(default)]
+    pub archived: bool,
+
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// Per-participant chat summary stored as `user#<sub>` / `chat@user#<other>`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatItem {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub chat_type: ChatType,
+
+    pub unread_messages: u32,
+
+    pub last_message: LastMessage,
+
+    pub title: String,
+
+    pub user: User,
+
+    #[serde(default)]
+    pub settings: ChatSettings,
+
+    /// Left out of the owner's chat list until the next message arrives.
+    #[serde(default)]
+    pub hidden: bool,
+
+    /// Messages with an id up to this ULID were cleared by the owner and
+    /// aren't part of their history anymore.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cleared_before: Option<String>,
+}
+
+impl ChatItem {
+    /// Builds the summary `owner` sees for their private chat with `other`.
+    pub fn private(owner: &User, other: &User, last_message: &Message) -> Self {
+        let mut db_item = DatabaseItem::new(
+            keys::user_key(&owner.sub),
+            keys::chat_with_user_key(&other.sub),
+            "chat",
+        );
+
+        db_item.gsi2_pk = Some(keys::user_key(&owner.sub));
+        db_item.gsi2_sk = Some(keys::chat_timestamp_key(&last_message.timestamp));
+
+        Self {
+            db_item,
+            chat_type: ChatType::Private,
+            unread_messages: 1,
+            last_message: LastMessage {
+                user_name: last_message.user.name.to_owned(),
+                user_sub: last_message.user.sub.to_owned(),
+                timestamp: last_message.timestamp.to_owned(),
+                preview: last_message.content.to_owned(),
+                message_type: last_message.message_type,
+            },
+            title: other.name.to_owned(),
+            user: owner.clone(),
+            settings: ChatSettings::default(),
+            hidden: false,
+            cleared_before: None,
+        }
+    }
+
+    pub fn owner_sub(&self) -> Option<&str> {
+        keys::parse_user_key(&self.db_item.partition_key)
+    }
+
+    pub fn other_sub(&self) -> Option<&str> {
+        keys::parse_chat_with_user_key(&self.db_item.sort_key)
+    }
+}
+
+/// Chat summary as returned by the API, without any of the storage keys.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSummary {
+    /// For private chats, the sub of the other participant.
+    pub chat_id: String,
+
+    pub chat_type: ChatType,
+
+    pub title: String,
+
+    pub unread_messages: u32,
+
+    pub last_message: LastMessage,
+
+    pub settings: ChatSettings,
+
+    pub updated_at: String,
+}
+
+impl From<ChatItem> for ChatSummary {
+    fn from(item: ChatItem) -> Self {
+        Self {
+            chat_id: item.other_sub().unwrap_or_default().to_owned(),
+            chat_type: item.chat_type,
+            title: item.title,
+            unread_messages: item.unread_messages,
+            updated_at: item.last_message.timestamp.to_owned(),
+            last_message: item.last_message,
+            settings: item.settings,
+        }
+    }
+}
+
+/// Full view of a chat as seen by one of its participants.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Chat {
+    pub chat_id: String,
+
+    pub chat_type: ChatType,
+
+    pub title: String,
+
+    pub participants: Vec<User>,
+
+    pub settings: ChatSettings,
+
+    pub unread_messages: u32,
+
+    pub last_message: LastMessage,
+
+    /// Id of the last message the caller cleared, older ones are gone from
+    /// their history.
+    pub cleared_before: Option<String>,
+}
+
+impl Chat {
+    pub fn from_item(item: ChatItem, participants: Vec<User>) -> Self {
+        Self {
+            chat_id: item.other_sub().unwrap_or_default().to_owned(),
+            chat_type: item.chat_type,
+            title: item.title,
+            participants,
+            settings: item.settings,
+            unread_messages: item.unread_messages,
+            last_message: item.last_message,
+            cleared_before: item.cleared_before,
+        }
+    }
+}
@@ -1,108 +1,483 @@
 /*---------- Imports ----------*/
-use aws_lambda_events::dynamodb::attributes::AttributeValue as EventAttributeValue;
+use super::stream::{StreamAttributeValue, StreamImage};
 use aws_sdk_dynamodb::model::AttributeValue as DynamoAttributeValue;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::collections::HashMap;
+use schemars::JsonSchema;
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor},
+    Deserialize, Serialize,
+};
+use std::{cell::RefCell, collections::HashMap};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct WebSocketEvent<T> {
     pub action: String,
     pub data: T,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DatabaseItem {
     pub partition_key: String,
     pub sort_key: String,
     pub entity_type: String,
 
-    #[serde(rename = "gsi1PK")]
+    // Index keys can't be stored as NULL, so unset ones are left out entirely
+    #[serde(rename = "gsi1PK", skip_serializing_if = "Option::is_none")]
     pub gsi1_pk: Option<String>,
 
-    #[serde(rename = "gsi1SK")]
+    #[serde(rename = "gsi1SK", skip_serializing_if = "Option::is_none")]
     pub gsi1_sk: Option<String>,
 
-    #[serde(rename = "gsi2PK")]
+    #[serde(rename = "gsi2PK", skip_serializing_if = "Option::is_none")]
     pub gsi2_pk: Option<String>,
 
-    #[serde(rename = "gsi2SK")]
+    #[serde(rename = "gsi2SK", skip_serializing_if = "Option::is_none")]
     pub gsi2_sk: Option<String>,
 }
 
-fn convert_attribute(attribute: &EventAttributeValue) -> DynamoAttributeValue {
-    match attribute {
-        EventAttributeValue::String(str_val) => DynamoAttributeValue::S(str_val.to_owned()),
+impl DatabaseItem {
+    pub fn new(partition_key: String, sort_key: String, entity_type: &str) -> Self {
+        Self {
+            partition_key,
+            sort_key,
+            entity_type: entity_type.to_owned(),
+            gsi1_pk: None,
+            gsi1_sk: None,
+            gsi2_pk: None,
+            gsi2_sk: None,
+        }
+    }
+}
 
-        EventAttributeValue::Number(num_val) => DynamoAttributeValue::N(num_val.to_string()),
+#[derive(Debug, PartialEq)]
+pub enum AttributeConversionError {
+    UnsupportedType { path: String },
+    Deserialization { path: String, message: String },
+}
 
-        EventAttributeValue::Boolean(bool_value) => {
-            DynamoAttributeValue::Bool(bool_value.to_owned())
+impl std::fmt::Display for AttributeConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributeConversionError::UnsupportedType { path } => {
+                write!(f, "Attribute \"{}\" has an unsupported type", path)
+            }
+            AttributeConversionError::Deserialization { path, message } if path.is_empty() => {
+                write!(f, "Couldn't deserialize item: {}", message)
+            }
+            AttributeConversionError::Deserialization { path, message } => {
+                write!(
+                    f,
+                    "Couldn't deserialize attribute \"{}\": {}",
+                    path, message
+                )
+            }
         }
+    }
+}
 
-        EventAttributeValue::Binary(bin_val) => {
-            DynamoAttributeValue::B(aws_sdk_dynamodb::types::Blob::new(bin_val.to_owned()))
-        }
+impl std::error::Error for AttributeConversionError {}
 
-        EventAttributeValue::StringSet(ss_val) => DynamoAttributeValue::Ss(ss_val.to_owned()),
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
 
-        EventAttributeValue::NumberSet(ns_val) => {
-            let converted_list = ns_val
-                .into_iter()
-                .map(|current| current.to_string())
-                .collect();
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+/// Converts a stream record attribute into its SDK counterpart. Both sides
+/// hold numbers as decimal strings and binary data as raw bytes, so nothing
+/// is reformatted on the way.
+pub fn convert_attribute(attribute: &StreamAttributeValue) -> DynamoAttributeValue {
+    match attribute {
+        StreamAttributeValue::Null(null_val) => DynamoAttributeValue::Null(*null_val),
 
-            DynamoAttributeValue::Ns(converted_list)
+        StreamAttributeValue::S(str_val) => DynamoAttributeValue::S(str_val.to_owned()),
+
+        StreamAttributeValue::N(num_val) => DynamoAttributeValue::N(num_val.to_owned()),
+
+        StreamAttributeValue::Bool(bool_val) => DynamoAttributeValue::Bool(*bool_val),
+
+        StreamAttributeValue::B(bin_val) => {
+            DynamoAttributeValue::B(aws_sdk_dynamodb::types::Blob::new(bin_val.to_owned()))
         }
 
-        EventAttributeValue::BinarySet(bs_val) => {
+        StreamAttributeValue::Ss(ss_val) => DynamoAttributeValue::Ss(ss_val.to_owned()),
+
+        StreamAttributeValue::Ns(ns_val) => DynamoAttributeValue::Ns(ns_val.to_owned()),
+
+        StreamAttributeValue::Bs(bs_val) => {
             let converted_list = bs_val
-                .into_iter()
+                .iter()
                 .map(|current| aws_sdk_dynamodb::types::Blob::new(current.to_owned()))
                 .collect();
 
             DynamoAttributeValue::Bs(converted_list)
         }
 
-        EventAttributeValue::AttributeMap(am_val) => {
-            let converted_map = parse_attribute_map(am_val);
+        StreamAttributeValue::M(am_val) => DynamoAttributeValue::M(parse_attribute_map(am_val)),
 
-            DynamoAttributeValue::M(converted_map)
+        StreamAttributeValue::L(al_val) => {
+            DynamoAttributeValue::L(al_val.iter().map(convert_attribute).collect())
         }
+    }
+}
+
+/// Converts an SDK attribute back into the representation used by stream
+/// records. `path` names the attribute being converted and is reported back
+/// for types streams can't carry.
+pub fn convert_sdk_attribute(
+    path: &str,
+    attribute: &DynamoAttributeValue,
+) -> Result<StreamAttributeValue, AttributeConversionError> {
+    let converted = match attribute {
+        DynamoAttributeValue::Null(null_val) => StreamAttributeValue::Null(*null_val),
+
+        DynamoAttributeValue::S(str_val) => StreamAttributeValue::S(str_val.to_owned()),
+
+        DynamoAttributeValue::N(num_val) => StreamAttributeValue::N(num_val.to_owned()),
+
+        DynamoAttributeValue::Bool(bool_val) => StreamAttributeValue::Bool(*bool_val),
+
+        DynamoAttributeValue::B(bin_val) => StreamAttributeValue::B(bin_val.as_ref().to_vec()),
+
+        DynamoAttributeValue::Ss(ss_val) => StreamAttributeValue::Ss(ss_val.to_owned()),
 
-        EventAttributeValue::AttributeList(al_val) => {
-            let converted_list = al_val.iter().map(|attr| convert_attribute(attr)).collect();
+        DynamoAttributeValue::Ns(ns_val) => StreamAttributeValue::Ns(ns_val.to_owned()),
 
-            DynamoAttributeValue::L(converted_list)
+        DynamoAttributeValue::Bs(bs_val) => {
+            let converted_list = bs_val
+                .iter()
+                .map(|current| current.as_ref().to_vec())
+                .collect();
+
+            StreamAttributeValue::Bs(converted_list)
+        }
+
+        DynamoAttributeValue::M(am_val) => {
+            StreamAttributeValue::M(convert_sdk_attribute_map(path, am_val)?)
+        }
+
+        DynamoAttributeValue::L(al_val) => {
+            let converted_list = al_val
+                .iter()
+                .enumerate()
+                .map(|(index, attr)| convert_sdk_attribute(&index_path(path, index), attr))
+                .collect::<Result<Vec<StreamAttributeValue>, AttributeConversionError>>()?;
+
+            StreamAttributeValue::L(converted_list)
+        }
+
+        _ => {
+            return Err(AttributeConversionError::UnsupportedType {
+                path: path.to_owned(),
+            })
         }
+    };
+
+    Ok(converted)
+}
+
+fn convert_sdk_attribute_map(
+    path: &str,
+    map: &HashMap<String, DynamoAttributeValue>,
+) -> Result<StreamImage, AttributeConversionError> {
+    map.iter()
+        .map(|(key, value)| {
+            let parsed_value = convert_sdk_attribute(&child_path(path, key), value)?;
 
-        _ => DynamoAttributeValue::Null(true),
+            Ok((key.to_owned(), parsed_value))
+        })
+        .collect()
+}
+
+/// Converts a whole stream image (`NewImage` / `OldImage`) into an SDK item.
+pub fn parse_attribute_map(map: &StreamImage) -> HashMap<String, DynamoAttributeValue> {
+    map.iter()
+        .map(|(key, value)| (key.to_owned(), convert_attribute(value)))
+        .collect()
+}
+
+/// Converts an SDK item into the image format used by stream records.
+pub fn to_event_attribute_map(
+    map: &HashMap<String, DynamoAttributeValue>,
+) -> Result<StreamImage, AttributeConversionError> {
+    convert_sdk_attribute_map("", map)
+}
+
+/// Hands the attributes of an image to the item's `Deserialize` one at a
+/// time, keeping track of the one being read so a failure can name it.
+struct ImageDeserializer<'a> {
+    attributes: std::collections::hash_map::IntoIter<String, StreamAttributeValue>,
+    pending_value: Option<StreamAttributeValue>,
+    current_path: &'a RefCell<String>,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for ImageDeserializer<'a> {
+    type Error = serde_dynamo::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
     }
 }
 
-fn parse_attribute_map(
-    map: &HashMap<String, EventAttributeValue>,
-) -> HashMap<String, DynamoAttributeValue> {
-    let mut converted_hashmap: HashMap<String, DynamoAttributeValue> = HashMap::new();
-    map.into_iter().for_each(|(key, value)| {
-        let parsed_value = convert_attribute(value);
+impl<'de, 'a> MapAccess<'de> for ImageDeserializer<'a> {
+    type Error = serde_dynamo::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.attributes.next() {
+            Some((key, value)) => {
+                self.current_path.replace(key.to_owned());
+                self.pending_value = Some(value);
+
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => {
+                // Whatever fails from here on, like a missing field, concerns
+                // the item as a whole
+                self.current_path.replace(String::new());
+
+                Ok(None)
+            }
+        }
+    }
 
-        converted_hashmap.insert(key.to_owned(), parsed_value);
-    });
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = self
+            .pending_value
+            .take()
+            .ok_or_else(|| de::Error::custom("attribute value read before its name"))?;
 
-    converted_hashmap
+        seed.deserialize(serde_dynamo::Deserializer::from_attribute_value(value))
+    }
 }
 
 pub fn parse_event_item<T: DeserializeOwned>(
-    item: &HashMap<String, EventAttributeValue>,
-) -> Option<T> {
-    let parsed_hashmap = parse_attribute_map(item);
-
-    let parsed_message: T = match serde_dynamo::from_item(parsed_hashmap) {
-        Ok(parsed) => parsed,
-        Err(_) => return None,
+    item: &StreamImage,
+) -> Result<T, AttributeConversionError> {
+    let current_path = RefCell::new(String::new());
+    let deserializer = ImageDeserializer {
+        attributes: item.clone().into_iter(),
+        pending_value: None,
+        current_path: &current_path,
     };
 
-    Some(parsed_message)
+    T::deserialize(deserializer).map_err(|error| AttributeConversionError::Deserialization {
+        path: current_path.take(),
+        message: error.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::stream::StreamEvent;
+    use serde_json::json;
+
+    /// Small deterministic generator so failures reproduce without a seed.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0 >> 33
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+
+        fn digits(&mut self, length: u64) -> String {
+            (0..length)
+                .map(|_| char::from(b'0' + self.below(10) as u8))
+                .collect()
+        }
+
+        /// Decimal strings DynamoDB accepts, up to its 38 digits of precision.
+        fn number(&mut self) -> String {
+            let sign = if self.below(2) == 0 { "" } else { "-" };
+            let integer_length = 1 + self.below(20);
+            let fraction_length = self.below(38 - integer_length);
+            let integer = self.digits(integer_length);
+
+            match fraction_length {
+                0 => format!("{}{}", sign, integer),
+                _ => format!("{}{}.{}", sign, integer, self.digits(fraction_length)),
+            }
+        }
+
+        fn bytes(&mut self) -> Vec<u8> {
+            (0..self.below(16)).map(|_| self.below(256) as u8).collect()
+        }
+
+        fn attribute(&mut self, depth: u32) -> StreamAttributeValue {
+            let kinds = if depth == 0 { 8 } else { 10 };
+
+            match self.below(kinds) {
+                0 => StreamAttributeValue::Null(true),
+                1 => {
+                    let length = self.below(8);
+                    StreamAttributeValue::S(self.digits(length))
+                }
+                2 => StreamAttributeValue::N(self.number()),
+                3 => StreamAttributeValue::Bool(self.below(2) == 0),
+                4 => StreamAttributeValue::B(self.bytes()),
+                5 => StreamAttributeValue::Ss((0..3).map(|_| self.digits(4)).collect()),
+                6 => StreamAttributeValue::Ns((0..3).map(|_| self.number()).collect()),
+                7 => StreamAttributeValue::Bs((0..3).map(|_| self.bytes()).collect()),
+                8 => StreamAttributeValue::L((0..3).map(|_| self.attribute(depth - 1)).collect()),
+                _ => StreamAttributeValue::M(self.image(depth - 1)),
+            }
+        }
+
+        fn image(&mut self, depth: u32) -> StreamImage {
+            (0..self.below(5))
+                .map(|index| (format!("attr{}", index), self.attribute(depth)))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn stream_and_sdk_attributes_round_trip() {
+        let mut generator = Lcg(28);
+
+        for _ in 0..500 {
+            let image = generator.image(3);
+            let sdk_item = parse_attribute_map(&image);
+
+            assert_eq!(to_event_attribute_map(&sdk_item), Ok(image));
+        }
+    }
+
+    #[test]
+    fn numbers_keep_their_exact_digits() {
+        let mut generator = Lcg(38);
+
+        for _ in 0..500 {
+            let number = generator.number();
+            let event: StreamEvent = serde_json::from_value(json!({
+                "Records": [{
+                    "eventID": "1",
+                    "eventName": "INSERT",
+                    "dynamodb": { "NewImage": { "amount": { "N": number } } },
+                }],
+            }))
+            .unwrap();
+
+            let sdk_item = parse_attribute_map(&event.records[0].change.new_image);
+
+            assert_eq!(sdk_item["amount"], DynamoAttributeValue::N(number));
+        }
+
+        // Beyond what an f64 can hold
+        let precise = "12345678901234567890.123456789012345678";
+        let image = StreamImage::from([(
+            "amount".to_owned(),
+            StreamAttributeValue::N(precise.to_owned()),
+        )]);
+
+        assert_eq!(
+            parse_attribute_map(&image)["amount"],
+            DynamoAttributeValue::N(precise.to_owned())
+        );
+    }
+
+    #[test]
+    fn binary_data_is_decoded_from_the_stream() {
+        let event: StreamEvent = serde_json::from_value(json!({
+            "Records": [{
+                "eventName": "INSERT",
+                "dynamodb": { "NewImage": { "blob": { "B": "AP8Q" } } },
+            }],
+        }))
+        .unwrap();
+
+        let sdk_item = parse_attribute_map(&event.records[0].change.new_image);
+
+        assert_eq!(
+            sdk_item["blob"],
+            DynamoAttributeValue::B(aws_sdk_dynamodb::types::Blob::new(vec![0x00, 0xff, 0x10]))
+        );
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[serde(rename_all = "camelCase")]
+    struct Counter {
+        #[serde(flatten)]
+        db_item: DatabaseItem,
+
+        count: u64,
+    }
+
+    fn counter_image(count: StreamAttributeValue) -> StreamImage {
+        StreamImage::from([
+            (
+                "partitionKey".to_owned(),
+                StreamAttributeValue::S("user#1".to_owned()),
+            ),
+            (
+                "sortKey".to_owned(),
+                StreamAttributeValue::S("counter".to_owned()),
+            ),
+            (
+                "entityType".to_owned(),
+                StreamAttributeValue::S("counter".to_owned()),
+            ),
+            ("count".to_owned(), count),
+        ])
+    }
+
+    #[test]
+    fn event_items_deserialize() {
+        let image = counter_image(StreamAttributeValue::N("18446744073709551615".to_owned()));
+        let counter: Counter = parse_event_item(&image).unwrap();
+
+        assert_eq!(counter.count, u64::MAX);
+        assert_eq!(counter.db_item.sort_key, "counter");
+    }
+
+    #[test]
+    fn deserialization_errors_name_the_attribute() {
+        let image = counter_image(StreamAttributeValue::S("many".to_owned()));
+        let error = parse_event_item::<Counter>(&image).unwrap_err();
+
+        match error {
+            AttributeConversionError::Deserialization { path, .. } => assert_eq!(path, "count"),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_attributes_are_reported_for_the_item() {
+        let mut image = counter_image(StreamAttributeValue::N("1".to_owned()));
+        image.remove("count");
+
+        match parse_event_item::<Counter>(&image).unwrap_err() {
+            AttributeConversionError::Deserialization { path, message } => {
+                assert_eq!(path, "");
+                assert!(message.contains("count"), "{}", message);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,34 @@
+/*---------- Imports ----------*/
+use super::{common::DatabaseItem, keys};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Connection {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub connection_id: String,
+}
+
+impl Connection {
+    pub fn new(user_sub: &str, connection_id: &str) -> Self {
+        let mut db_item = DatabaseItem::new(
+            keys::user_key(user_sub),
+            keys::CONNECTION_KEY.to_owned(),
+            "connection",
+        );
+
+        db_item.gsi1_pk = Some(keys::CONNECTION_KEY.to_owned());
+        db_item.gsi1_sk = Some(keys::user_key(user_sub));
+
+        Self {
+            db_item,
+            connection_id: connection_id.to_owned(),
+        }
+    }
+
+    pub fn user_sub(&self) -> Option<&str> {
+        keys::parse_user_key(&self.db_item.partition_key)
+    }
+}
@@ -0,0 +1,106 @@
+/*---------- Imports ----------*/
+use super::{common::DatabaseItem, keys};
+use chrono::{SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/*---------- Enums ----------*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DeletionStep {
+    Connection,
+    Messages,
+    ChatSummaries,
+    Exports,
+    LoginCode,
+    ProfileUpdates,
+    InviteQuota,
+    Invitations,
+    LegacyLink,
+    Avatar,
+    EmailClaim,
+    Profile,
+    Account,
+}
+
+impl DeletionStep {
+    /// Every step of the cascade, in the order they must run. Messages are
+    /// anonymized before the chat summaries are removed because the summaries
+    /// are how the other participants are discovered, and the email claim is
+    /// released before the profile it's read from.
+    pub const ALL: [DeletionStep; 13] = [
+        DeletionStep::Connection,
+        DeletionStep::Messages,
+        DeletionStep::ChatSummaries,
+        DeletionStep::Exports,
+        DeletionStep::LoginCode,
+        DeletionStep::ProfileUpdates,
+        DeletionStep::InviteQuota,
+        DeletionStep::Invitations,
+        DeletionStep::LegacyLink,
+        DeletionStep::Avatar,
+        DeletionStep::EmailClaim,
+        DeletionStep::Profile,
+        DeletionStep::Account,
+    ];
+}
+
+impl std::fmt::Display for DeletionStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string_version = match self {
+            DeletionStep::Connection => "connection",
+            DeletionStep::Messages => "messages",
+            DeletionStep::ChatSummaries => "chatSummaries",
+            DeletionStep::Exports => "exports",
+            DeletionStep::LoginCode => "loginCode",
+            DeletionStep::ProfileUpdates => "profileUpdates",
+            DeletionStep::InviteQuota => "inviteQuota",
+            DeletionStep::Invitations => "invitations",
+            DeletionStep::LegacyLink => "legacyLink",
+            DeletionStep::Avatar => "avatar",
+            DeletionStep::EmailClaim => "emailClaim",
+            DeletionStep::Profile => "profile",
+            DeletionStep::Account => "account",
+        };
+
+        write!(f, "{}", string_version)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DeletionStatus {
+    InProgress,
+    Completed,
+}
+
+/*---------- Structs ----------*/
+/// How far the deletion of an account got, stored as `user#<sub>` /
+/// `deletion` so a failed run resumes where it stopped.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionProgress {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub status: DeletionStatus,
+
+    pub completed_steps: Vec<DeletionStep>,
+
+    pub updated_at: String,
+}
+
+impl DeletionProgress {
+    pub fn new(user_sub: &str) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::user_key(user_sub),
+                keys::DELETION_KEY.to_owned(),
+                "deletion",
+            ),
+            status: DeletionStatus::InProgress,
+            completed_steps: vec![],
+            updated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+/*---------- Imports ----------*/
+use super::{common::DatabaseItem, keys};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+impl std::fmt::Display for ExportStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string_version = match self {
+            ExportStatus::Pending => "pending",
+            ExportStatus::Completed => "completed",
+            ExportStatus::Failed => "failed",
+        };
+
+        write!(f, "{}", string_version)
+    }
+}
+
+/// A data export stored as `user#<sub>` / `export#<ulid>`. Inserting one is
+/// what starts the export, the processor then records how it ended.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportJob {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub job_id: String,
+
+    pub user_sub: String,
+
+    pub status: ExportStatus,
+
+    pub requested_at: String,
+}
+
+impl ExportJob {
+    pub fn new(user_sub: &str, job_id: &str, requested_at: &str) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::user_key(user_sub),
+                keys::export_key(job_id),
+                "export",
+            ),
+            job_id: job_id.to_owned(),
+            user_sub: user_sub.to_owned(),
+            status: ExportStatus::Pending,
+            requested_at: requested_at.to_owned(),
+        }
+    }
+
+    /// Where the archive is written in the export bucket.
+    pub fn archive_key(&self) -> String {
+        format!("exports/{}/{}.zip", self.user_sub, self.job_id)
+    }
+}
@@ -0,0 +1,110 @@
+/*---------- Imports ----------*/
+use super::{
+    chat::{Message, MessageType},
+    common::DatabaseItem,
+    keys,
+    user::User,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A message written to someone who hasn't signed up yet. The id and
+/// timestamp are fixed when it's written, so the chat reads in the original
+/// order once it's delivered.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedMessage {
+    pub message_id: String,
+
+    pub content: String,
+
+    pub timestamp: String,
+}
+
+/// Pending invitation stored as `invite#<email>` / `user#<inviter>`, one
+/// per inviter. The email is normalized, so every alias of the invitee's
+/// mailbox matches it when they sign up. The inviter finds theirs on GSI1,
+/// under `invites@user#<inviter>`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Invitation {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub email: String,
+
+    pub inviter: User,
+
+    pub invited_at: String,
+
+    #[serde(default)]
+    pub messages: Vec<QueuedMessage>,
+}
+
+impl Invitation {
+    pub fn new(email: &str, inviter: &User, invited_at: &str) -> Self {
+        let mut db_item = DatabaseItem::new(
+            keys::invite_key(email),
+            keys::user_key(&inviter.sub),
+            "invitation",
+        );
+
+        db_item.gsi1_pk = Some(keys::sent_invites_key(&inviter.sub));
+        db_item.gsi1_sk = Some(keys::invite_key(email));
+
+        Self {
+            db_item,
+            email: email.to_owned(),
+            inviter: inviter.clone(),
+            invited_at: invited_at.to_owned(),
+            messages: vec![],
+        }
+    }
+
+    /// The queued messages as sent by the inviter to `invitee_sub`.
+    pub fn to_messages(&self, invitee_sub: &str) -> Vec<Message> {
+        self.messages
+            .iter()
+            .map(|queued_message| {
+                Message::private(
+                    &self.inviter,
+                    invitee_sub,
+                    &queued_message.message_id,
+                    &queued_message.timestamp,
+                    &queued_message.content,
+                    MessageType::Text,
+                )
+            })
+            .collect()
+    }
+}
+
+/// How many invites a user sent on a given day, stored as `user#<sub>` /
+/// `invite-quota#<day>`. DynamoDB removes it once `expiresAt` has passed,
+/// through the table's TTL.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteQuota {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    #[serde(default)]
+    pub sent: u32,
+
+    /// Unix timestamp in seconds.
+    pub expires_at: i64,
+}
+
+impl InviteQuota {
+    pub fn new(inviter_sub: &str, day: &str, expires_at: i64) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::user_key(inviter_sub),
+                keys::invite_quota_key(day),
+                "inviteQuota",
+            ),
+            sent: 0,
+            expires_at,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Key builders and parsers for the single-table design.
+//!
+//! | Item         | partitionKey     | sortKey              | GSI1                                                    | GSI2                                 |
+//! |--------------|------------------|----------------------|---------------------------------------------------------|--------------------------------------|
+//! | Profile      | `user#<sub>`     | `profile`            |                                                         |                                      |
+//! | Connection   | `user#<sub>`     | `connection`         | `connection` / `user#<sub>`                             |                                      |
+//! | Login code   | `user#<sub>`     | `login-code`         |                                                         |                                      |
+//! | Code quota   | `user#<sub>`     | `login-quota#<hour>` |                                                         |                                      |
+//! | Deletion     | `user#<sub>`     | `deletion`           |                                                         |                                      |
+//! | Data export  | `user#<sub>`     | `export#<ulid>`      |                                                         |                                      |
+//! | Chat summary | `user#<sub>`     | `chat@user#<other>`  | `pinned@user#<sub>` / `chat@user#<other>` (pinned only) | `user#<sub>` / `chat-timestamp#<ts>` |
+//! | Message      | `users#<a>\|<b>` | `message#<ulid>`     |                                                         |                                      |
+//! | Block        | `user#<blocked>` | `blocked-by#<sub>`   |                                                         |                                      |
+//! | Sign-up rule | `config`         | `signup-policy`      |                                                         |                                      |
+//! | Invitation   | `invite#<email>` | `user#<inviter>`     | `invites@user#<inviter>` / `invite#<email>`             |                                      |
+//! | Invite quota | `user#<sub>`     | `invite-quota#<day>` |                                                         |                                      |
+//! | Legacy link  | `legacy#<id>`    | `import`             | `legacy@user#<sub>` / `legacy#<id>`                     |                                      |
+//! | Email owner  | `email#<email>`  | `owner`              |                                                         |                                      |
+
+/*---------- Constants ----------*/
+pub const USER_PREFIX: &str = "user#";
+pub const PRIVATE_CHAT_PREFIX: &str = "users#";
+pub const MESSAGE_PREFIX: &str = "message#";
+pub const CHAT_WITH_USER_PREFIX: &str = "chat@user#";
+pub const CHAT_TIMESTAMP_PREFIX: &str = "chat-timestamp#";
+pub const BLOCKED_BY_PREFIX: &str = "blocked-by#";
+pub const PINNED_CHATS_PREFIX: &str = "pinned@user#";
+pub const PROFILE_UPDATE_PREFIX: &str = "profile-update#";
+pub const EXPORT_PREFIX: &str = "export#";
+pub const LEGACY_USER_PREFIX: &str = "legacy#";
+pub const LEGACY_IMPORTS_PREFIX: &str = "legacy@user#";
+pub const INVITE_PREFIX: &str = "invite#";
+pub const SENT_INVITES_PREFIX: &str = "invites@user#";
+pub const INVITE_QUOTA_PREFIX: &str = "invite-quota#";
+pub const LOGIN_QUOTA_PREFIX: &str = "login-quota#";
+pub const EMAIL_PREFIX: &str = "email#";
+pub const CONNECTION_KEY: &str = "connection";
+pub const PROFILE_KEY: &str = "profile";
+pub const LOGIN_CODE_KEY: &str = "login-code";
+pub const DELETION_KEY: &str = "deletion";
+pub const CONFIG_KEY: &str = "config";
+pub const SIGNUP_POLICY_KEY: &str = "signup-policy";
+pub const LEGACY_IMPORT_KEY: &str = "import";
+pub const EMAIL_OWNER_KEY: &str = "owner";
+
+/// `user#<sub>`, the partition holding everything owned by a single user.
+pub fn user_key(sub: &str) -> String {
+    format!("{}{}", USER_PREFIX, sub)
+}
+
+pub fn parse_user_key(key: &str) -> Option<&str> {
+    key.strip_prefix(USER_PREFIX)
+}
+
+/// `users#<a>|<b>`, the partition holding the messages of a private chat.
+/// Subs are sorted in descending order so both participants map to the same
+/// partition regardless of who sent the message.
+pub fn private_chat_key(first_sub: &str, second_sub: &str) -> String {
+    let mut sorted_subs_list = [first_sub, second_sub];
+
+    sorted_subs_list.sort_by(|a, b| b.cmp(a));
+
+    format!(
+        "{}{}|{}",
+        PRIVATE_CHAT_PREFIX, sorted_subs_list[0], sorted_subs_list[1]
+    )
+}
+
+pub fn parse_private_chat_key(key: &str) -> Option<(&str, &str)> {
+    key.strip_prefix(PRIVATE_CHAT_PREFIX)?.split_once('|')
+}
+
+/// `message#<ulid>`
+pub fn message_key(message_id: &str) -> String {
+    format!("{}{}", MESSAGE_PREFIX, message_id)
+}
+
+pub fn parse_message_key(key: &str) -> Option<&str> {
+    key.strip_prefix(MESSAGE_PREFIX)
+}
+
+/// `chat@user#<sub>`, the sort key of a chat summary with another user.
+pub fn chat_with_user_key(sub: &str) -> String {
+    format!("{}{}", CHAT_WITH_USER_PREFIX, sub)
+}
+
+pub fn parse_chat_with_user_key(key: &str) -> Option<&str> {
+    key.strip_prefix(CHAT_WITH_USER_PREFIX)
+}
+
+/// `chat-timestamp#<timestamp>`, used to sort chat summaries on GSI2.
+pub fn chat_timestamp_key(timestamp: &str) -> String {
+    format!("{}{}", CHAT_TIMESTAMP_PREFIX, timestamp)
+}
+
+pub fn parse_chat_timestamp_key(key: &str) -> Option<&str> {
+    key.strip_prefix(CHAT_TIMESTAMP_PREFIX)
+}
+
+/// `blocked-by#<sub>`, marks the owner of the partition as blocked by `sub`.
+pub fn blocked_by_key(sub: &str) -> String {
+    format!("{}{}", BLOCKED_BY_PREFIX, sub)
+}
+
+pub fn parse_blocked_by_key(key: &str) -> Option<&str> {
+    key.strip_prefix(BLOCKED_BY_PREFIX)
+}
+
+/// `pinned@user#<sub>`, the GSI1 partition listing the chats `sub` pinned.
+pub fn pinned_chats_key(sub: &str) -> String {
+    format!("{}{}", PINNED_CHATS_PREFIX, sub)
+}
+
+/// `profile-update#<ulid>`, a pending propagation of a profile change.
+pub fn profile_update_key(update_id: &str) -> String {
+    format!("{}{}", PROFILE_UPDATE_PREFIX, update_id)
+}
+
+/// `export#<ulid>`, the sort key of a data export job.
+pub fn export_key(job_id: &str) -> String {
+    format!("{}{}", EXPORT_PREFIX, job_id)
+}
+
+/// `invite#<email>`, the partition holding every pending invitation of a
+/// normalized email address.
+pub fn invite_key(email: &str) -> String {
+    format!("{}{}", INVITE_PREFIX, email)
+}
+
+/// `invites@user#<sub>`, the GSI1 partition of the invitations a user sent.
+pub fn sent_invites_key(sub: &str) -> String {
+    format!("{}{}", SENT_INVITES_PREFIX, sub)
+}
+
+/// `invite-quota#<day>`, with the day as `YYYY-MM-DD`.
+pub fn invite_quota_key(day: &str) -> String {
+    format!("{}{}", INVITE_QUOTA_PREFIX, day)
+}
+
+/// `login-quota#<hour>`, with the hour as `YYYY-MM-DDTHH`.
+pub fn login_quota_key(hour: &str) -> String {
+    format!("{}{}", LOGIN_QUOTA_PREFIX, hour)
+}
+
+/// `legacy#<id>`, the partition of a user migrated from the old system.
+pub fn legacy_user_key(legacy_id: &str) -> String {
+    format!("{}{}", LEGACY_USER_PREFIX, legacy_id)
+}
+
+/// `legacy@user#<sub>`, the GSI1 partition finding the legacy link of `sub`.
+pub fn legacy_imports_key(sub: &str) -> String {
+    format!("{}{}", LEGACY_IMPORTS_PREFIX, sub)
+}
+
+/// `email#<email>`, the partition of a normalized email address.
+pub fn email_key(email: &str) -> String {
+    format!("{}{}", EMAIL_PREFIX, email)
+}
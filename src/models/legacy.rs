@@ -0,0 +1,42 @@
+/*---------- Imports ----------*/
+use super::{common::DatabaseItem, keys};
+use serde::{Deserialize, Serialize};
+
+/// Links a legacy user to the Cognito account they were migrated to, stored
+/// as `legacy#<id>` / `import`. Conversations with people who haven't
+/// signed in since the migration are imported by whoever of the two does it
+/// last, once both subs are known. GSI1 finds the link from the sub.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyImport {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub legacy_id: String,
+
+    pub sub: String,
+
+    /// Set once the user's conversations have been copied over.
+    #[serde(default)]
+    pub imported_at: Option<String>,
+}
+
+impl LegacyImport {
+    pub fn new(legacy_id: &str, sub: &str) -> Self {
+        let mut db_item = DatabaseItem::new(
+            keys::legacy_user_key(legacy_id),
+            keys::LEGACY_IMPORT_KEY.to_owned(),
+            "legacyImport",
+        );
+
+        db_item.gsi1_pk = Some(keys::legacy_imports_key(sub));
+        db_item.gsi1_sk = Some(keys::legacy_user_key(legacy_id));
+
+        Self {
+            db_item,
+            legacy_id: legacy_id.to_owned(),
+            sub: sub.to_owned(),
+            imported_at: None,
+        }
+    }
+}
@@ -1,3 +1,13 @@
+pub mod block;
 pub mod chat;
 pub mod common;
+pub mod connection;
+pub mod deletion;
+pub mod export;
+pub mod invite;
+pub mod keys;
+pub mod legacy;
+pub mod otp;
+pub mod signup;
+pub mod stream;
 pub mod user;
@@ -0,0 +1,68 @@
+/*---------- Imports ----------*/
+use super::{common::DatabaseItem, keys};
+use serde::{Deserialize, Serialize};
+
+/// One-time sign-in code stored as `user#<sub>` / `login-code`. Only the
+/// hash is kept, and DynamoDB removes the item on its own once `expiresAt`
+/// has passed, through the table's TTL.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginCode {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub code_hash: String,
+
+    /// Unix timestamp in seconds. TTL deletion can lag behind, so it's
+    /// checked on every verification as well.
+    pub expires_at: i64,
+
+    /// Wrong answers given so far.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl LoginCode {
+    pub fn new(user_sub: &str, code_hash: &str, expires_at: i64) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::user_key(user_sub),
+                keys::LOGIN_CODE_KEY.to_owned(),
+                "loginCode",
+            ),
+            code_hash: code_hash.to_owned(),
+            expires_at,
+            attempts: 0,
+        }
+    }
+}
+
+/// How many sign-in codes were emailed to a user in a given hour, stored as
+/// `user#<sub>` / `login-quota#<hour>`. DynamoDB removes it once
+/// `expiresAt` has passed, through the table's TTL.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginCodeQuota {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    #[serde(default)]
+    pub issued: u32,
+
+    /// Unix timestamp in seconds.
+    pub expires_at: i64,
+}
+
+impl LoginCodeQuota {
+    pub fn new(user_sub: &str, hour: &str, expires_at: i64) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::user_key(user_sub),
+                keys::login_quota_key(hour),
+                "loginCodeQuota",
+            ),
+            issued: 0,
+            expires_at,
+        }
+    }
+}
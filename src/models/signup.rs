@@ -0,0 +1,86 @@
+/*---------- Imports ----------*/
+use super::{common::DatabaseItem, keys};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DomainMode {
+    /// Any domain not explicitly blocked.
+    #[default]
+    Open,
+    /// Only the listed domains.
+    Allowlist,
+    /// Everything but the listed domains.
+    Denylist,
+}
+
+fn default_block_disposable() -> bool {
+    true
+}
+
+/// Sign-up rules stored as `config` / `signup-policy`. Editing the item is
+/// enough to change them, the PreSignUp trigger picks it up on its own.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SignupPolicy {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    #[serde(default)]
+    pub mode: DomainMode,
+
+    /// Domains the mode applies to. Subdomains match as well.
+    #[serde(default)]
+    pub domains: Vec<String>,
+
+    /// Addresses that can't sign up, compared after normalization.
+    #[serde(default)]
+    pub blocked_addresses: Vec<String>,
+
+    #[serde(default = "default_block_disposable")]
+    pub block_disposable: bool,
+}
+
+impl Default for SignupPolicy {
+    fn default() -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::CONFIG_KEY.to_owned(),
+                keys::SIGNUP_POLICY_KEY.to_owned(),
+                "signupPolicy",
+            ),
+            mode: DomainMode::Open,
+            domains: vec![],
+            blocked_addresses: vec![],
+            block_disposable: true,
+        }
+    }
+}
+
+/// Which account a normalized email address belongs to, stored as
+/// `email#<email>` / `owner`. Providers deliver every alias of an address to
+/// the same mailbox, so this is what keeps a mailbox to a single account.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailOwner {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub email: String,
+
+    pub sub: String,
+}
+
+impl EmailOwner {
+    pub fn new(email: &str, sub: &str) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::email_key(email),
+                keys::EMAIL_OWNER_KEY.to_owned(),
+                "emailOwner",
+            ),
+            email: email.to_owned(),
+            sub: sub.to_owned(),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! DynamoDB stream events as delivered to Lambda. `aws_lambda_events` parses
+//! number attributes into `f64`, which rounds large or very precise values
+//! before any handler sees them, so the images here keep every attribute in
+//! its wire form, numbers included as the decimal strings DynamoDB sent.
+
+/*---------- Imports ----------*/
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub use serde_dynamo::AttributeValue as StreamAttributeValue;
+
+/// An item image of a stream record, keyed by attribute name.
+pub type StreamImage = HashMap<String, StreamAttributeValue>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StreamEvent {
+    #[serde(rename = "Records")]
+    pub records: Vec<StreamRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamRecord {
+    #[serde(rename = "eventID", default)]
+    pub event_id: String,
+
+    /// `INSERT`, `MODIFY` or `REMOVE`.
+    pub event_name: String,
+
+    #[serde(rename = "dynamodb")]
+    pub change: StreamChange,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct StreamChange {
+    #[serde(default)]
+    pub keys: StreamImage,
+
+    #[serde(default)]
+    pub new_image: StreamImage,
+
+    #[serde(default)]
+    pub old_image: StreamImage,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence_number: Option<String>,
+}
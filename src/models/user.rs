@@ -1,9 +1,156 @@
 /*---------- Imports ----------*/
-use serde::{Deserialize, Serialize};
+use super::{common::DatabaseItem, keys};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Claims added by the PreTokenGeneration trigger are strings, so list and
+/// number claims come in their string form.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClaimValue {
+    Number(u64),
+    Text(String),
+}
+
+fn deserialize_roles<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let roles = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+
+    Ok(roles
+        .split(',')
+        .map(|role| role.trim().to_owned())
+        .filter(|role| !role.is_empty())
+        .collect())
+}
+
+fn deserialize_version<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    match Option::<ClaimValue>::deserialize(deserializer)? {
+        Some(ClaimValue::Number(version)) => Ok(Some(version)),
+        Some(ClaimValue::Text(version)) => Ok(version.parse().ok()),
+        None => Ok(None),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub struct User {
     pub sub: String,
     pub name: String,
     pub email: String,
+
+    // The fields below only come from ID tokens. They are never serialized,
+    // so the copies of the user embedded in chats and messages stay lean.
+    #[serde(default, skip_serializing)]
+    pub avatar_url: Option<String>,
+
+    #[serde(default, skip_serializing, deserialize_with = "deserialize_roles")]
+    pub roles: Vec<String>,
+
+    #[serde(default, skip_serializing)]
+    pub tenant: Option<String>,
+
+    #[serde(default, skip_serializing, deserialize_with = "deserialize_version")]
+    pub profile_version: Option<u64>,
+}
+
+impl User {
+    pub fn new(sub: &str, name: &str, email: &str) -> Self {
+        Self {
+            sub: sub.to_owned(),
+            name: name.to_owned(),
+            email: email.to_owned(),
+            avatar_url: None,
+            roles: vec![],
+            tenant: None,
+            profile_version: None,
+        }
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|user_role| user_role == role)
+    }
+}
+
+/// Where the current avatar came from. Generated ones follow the name, so
+/// they are redrawn when it changes, while uploads are left alone.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AvatarSource {
+    #[default]
+    Generated,
+    Custom,
+}
+
+/// Copy of the Cognito profile stored as `user#<sub>` / `profile`, so
+/// lookups don't have to go through Cognito.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserProfile {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub sub: String,
+
+    pub name: String,
+
+    pub email: String,
+
+    pub avatar_url: String,
+
+    // Profiles stored before uploads were tracked only ever had initials
+    #[serde(default)]
+    pub avatar_source: AvatarSource,
+
+    pub created_at: String,
+
+    /// Bumped on every profile change, and handed out as the
+    /// `profile_version` claim so clients can tell a stale copy apart.
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl UserProfile {
+    pub fn new(user: &User, avatar_url: &str, created_at: &str) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::user_key(&user.sub),
+                keys::PROFILE_KEY.to_owned(),
+                "profile",
+            ),
+            sub: user.sub.to_owned(),
+            name: user.name.to_owned(),
+            email: user.email.to_owned(),
+            avatar_url: avatar_url.to_owned(),
+            avatar_source: AvatarSource::Generated,
+            created_at: created_at.to_owned(),
+            version: 1,
+        }
+    }
+}
+
+/// Recorded when a user changes their profile, so the stream processor can
+/// refresh the copies embedded in chat summaries.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileUpdate {
+    #[serde(flatten)]
+    pub db_item: DatabaseItem,
+
+    pub user: User,
+
+    pub requested_at: String,
+}
+
+impl ProfileUpdate {
+    pub fn new(user: &User, update_id: &str, requested_at: &str) -> Self {
+        Self {
+            db_item: DatabaseItem::new(
+                keys::user_key(&user.sub),
+                keys::profile_update_key(update_id),
+                "profileUpdate",
+            ),
+            user: user.clone(),
+            requested_at: requested_at.to_owned(),
+        }
+    }
 }
@@ -0,0 +1,41 @@
+/*---------- Imports ----------*/
+use super::{ConnectionNotifier, NotifyError};
+use async_trait::async_trait;
+use aws_sdk_apigatewaymanagement::types::{Blob, SdkError};
+use serde_json::Value;
+
+/*---------- Structs ----------*/
+/// Posts frames through the `@connections` API of the WebSocket stage.
+pub struct ApiGatewayNotifier {
+    client: aws_sdk_apigatewaymanagement::Client,
+}
+
+impl ApiGatewayNotifier {
+    pub fn new(client: aws_sdk_apigatewaymanagement::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl ConnectionNotifier for ApiGatewayNotifier {
+    async fn send(&self, connection_id: &str, frame: &Value) -> Result<(), NotifyError> {
+        let send_result = self
+            .client
+            .post_to_connection()
+            .connection_id(connection_id)
+            .data(Blob::new(frame.to_string()))
+            .send()
+            .await;
+
+        match send_result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. }) if err.is_gone_exception() => {
+                Err(NotifyError::Gone)
+            }
+            Err(SdkError::ServiceError { err, .. }) if err.is_limit_exceeded_exception() => {
+                Err(NotifyError::Throttled)
+            }
+            Err(error) => Err(NotifyError::SendFailed(error.to_string())),
+        }
+    }
+}
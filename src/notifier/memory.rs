@@ -0,0 +1,80 @@
+/*---------- Imports ----------*/
+use super::{ConnectionNotifier, NotifyError};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+/*---------- Structs ----------*/
+/// Records every frame by connection instead of posting it, so delivery can
+/// be asserted in tests. Connections can be closed or throttled on demand to
+/// exercise the failure paths.
+#[derive(Default)]
+pub struct InMemoryNotifier {
+    frames: Mutex<HashMap<String, Vec<Value>>>,
+    gone: Mutex<HashSet<String>>,
+    /// How many of the next sends to each connection are throttled.
+    throttled: Mutex<HashMap<String, u32>>,
+}
+
+impl InMemoryNotifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Frames delivered to `connection_id`, oldest first.
+    pub fn frames(&self, connection_id: &str) -> Vec<Value> {
+        self.frames
+            .lock()
+            .unwrap()
+            .get(connection_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The latest frame delivered to `connection_id`.
+    pub fn last_frame(&self, connection_id: &str) -> Option<Value> {
+        self.frames(connection_id).pop()
+    }
+
+    /// Every later send to `connection_id` fails with `Gone`.
+    pub fn close(&self, connection_id: &str) {
+        self.gone.lock().unwrap().insert(connection_id.to_owned());
+    }
+
+    /// The next `times` sends to `connection_id` fail with `Throttled`.
+    pub fn throttle(&self, connection_id: &str, times: u32) {
+        self.throttled
+            .lock()
+            .unwrap()
+            .insert(connection_id.to_owned(), times);
+    }
+}
+
+#[async_trait]
+impl ConnectionNotifier for InMemoryNotifier {
+    async fn send(&self, connection_id: &str, frame: &Value) -> Result<(), NotifyError> {
+        if self.gone.lock().unwrap().contains(connection_id) {
+            return Err(NotifyError::Gone);
+        }
+
+        if let Some(remaining) = self.throttled.lock().unwrap().get_mut(connection_id) {
+            if *remaining > 0 {
+                *remaining -= 1;
+
+                return Err(NotifyError::Throttled);
+            }
+        }
+
+        self.frames
+            .lock()
+            .unwrap()
+            .entry(connection_id.to_owned())
+            .or_default()
+            .push(frame.to_owned());
+
+        Ok(())
+    }
+}
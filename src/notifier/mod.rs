@@ -0,0 +1,37 @@
+/*---------- Imports ----------*/
+use async_trait::async_trait;
+use serde_json::Value;
+
+pub mod apigateway;
+pub mod memory;
+
+/*---------- Enums ----------*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifyError {
+    /// The connection is closed, the client has to reconnect to get frames.
+    Gone,
+    /// Too many frames were posted, retrying later may work.
+    Throttled,
+    SendFailed(String),
+}
+
+impl std::fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotifyError::Gone => write!(f, "The connection is gone"),
+            NotifyError::Throttled => write!(f, "Posting to the connection was throttled"),
+            NotifyError::SendFailed(message) => {
+                write!(f, "Couldn't post to the connection: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NotifyError {}
+
+/*---------- Traits ----------*/
+/// Pushes frames to open WebSocket connections.
+#[async_trait]
+pub trait ConnectionNotifier: Send + Sync {
+    async fn send(&self, connection_id: &str, frame: &Value) -> Result<(), NotifyError>;
+}
@@ -0,0 +1,1209 @@
+/*---------- Imports ----------*/
+use super::{decode_cursor, encode_cursor, ChatListQuery, ChatRepository, Page, RepositoryError};
+use crate::models::{
+    block::Block,
+    chat::{ChatItem, ChatSettings, ChatView, Message},
+    common::DatabaseItem,
+    connection::Connection,
+    deletion::DeletionProgress,
+    export::ExportJob,
+    invite::{Invitation, InviteQuota, QueuedMessage},
+    keys,
+    legacy::LegacyImport,
+    otp::{LoginCode, LoginCodeQuota},
+    signup::{EmailOwner, SignupPolicy},
+    user::{AvatarSource, ProfileUpdate, User, UserProfile},
+};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{
+    model::{AttributeValue, PutRequest, ReturnValue, WriteRequest},
+    types::SdkError,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_dynamo::aws_sdk_dynamodb_0_21::{from_item, from_items, to_attribute_value, to_item};
+use std::collections::HashMap;
+
+/*---------- Constants ----------*/
+/// Most items a single BatchWriteItem request accepts.
+const BATCH_WRITE_SIZE: usize = 25;
+
+/// How many times the items DynamoDB left unprocessed are sent again before
+/// giving up.
+const BATCH_WRITE_ATTEMPTS: usize = 5;
+
+/*---------- Structs ----------*/
+pub struct DynamoChatRepository {
+    client: aws_sdk_dynamodb::Client,
+    table_name: String,
+}
+
+impl DynamoChatRepository {
+    pub fn new(client: aws_sdk_dynamodb::Client, table_name: &str) -> Self {
+        Self {
+            client,
+            table_name: table_name.to_owned(),
+        }
+    }
+
+    async fn put<T: Serialize>(&self, value: &T) -> Result<(), RepositoryError> {
+        let item: HashMap<String, AttributeValue> =
+            to_item(value).map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .send()
+            .await
+            .map_err(|error| RepositoryError::RequestFailed(error.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        partition_key: String,
+        sort_key: String,
+    ) -> Result<Option<T>, RepositoryError> {
+        let get_item_output = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("partitionKey", AttributeValue::S(partition_key))
+            .key("sortKey", AttributeValue::S(sort_key))
+            .send()
+            .await
+            .map_err(|error| RepositoryError::RequestFailed(error.to_string()))?;
+
+        match get_item_output.item() {
+            Some(item) => from_item(item.to_owned())
+                .map(Some)
+                .map_err(|error| RepositoryError::InvalidItem(error.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete(&self, partition_key: String, sort_key: String) -> Result<(), RepositoryError> {
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("partitionKey", AttributeValue::S(partition_key))
+            .key("sortKey", AttributeValue::S(sort_key))
+            .send()
+            .await
+            .map_err(|error| RepositoryError::RequestFailed(error.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Applies `update_expression` to a chat summary when `condition` holds,
+    /// treating a failed condition as nothing to do.
+    async fn update_existing_chat(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        condition: &str,
+        update_expression: &str,
+        attribute_names: Option<HashMap<String, String>>,
+        attribute_values: HashMap<String, AttributeValue>,
+    ) -> Result<(), RepositoryError> {
+        let update_result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("partitionKey", AttributeValue::S(keys::user_key(owner_sub)))
+            .key(
+                "sortKey",
+                AttributeValue::S(keys::chat_with_user_key(other_sub)),
+            )
+            .condition_expression(condition)
+            .update_expression(update_expression)
+            .set_expression_attribute_names(attribute_names)
+            .set_expression_attribute_values(Some(attribute_values))
+            .send()
+            .await;
+
+        match update_result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(())
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    /// Runs a query on the table or one of its indexes and follows
+    /// `LastEvaluatedKey` until every page has been read.
+    async fn query_all<T: DeserializeOwned>(
+        &self,
+        index_name: Option<&str>,
+        key_condition: &str,
+        attribute_names: HashMap<String, String>,
+        attribute_values: HashMap<String, AttributeValue>,
+    ) -> Result<Vec<T>, RepositoryError> {
+        let mut items: Vec<HashMap<String, AttributeValue>> = vec![];
+        let mut exclusive_start_key: Option<HashMap<String, AttributeValue>> = None;
+
+        loop {
+            let query_output = self
+                .client
+                .query()
+                .table_name(&self.table_name)
+                .set_index_name(index_name.map(|name| name.to_owned()))
+                .key_condition_expression(key_condition)
+                // DynamoDB rejects an empty map, so it's only sent when used
+                .set_expression_attribute_names(
+                    (!attribute_names.is_empty()).then(|| attribute_names.clone()),
+                )
+                .set_expression_attribute_values(Some(attribute_values.clone()))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await
+                .map_err(|error| RepositoryError::RequestFailed(error.to_string()))?;
+
+            items.extend_from_slice(query_output.items().unwrap_or_default());
+
+            exclusive_start_key = query_output.last_evaluated_key().map(|key| key.to_owned());
+
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        from_items(items).map_err(|error| RepositoryError::InvalidItem(error.to_string()))
+    }
+
+    /// Every item of a partition, on the table or one of its indexes, whose
+    /// sort key starts with `sort_key_prefix`.
+    async fn query_prefix<T: DeserializeOwned>(
+        &self,
+        index: Option<(&str, &str, &str)>,
+        partition_key: &str,
+        sort_key_prefix: &str,
+    ) -> Result<Vec<T>, RepositoryError> {
+        let (index_name, partition_attribute, sort_attribute) = match index {
+            Some((index_name, partition_attribute, sort_attribute)) => {
+                (Some(index_name), partition_attribute, sort_attribute)
+            }
+            None => (None, "partitionKey", "sortKey"),
+        };
+
+        self.query_all(
+            index_name,
+            "#pk = :pk and begins_with(#sk, :sk_prefix)",
+            HashMap::from([
+                ("#pk".to_owned(), partition_attribute.to_owned()),
+                ("#sk".to_owned(), sort_attribute.to_owned()),
+            ]),
+            HashMap::from([
+                (
+                    ":pk".to_owned(),
+                    AttributeValue::S(partition_key.to_owned()),
+                ),
+                (
+                    ":sk_prefix".to_owned(),
+                    AttributeValue::S(sort_key_prefix.to_owned()),
+                ),
+            ]),
+        )
+        .await
+    }
+
+    /// Removes every item of the partition whose sort key starts with
+    /// `sort_key_prefix`.
+    async fn delete_prefix(
+        &self,
+        partition_key: &str,
+        sort_key_prefix: &str,
+    ) -> Result<(), RepositoryError> {
+        let items: Vec<DatabaseItem> = self
+            .query_prefix(None, partition_key, sort_key_prefix)
+            .await?;
+
+        for item in items {
+            self.delete(item.partition_key, item.sort_key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds one to the `counter` attribute of a quota item, creating it when
+    /// missing, unless it already reached `max`. Returns whether it was
+    /// counted.
+    async fn increment_quota(
+        &self,
+        db_item: &DatabaseItem,
+        counter: &str,
+        expires_at: i64,
+        max: u32,
+    ) -> Result<bool, RepositoryError> {
+        let update_result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(
+                "partitionKey",
+                AttributeValue::S(db_item.partition_key.to_owned()),
+            )
+            .key("sortKey", AttributeValue::S(db_item.sort_key.to_owned()))
+            .condition_expression("attribute_not_exists(#counter) OR #counter < :max")
+            .update_expression(
+                "SET entityType = :entity_type, expiresAt = :expires_at ADD #counter :one",
+            )
+            .expression_attribute_names("#counter", counter)
+            .expression_attribute_values(
+                ":entity_type",
+                AttributeValue::S(db_item.entity_type.to_owned()),
+            )
+            .expression_attribute_values(":expires_at", AttributeValue::N(expires_at.to_string()))
+            .expression_attribute_values(":one", AttributeValue::N("1".to_owned()))
+            .expression_attribute_values(":max", AttributeValue::N(max.to_string()))
+            .send()
+            .await;
+
+        match update_result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(false)
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    /// Pinned chats live under their own GSI1 partition, so they can be put
+    /// first without reading the whole chat list.
+    async fn list_pinned_chats(
+        &self,
+        user_sub: &str,
+        query: &ChatListQuery,
+    ) -> Result<Vec<ChatItem>, RepositoryError> {
+        let mut chats: Vec<ChatItem> = self
+            .query_prefix(
+                Some(("GSI1", "gsi1PK", "gsi1SK")),
+                &keys::pinned_chats_key(user_sub),
+                keys::CHAT_WITH_USER_PREFIX,
+            )
+            .await?;
+
+        chats.retain(|chat| query.matches(chat));
+        chats.sort_by(|a, b| b.last_message.timestamp.cmp(&a.last_message.timestamp));
+
+        Ok(chats)
+    }
+
+    /// One page of GSI2, newest first, with the query's filters pushed down
+    /// as a filter expression.
+    async fn query_chat_page(
+        &self,
+        user_sub: &str,
+        query: &ChatListQuery,
+    ) -> Result<Page<ChatItem>, RepositoryError> {
+        let exclusive_start_key = match &query.cursor {
+            Some(cursor) => Some(
+                decode_cursor(cursor)?
+                    .into_iter()
+                    .map(|(name, value)| (name, AttributeValue::S(value)))
+                    .collect::<HashMap<String, AttributeValue>>(),
+            ),
+            None => None,
+        };
+
+        // "$" sorts right after "#", so this upper bound covers every
+        // `chat-timestamp#` key without matching anything else
+        let lower_bound = keys::chat_timestamp_key(query.updated_since.as_deref().unwrap_or(""));
+        let upper_bound = keys::CHAT_TIMESTAMP_PREFIX.replace('#', "$");
+
+        let mut filters: Vec<&str> = vec![];
+        let mut request = self
+            .client
+            .query()
+            .table_name(&self.table_name)
+            .index_name("GSI2")
+            .key_condition_expression("gsi2PK = :pk and gsi2SK between :from and :to")
+            .expression_attribute_values(":pk", AttributeValue::S(keys::user_key(user_sub)))
+            .expression_attribute_values(":from", AttributeValue::S(lower_bound))
+            .expression_attribute_values(":to", AttributeValue::S(upper_bound))
+            .scan_index_forward(false)
+            .set_limit(query.limit.map(|limit| limit as i32))
+            .set_exclusive_start_key(exclusive_start_key);
+
+        if let Some(chat_type) = query.chat_type {
+            filters.push("chatType = :chat_type");
+            request = request.expression_attribute_values(
+                ":chat_type",
+                AttributeValue::S(chat_type.to_string()),
+            );
+        }
+
+        match query.view {
+            ChatView::Inbox => {
+                filters.push(
+                    "(attribute_not_exists(settings.archived) or settings.archived = :false)",
+                );
+                filters.push("(attribute_not_exists(settings.pinned) or settings.pinned = :false)");
+                request =
+                    request.expression_attribute_values(":false", AttributeValue::Bool(false));
+            }
+            ChatView::Archived => {
+                filters.push("settings.archived = :true");
+                request = request.expression_attribute_values(":true", AttributeValue::Bool(true));
+            }
+            // Served from GSI1 by `list_pinned_chats`
+            ChatView::Pinned | ChatView::All => {}
+        }
+
+        if query.view != ChatView::All {
+            filters.push("(attribute_not_exists(hidden) or hidden = :false)");
+            request = request.expression_attribute_values(":false", AttributeValue::Bool(false));
+        }
+
+        if query.unread_only {
+            filters.push("unreadMessages > :zero");
+            request =
+                request.expression_attribute_values(":zero", AttributeValue::N("0".to_owned()));
+        }
+
+        if !filters.is_empty() {
+            request = request.filter_expression(filters.join(" and "));
+        }
+
+        let query_output = request
+            .send()
+            .await
+            .map_err(|error| RepositoryError::RequestFailed(error.to_string()))?;
+
+        let items: Vec<ChatItem> = from_items(query_output.items().unwrap_or_default().to_vec())
+            .map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        let next_cursor = query_output.last_evaluated_key().map(|key| {
+            let string_key: HashMap<String, String> = key
+                .iter()
+                .filter_map(|(name, value)| Some((name.to_owned(), value.as_s().ok()?.to_owned())))
+                .collect();
+
+            encode_cursor(&string_key)
+        });
+
+        Ok(Page { items, next_cursor })
+    }
+}
+
+#[async_trait]
+impl ChatRepository for DynamoChatRepository {
+    async fn save_message(&self, message: &Message) -> Result<(), RepositoryError> {
+        self.put(message).await
+    }
+
+    async fn save_messages(&self, messages: &[Message]) -> Result<(), RepositoryError> {
+        for batch in messages.chunks(BATCH_WRITE_SIZE) {
+            let mut write_requests = batch
+                .iter()
+                .map(|message| {
+                    let item = to_item(message)
+                        .map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+                    Ok(WriteRequest::builder()
+                        .put_request(PutRequest::builder().set_item(Some(item)).build())
+                        .build())
+                })
+                .collect::<Result<Vec<WriteRequest>, RepositoryError>>()?;
+
+            // Throttled writes come back as unprocessed items
+            for _ in 0..BATCH_WRITE_ATTEMPTS {
+                let batch_write_output = self
+                    .client
+                    .batch_write_item()
+                    .request_items(&self.table_name, write_requests)
+                    .send()
+                    .await
+                    .map_err(|error| RepositoryError::RequestFailed(error.to_string()))?;
+
+                write_requests = batch_write_output
+                    .unprocessed_items()
+                    .and_then(|unprocessed_items| unprocessed_items.get(&self.table_name))
+                    .cloned()
+                    .unwrap_or_default();
+
+                if write_requests.is_empty() {
+                    break;
+                }
+            }
+
+            if !write_requests.is_empty() {
+                return Err(RepositoryError::RequestFailed(format!(
+                    "{} messages were left unprocessed",
+                    write_requests.len()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn list_private_messages(
+        &self,
+        first_sub: &str,
+        second_sub: &str,
+    ) -> Result<Vec<Message>, RepositoryError> {
+        let partition_key = keys::private_chat_key(first_sub, second_sub);
+
+        self.query_prefix(None, &partition_key, keys::MESSAGE_PREFIX)
+            .await
+    }
+
+    async fn list_chat_history(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+    ) -> Result<Vec<Message>, RepositoryError> {
+        let partition_key = keys::private_chat_key(owner_sub, other_sub);
+
+        let cleared_before = match self.get_private_chat(owner_sub, other_sub).await? {
+            Some(ChatItem {
+                cleared_before: Some(cleared_before),
+                ..
+            }) => cleared_before,
+            _ => {
+                return self
+                    .query_prefix(None, &partition_key, keys::MESSAGE_PREFIX)
+                    .await
+            }
+        };
+
+        // The watermark is a ULID like the message ids, so the messages
+        // still shown are the ones sorting after it. "$" sorts right after
+        // "#", which bounds the range to `message#` keys
+        self.query_all(
+            None,
+            "partitionKey = :pk and sortKey between :from and :to",
+            HashMap::new(),
+            HashMap::from([
+                (":pk".to_owned(), AttributeValue::S(partition_key)),
+                (
+                    ":from".to_owned(),
+                    AttributeValue::S(keys::message_key(&cleared_before)),
+                ),
+                (
+                    ":to".to_owned(),
+                    AttributeValue::S(keys::MESSAGE_PREFIX.replace('#', "$")),
+                ),
+            ]),
+        )
+        .await
+    }
+
+    async fn list_chats(
+        &self,
+        user_sub: &str,
+        query: &ChatListQuery,
+    ) -> Result<Page<ChatItem>, RepositoryError> {
+        if query.view == ChatView::Pinned {
+            return Ok(Page {
+                items: self.list_pinned_chats(user_sub, query).await?,
+                next_cursor: None,
+            });
+        }
+
+        let mut pinned_chats = match query.pinned() {
+            Some(pinned_query) => self.list_pinned_chats(user_sub, &pinned_query).await?,
+            None => vec![],
+        };
+
+        let mut page = self.query_chat_page(user_sub, query).await?;
+
+        pinned_chats.append(&mut page.items);
+        page.items = pinned_chats;
+
+        Ok(page)
+    }
+
+    async fn get_private_chat(
+        &self,
+        user_sub: &str,
+        other_sub: &str,
+    ) -> Result<Option<ChatItem>, RepositoryError> {
+        self.get(
+            keys::user_key(user_sub),
+            keys::chat_with_user_key(other_sub),
+        )
+        .await
+    }
+
+    async fn upsert_chat_summary(&self, chat: &ChatItem) -> Result<(), RepositoryError> {
+        let mut item: HashMap<String, AttributeValue> =
+            to_item(chat).map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        // Everything the owner controls is left out of the update, so a new
+        // message doesn't undo their settings. `hidden` does go through, which
+        // is what brings a hidden chat back
+        for owned_attribute in [
+            "partitionKey",
+            "sortKey",
+            "settings",
+            "gsi1PK",
+            "gsi1SK",
+            "clearedBefore",
+        ] {
+            item.remove(owned_attribute);
+        }
+
+        let mut assignments: Vec<String> = vec![];
+        let mut attribute_names: HashMap<String, String> = HashMap::new();
+        let mut attribute_values: HashMap<String, AttributeValue> = HashMap::new();
+
+        for (index, (name, value)) in item.into_iter().enumerate() {
+            assignments.push(format!("#attr{0} = :attr{0}", index));
+            attribute_names.insert(format!("#attr{}", index), name);
+            attribute_values.insert(format!(":attr{}", index), value);
+        }
+
+        self.client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(
+                "partitionKey",
+                AttributeValue::S(chat.db_item.partition_key.to_owned()),
+            )
+            .key(
+                "sortKey",
+                AttributeValue::S(chat.db_item.sort_key.to_owned()),
+            )
+            .update_expression(format!("SET {}", assignments.join(", ")))
+            .set_expression_attribute_names(Some(attribute_names))
+            .set_expression_attribute_values(Some(attribute_values))
+            .send()
+            .await
+            .map_err(|error| RepositoryError::RequestFailed(error.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn update_chat_settings(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        settings: &ChatSettings,
+    ) -> Result<(), RepositoryError> {
+        let settings_value = to_attribute_value(settings)
+            .map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        let mut attribute_values = HashMap::from([(":settings".to_owned(), settings_value)]);
+
+        // The pinned index entry is written alongside the flag, so both
+        // always agree
+        let update_expression = if settings.pinned {
+            attribute_values.insert(
+                ":pinned_pk".to_owned(),
+                AttributeValue::S(keys::pinned_chats_key(owner_sub)),
+            );
+            attribute_values.insert(
+                ":pinned_sk".to_owned(),
+                AttributeValue::S(keys::chat_with_user_key(other_sub)),
+            );
+
+            "SET settings = :settings, gsi1PK = :pinned_pk, gsi1SK = :pinned_sk"
+        } else {
+            "SET settings = :settings REMOVE gsi1PK, gsi1SK"
+        };
+
+        self.update_existing_chat(
+            owner_sub,
+            other_sub,
+            "attribute_exists(partitionKey)",
+            update_expression,
+            None,
+            attribute_values,
+        )
+        .await
+    }
+
+    async fn clear_chat_history(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        cleared_before: &str,
+        hide: bool,
+    ) -> Result<(), RepositoryError> {
+        let mut attribute_values = HashMap::from([
+            (
+                ":cleared_before".to_owned(),
+                AttributeValue::S(cleared_before.to_owned()),
+            ),
+            (":zero".to_owned(), AttributeValue::N("0".to_owned())),
+            (":empty".to_owned(), AttributeValue::S(String::new())),
+        ]);
+
+        let update_expression = if hide {
+            attribute_values.insert(":hidden".to_owned(), AttributeValue::Bool(true));
+
+            "SET clearedBefore = :cleared_before, unreadMessages = :zero, \
+             lastMessage.preview = :empty, hidden = :hidden"
+        } else {
+            "SET clearedBefore = :cleared_before, unreadMessages = :zero, \
+             lastMessage.preview = :empty"
+        };
+
+        self.update_existing_chat(
+            owner_sub,
+            other_sub,
+            "attribute_exists(partitionKey)",
+            update_expression,
+            None,
+            attribute_values,
+        )
+        .await
+    }
+
+    async fn replace_chat_participant(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        replacement: &User,
+    ) -> Result<(), RepositoryError> {
+        self.update_existing_chat(
+            owner_sub,
+            other_sub,
+            "attribute_exists(partitionKey)",
+            "SET title = :title",
+            None,
+            HashMap::from([(
+                ":title".to_owned(),
+                AttributeValue::S(replacement.name.to_owned()),
+            )]),
+        )
+        .await?;
+
+        self.update_existing_chat(
+            owner_sub,
+            other_sub,
+            "lastMessage.userSub = :sub",
+            "SET lastMessage.userName = :name, lastMessage.userSub = :replacement_sub",
+            None,
+            HashMap::from([
+                (":sub".to_owned(), AttributeValue::S(other_sub.to_owned())),
+                (
+                    ":name".to_owned(),
+                    AttributeValue::S(replacement.name.to_owned()),
+                ),
+                (
+                    ":replacement_sub".to_owned(),
+                    AttributeValue::S(replacement.sub.to_owned()),
+                ),
+            ]),
+        )
+        .await
+    }
+
+    async fn delete_chat_summary(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+    ) -> Result<(), RepositoryError> {
+        self.delete(
+            keys::user_key(owner_sub),
+            keys::chat_with_user_key(other_sub),
+        )
+        .await
+    }
+
+    async fn save_profile_update(&self, update: &ProfileUpdate) -> Result<(), RepositoryError> {
+        self.put(update).await
+    }
+
+    async fn delete_profile_updates(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete_prefix(&keys::user_key(user_sub), keys::PROFILE_UPDATE_PREFIX)
+            .await
+    }
+
+    async fn update_chat_profile(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        profile: &User,
+    ) -> Result<(), RepositoryError> {
+        if profile.sub == owner_sub {
+            let profile_value = to_attribute_value(profile)
+                .map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+            self.update_existing_chat(
+                owner_sub,
+                other_sub,
+                "attribute_exists(partitionKey)",
+                "SET #user = :user",
+                Some(HashMap::from([("#user".to_owned(), "user".to_owned())])),
+                HashMap::from([(":user".to_owned(), profile_value)]),
+            )
+            .await?;
+        } else {
+            self.update_existing_chat(
+                owner_sub,
+                other_sub,
+                "attribute_exists(partitionKey)",
+                "SET title = :title",
+                None,
+                HashMap::from([(
+                    ":title".to_owned(),
+                    AttributeValue::S(profile.name.to_owned()),
+                )]),
+            )
+            .await?;
+        }
+
+        self.update_existing_chat(
+            owner_sub,
+            other_sub,
+            "lastMessage.userSub = :sub",
+            "SET lastMessage.userName = :name",
+            None,
+            HashMap::from([
+                (":sub".to_owned(), AttributeValue::S(profile.sub.to_owned())),
+                (
+                    ":name".to_owned(),
+                    AttributeValue::S(profile.name.to_owned()),
+                ),
+            ]),
+        )
+        .await
+    }
+
+    async fn get_profile(&self, user_sub: &str) -> Result<Option<UserProfile>, RepositoryError> {
+        self.get(keys::user_key(user_sub), keys::PROFILE_KEY.to_owned())
+            .await
+    }
+
+    async fn create_profile(&self, profile: &UserProfile) -> Result<bool, RepositoryError> {
+        let item: HashMap<String, AttributeValue> =
+            to_item(profile).map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        let put_result = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(partitionKey)")
+            .send()
+            .await;
+
+        match put_result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(false)
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    async fn update_profile(&self, user: &User) -> Result<(), RepositoryError> {
+        let update_result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("partitionKey", AttributeValue::S(keys::user_key(&user.sub)))
+            .key("sortKey", AttributeValue::S(keys::PROFILE_KEY.to_owned()))
+            .condition_expression("attribute_exists(partitionKey)")
+            .update_expression("SET #name = :name, email = :email ADD #version :one")
+            .expression_attribute_names("#name", "name")
+            .expression_attribute_names("#version", "version")
+            .expression_attribute_values(":name", AttributeValue::S(user.name.to_owned()))
+            .expression_attribute_values(":email", AttributeValue::S(user.email.to_owned()))
+            .expression_attribute_values(":one", AttributeValue::N("1".to_owned()))
+            .send()
+            .await;
+
+        match update_result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(())
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    async fn set_avatar_source(
+        &self,
+        user_sub: &str,
+        source: AvatarSource,
+    ) -> Result<(), RepositoryError> {
+        let source_value = to_attribute_value(source)
+            .map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        let update_result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("partitionKey", AttributeValue::S(keys::user_key(user_sub)))
+            .key("sortKey", AttributeValue::S(keys::PROFILE_KEY.to_owned()))
+            .condition_expression("attribute_exists(partitionKey)")
+            .update_expression("SET avatarSource = :source")
+            .expression_attribute_values(":source", source_value)
+            .send()
+            .await;
+
+        match update_result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(())
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    async fn delete_profile(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete(keys::user_key(user_sub), keys::PROFILE_KEY.to_owned())
+            .await
+    }
+
+    async fn get_signup_policy(&self) -> Result<Option<SignupPolicy>, RepositoryError> {
+        self.get(
+            keys::CONFIG_KEY.to_owned(),
+            keys::SIGNUP_POLICY_KEY.to_owned(),
+        )
+        .await
+    }
+
+    async fn get_email_owner(&self, email: &str) -> Result<Option<EmailOwner>, RepositoryError> {
+        self.get(keys::email_key(email), keys::EMAIL_OWNER_KEY.to_owned())
+            .await
+    }
+
+    async fn claim_email(&self, owner: &EmailOwner) -> Result<bool, RepositoryError> {
+        let item: HashMap<String, AttributeValue> =
+            to_item(owner).map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        let put_result = self
+            .client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(partitionKey)")
+            .send()
+            .await;
+
+        match put_result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(false)
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    async fn release_email(&self, email: &str, user_sub: &str) -> Result<(), RepositoryError> {
+        let delete_result = self
+            .client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("partitionKey", AttributeValue::S(keys::email_key(email)))
+            .key(
+                "sortKey",
+                AttributeValue::S(keys::EMAIL_OWNER_KEY.to_owned()),
+            )
+            .condition_expression("#sub = :sub")
+            .expression_attribute_names("#sub", "sub")
+            .expression_attribute_values(":sub", AttributeValue::S(user_sub.to_owned()))
+            .send()
+            .await;
+
+        match delete_result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(())
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    async fn save_login_code(&self, login_code: &LoginCode) -> Result<(), RepositoryError> {
+        self.put(login_code).await
+    }
+
+    async fn get_login_code(&self, user_sub: &str) -> Result<Option<LoginCode>, RepositoryError> {
+        self.get(keys::user_key(user_sub), keys::LOGIN_CODE_KEY.to_owned())
+            .await
+    }
+
+    async fn record_login_code_attempt(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<u32>, RepositoryError> {
+        let update_result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("partitionKey", AttributeValue::S(keys::user_key(user_sub)))
+            .key(
+                "sortKey",
+                AttributeValue::S(keys::LOGIN_CODE_KEY.to_owned()),
+            )
+            .condition_expression("attribute_exists(partitionKey)")
+            .update_expression("ADD attempts :one")
+            .expression_attribute_values(":one", AttributeValue::N("1".to_owned()))
+            .return_values(ReturnValue::UpdatedNew)
+            .send()
+            .await;
+
+        match update_result {
+            Ok(output) => {
+                let attempts = output
+                    .attributes()
+                    .and_then(|attributes| attributes.get("attempts"))
+                    .and_then(|attempts| attempts.as_n().ok())
+                    .and_then(|attempts| attempts.parse::<u32>().ok())
+                    .ok_or_else(|| {
+                        RepositoryError::InvalidItem("attempts is missing".to_owned())
+                    })?;
+
+                Ok(Some(attempts))
+            }
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(None)
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    async fn delete_login_code(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete(keys::user_key(user_sub), keys::LOGIN_CODE_KEY.to_owned())
+            .await
+    }
+
+    async fn record_login_code_issue(
+        &self,
+        quota: &LoginCodeQuota,
+        max_codes: u32,
+    ) -> Result<bool, RepositoryError> {
+        self.increment_quota(&quota.db_item, "issued", quota.expires_at, max_codes)
+            .await
+    }
+
+    async fn delete_login_code_quotas(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete_prefix(&keys::user_key(user_sub), keys::LOGIN_QUOTA_PREFIX)
+            .await
+    }
+
+    async fn list_invitations(&self, email: &str) -> Result<Vec<Invitation>, RepositoryError> {
+        self.query_prefix(None, &keys::invite_key(email), keys::USER_PREFIX)
+            .await
+    }
+
+    async fn list_sent_invitations(
+        &self,
+        inviter_sub: &str,
+    ) -> Result<Vec<Invitation>, RepositoryError> {
+        self.query_prefix(
+            Some(("GSI1", "gsi1PK", "gsi1SK")),
+            &keys::sent_invites_key(inviter_sub),
+            keys::INVITE_PREFIX,
+        )
+        .await
+    }
+
+    async fn queue_invitation_message(
+        &self,
+        invitation: &Invitation,
+        message: &QueuedMessage,
+        max_messages: usize,
+    ) -> Result<Option<Invitation>, RepositoryError> {
+        let inviter: AttributeValue = to_attribute_value(&invitation.inviter)
+            .map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+        let queued_message: AttributeValue = to_attribute_value(message)
+            .map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        // Appending in place keeps concurrent requests from overwriting each
+        // other's messages, and the size check holds across them too
+        let update_result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key(
+                "partitionKey",
+                AttributeValue::S(invitation.db_item.partition_key.to_owned()),
+            )
+            .key(
+                "sortKey",
+                AttributeValue::S(invitation.db_item.sort_key.to_owned()),
+            )
+            .condition_expression("attribute_not_exists(messages) OR size(messages) < :max")
+            .update_expression(
+                "SET entityType = :entity_type, email = :email, inviter = :inviter, \
+                 gsi1PK = :gsi1_pk, gsi1SK = :gsi1_sk, \
+                 invitedAt = if_not_exists(invitedAt, :invited_at), \
+                 messages = list_append(if_not_exists(messages, :empty), :message)",
+            )
+            .expression_attribute_values(
+                ":entity_type",
+                AttributeValue::S(invitation.db_item.entity_type.to_owned()),
+            )
+            .expression_attribute_values(":email", AttributeValue::S(invitation.email.to_owned()))
+            .expression_attribute_values(":inviter", inviter)
+            .expression_attribute_values(
+                ":gsi1_pk",
+                AttributeValue::S(keys::sent_invites_key(&invitation.inviter.sub)),
+            )
+            .expression_attribute_values(
+                ":gsi1_sk",
+                AttributeValue::S(keys::invite_key(&invitation.email)),
+            )
+            .expression_attribute_values(
+                ":invited_at",
+                AttributeValue::S(invitation.invited_at.to_owned()),
+            )
+            .expression_attribute_values(":empty", AttributeValue::L(vec![]))
+            .expression_attribute_values(":message", AttributeValue::L(vec![queued_message]))
+            .expression_attribute_values(":max", AttributeValue::N(max_messages.to_string()))
+            .return_values(ReturnValue::AllNew)
+            .send()
+            .await;
+
+        match update_result {
+            Ok(output) => {
+                let item = output.attributes().cloned().unwrap_or_default();
+
+                from_item(item)
+                    .map(Some)
+                    .map_err(|error| RepositoryError::InvalidItem(error.to_string()))
+            }
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Ok(None)
+            }
+            Err(error) => Err(RepositoryError::RequestFailed(error.to_string())),
+        }
+    }
+
+    async fn record_invite(
+        &self,
+        quota: &InviteQuota,
+        max_invites: u32,
+    ) -> Result<bool, RepositoryError> {
+        self.increment_quota(&quota.db_item, "sent", quota.expires_at, max_invites)
+            .await
+    }
+
+    async fn delete_invite_quotas(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete_prefix(&keys::user_key(user_sub), keys::INVITE_QUOTA_PREFIX)
+            .await
+    }
+
+    async fn delete_invitation(
+        &self,
+        email: &str,
+        inviter_sub: &str,
+    ) -> Result<(), RepositoryError> {
+        self.delete(keys::invite_key(email), keys::user_key(inviter_sub))
+            .await
+    }
+
+    async fn get_legacy_import(
+        &self,
+        legacy_id: &str,
+    ) -> Result<Option<LegacyImport>, RepositoryError> {
+        self.get(
+            keys::legacy_user_key(legacy_id),
+            keys::LEGACY_IMPORT_KEY.to_owned(),
+        )
+        .await
+    }
+
+    async fn save_legacy_import(&self, import: &LegacyImport) -> Result<(), RepositoryError> {
+        self.put(import).await
+    }
+
+    async fn find_legacy_import(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<LegacyImport>, RepositoryError> {
+        let imports: Vec<LegacyImport> = self
+            .query_prefix(
+                Some(("GSI1", "gsi1PK", "gsi1SK")),
+                &keys::legacy_imports_key(user_sub),
+                keys::LEGACY_USER_PREFIX,
+            )
+            .await?;
+
+        Ok(imports.into_iter().next())
+    }
+
+    async fn delete_legacy_import(&self, legacy_id: &str) -> Result<(), RepositoryError> {
+        self.delete(
+            keys::legacy_user_key(legacy_id),
+            keys::LEGACY_IMPORT_KEY.to_owned(),
+        )
+        .await
+    }
+
+    async fn save_export_job(&self, job: &ExportJob) -> Result<(), RepositoryError> {
+        self.put(job).await
+    }
+
+    async fn list_export_jobs(&self, user_sub: &str) -> Result<Vec<ExportJob>, RepositoryError> {
+        self.query_prefix(None, &keys::user_key(user_sub), keys::EXPORT_PREFIX)
+            .await
+    }
+
+    async fn delete_export_job(&self, job: &ExportJob) -> Result<(), RepositoryError> {
+        self.delete(
+            job.db_item.partition_key.to_owned(),
+            job.db_item.sort_key.to_owned(),
+        )
+        .await
+    }
+
+    async fn get_connection(&self, user_sub: &str) -> Result<Option<Connection>, RepositoryError> {
+        self.get(keys::user_key(user_sub), keys::CONNECTION_KEY.to_owned())
+            .await
+    }
+
+    async fn save_connection(&self, connection: &Connection) -> Result<(), RepositoryError> {
+        self.put(connection).await
+    }
+
+    async fn delete_connection(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete(keys::user_key(user_sub), keys::CONNECTION_KEY.to_owned())
+            .await
+    }
+
+    async fn save_block(&self, block: &Block) -> Result<(), RepositoryError> {
+        self.put(block).await
+    }
+
+    async fn list_blocked_by(&self, user_sub: &str) -> Result<Vec<String>, RepositoryError> {
+        let blocks: Vec<Block> = self
+            .query_prefix(None, &keys::user_key(user_sub), keys::BLOCKED_BY_PREFIX)
+            .await?;
+
+        Ok(blocks.into_iter().map(|block| block.blocker_sub).collect())
+    }
+
+    async fn get_deletion_progress(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<DeletionProgress>, RepositoryError> {
+        self.get(keys::user_key(user_sub), keys::DELETION_KEY.to_owned())
+            .await
+    }
+
+    async fn save_deletion_progress(
+        &self,
+        progress: &DeletionProgress,
+    ) -> Result<(), RepositoryError> {
+        self.put(progress).await
+    }
+}
@@ -0,0 +1,859 @@
+/*---------- Imports ----------*/
+use super::{decode_cursor, encode_cursor, ChatListQuery, ChatRepository, Page, RepositoryError};
+use crate::models::{
+    block::Block,
+    chat::{ChatItem, ChatSettings, ChatView, Message},
+    connection::Connection,
+    deletion::DeletionProgress,
+    export::ExportJob,
+    invite::{Invitation, InviteQuota, QueuedMessage},
+    keys,
+    legacy::LegacyImport,
+    otp::{LoginCode, LoginCodeQuota},
+    signup::{EmailOwner, SignupPolicy},
+    user::{AvatarSource, ProfileUpdate, User, UserProfile},
+};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+/*---------- Structs ----------*/
+/// Table stand-in keyed by `(partitionKey, sortKey)`, so handlers can be
+/// exercised without AWS. Items are kept in their serialized form, which
+/// means the model's serde attributes are applied just like on DynamoDB.
+#[derive(Default)]
+pub struct InMemoryChatRepository {
+    items: Mutex<BTreeMap<(String, String), Value>>,
+}
+
+impl InMemoryChatRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every stored item, in key order. Useful for asserting on writes.
+    pub fn items(&self) -> Vec<Value> {
+        self.items.lock().unwrap().values().cloned().collect()
+    }
+
+    fn put<T: Serialize>(
+        &self,
+        partition_key: &str,
+        sort_key: &str,
+        value: &T,
+    ) -> Result<(), RepositoryError> {
+        let item = serde_json::to_value(value)
+            .map_err(|error| RepositoryError::InvalidItem(error.to_string()))?;
+
+        self.items
+            .lock()
+            .unwrap()
+            .insert((partition_key.to_owned(), sort_key.to_owned()), item);
+
+        Ok(())
+    }
+
+    fn get<T: DeserializeOwned>(
+        &self,
+        partition_key: &str,
+        sort_key: &str,
+    ) -> Result<Option<T>, RepositoryError> {
+        let items = self.items.lock().unwrap();
+
+        match items.get(&(partition_key.to_owned(), sort_key.to_owned())) {
+            Some(item) => serde_json::from_value(item.to_owned())
+                .map(Some)
+                .map_err(|error| RepositoryError::InvalidItem(error.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn delete(&self, partition_key: &str, sort_key: &str) {
+        self.items
+            .lock()
+            .unwrap()
+            .remove(&(partition_key.to_owned(), sort_key.to_owned()));
+    }
+
+    /// Removes every item of the partition whose sort key starts with
+    /// `sort_key_prefix`.
+    fn delete_prefix(&self, partition_key: &str, sort_key_prefix: &str) {
+        self.items
+            .lock()
+            .unwrap()
+            .retain(|(item_partition_key, item_sort_key), _| {
+                item_partition_key != partition_key || !item_sort_key.starts_with(sort_key_prefix)
+            });
+    }
+
+    fn filter<T: DeserializeOwned>(
+        &self,
+        predicate: impl Fn(&Value) -> bool,
+    ) -> Result<Vec<T>, RepositoryError> {
+        let items = self.items.lock().unwrap();
+
+        items
+            .values()
+            .filter(|item| predicate(item))
+            .map(|item| {
+                serde_json::from_value(item.to_owned())
+                    .map_err(|error| RepositoryError::InvalidItem(error.to_string()))
+            })
+            .collect()
+    }
+}
+
+fn string_field<'a>(item: &'a Value, field: &str) -> &'a str {
+    item.get(field).and_then(Value::as_str).unwrap_or_default()
+}
+
+#[async_trait]
+impl ChatRepository for InMemoryChatRepository {
+    async fn save_message(&self, message: &Message) -> Result<(), RepositoryError> {
+        self.put(
+            &message.db_item.partition_key,
+            &message.db_item.sort_key,
+            message,
+        )
+    }
+
+    async fn save_messages(&self, messages: &[Message]) -> Result<(), RepositoryError> {
+        for message in messages {
+            self.save_message(message).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn list_private_messages(
+        &self,
+        first_sub: &str,
+        second_sub: &str,
+    ) -> Result<Vec<Message>, RepositoryError> {
+        let partition_key = keys::private_chat_key(first_sub, second_sub);
+
+        self.filter(|item| {
+            string_field(item, "partitionKey") == partition_key
+                && string_field(item, "sortKey").starts_with(keys::MESSAGE_PREFIX)
+        })
+    }
+
+    async fn list_chat_history(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+    ) -> Result<Vec<Message>, RepositoryError> {
+        let cleared_before = self
+            .get_private_chat(owner_sub, other_sub)
+            .await?
+            .and_then(|chat| chat.cleared_before);
+        let partition_key = keys::private_chat_key(owner_sub, other_sub);
+        let lower_bound = keys::message_key(cleared_before.as_deref().unwrap_or(""));
+
+        self.filter(|item| {
+            string_field(item, "partitionKey") == partition_key
+                && string_field(item, "sortKey").starts_with(keys::MESSAGE_PREFIX)
+                && string_field(item, "sortKey") > lower_bound.as_str()
+        })
+    }
+
+    async fn list_chats(
+        &self,
+        user_sub: &str,
+        query: &ChatListQuery,
+    ) -> Result<Page<ChatItem>, RepositoryError> {
+        let partition_key = keys::user_key(user_sub);
+        let lower_bound = keys::chat_timestamp_key(query.updated_since.as_deref().unwrap_or(""));
+
+        let mut chats: Vec<ChatItem> = self.filter(|item| {
+            string_field(item, "gsi2PK") == partition_key
+                && string_field(item, "gsi2SK").starts_with(keys::CHAT_TIMESTAMP_PREFIX)
+                && string_field(item, "gsi2SK") >= lower_bound.as_str()
+        })?;
+
+        chats.sort_by(|a, b| {
+            (&b.db_item.gsi2_sk, &b.db_item.sort_key)
+                .cmp(&(&a.db_item.gsi2_sk, &a.db_item.sort_key))
+        });
+
+        if query.view == ChatView::Pinned {
+            chats.retain(|chat| query.matches(chat));
+
+            return Ok(Page {
+                items: chats,
+                next_cursor: None,
+            });
+        }
+
+        let mut items: Vec<ChatItem> = match query.pinned() {
+            Some(pinned_query) => chats
+                .iter()
+                .filter(|chat| pinned_query.matches(chat))
+                .cloned()
+                .collect(),
+            None => vec![],
+        };
+
+        if let Some(cursor) = &query.cursor {
+            let start_key = decode_cursor(cursor)?;
+            let position = chats
+                .iter()
+                .position(|chat| Some(&chat.db_item.sort_key) == start_key.get("sortKey"))
+                .ok_or(RepositoryError::InvalidCursor)?;
+
+            chats.drain(..=position);
+        }
+
+        let limit = query
+            .limit
+            .map(|limit| limit as usize)
+            .unwrap_or(chats.len());
+        let has_more = chats.len() > limit;
+
+        chats.truncate(limit);
+
+        let next_cursor = match (has_more, chats.last()) {
+            (true, Some(last_chat)) => Some(encode_cursor(&HashMap::from([
+                (
+                    "partitionKey".to_owned(),
+                    last_chat.db_item.partition_key.to_owned(),
+                ),
+                ("sortKey".to_owned(), last_chat.db_item.sort_key.to_owned()),
+            ]))),
+            _ => None,
+        };
+
+        items.extend(chats.into_iter().filter(|chat| query.matches(chat)));
+
+        Ok(Page { items, next_cursor })
+    }
+
+    async fn get_private_chat(
+        &self,
+        user_sub: &str,
+        other_sub: &str,
+    ) -> Result<Option<ChatItem>, RepositoryError> {
+        self.get(
+            &keys::user_key(user_sub),
+            &keys::chat_with_user_key(other_sub),
+        )
+    }
+
+    async fn upsert_chat_summary(&self, chat: &ChatItem) -> Result<(), RepositoryError> {
+        let mut updated_chat = chat.clone();
+
+        if let Some(existing_chat) =
+            self.get::<ChatItem>(&chat.db_item.partition_key, &chat.db_item.sort_key)?
+        {
+            updated_chat.settings = existing_chat.settings;
+            updated_chat.cleared_before = existing_chat.cleared_before;
+            updated_chat.db_item.gsi1_pk = existing_chat.db_item.gsi1_pk;
+            updated_chat.db_item.gsi1_sk = existing_chat.db_item.gsi1_sk;
+        }
+
+        self.put(
+            &chat.db_item.partition_key,
+            &chat.db_item.sort_key,
+            &updated_chat,
+        )
+    }
+
+    async fn update_chat_settings(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        settings: &ChatSettings,
+    ) -> Result<(), RepositoryError> {
+        let mut chat: ChatItem = match self.get_private_chat(owner_sub, other_sub).await? {
+            Some(chat) => chat,
+            None => return Ok(()),
+        };
+
+        chat.settings = settings.clone();
+
+        if settings.pinned {
+            chat.db_item.gsi1_pk = Some(keys::pinned_chats_key(owner_sub));
+            chat.db_item.gsi1_sk = Some(keys::chat_with_user_key(other_sub));
+        } else {
+            chat.db_item.gsi1_pk = None;
+            chat.db_item.gsi1_sk = None;
+        }
+
+        self.put(&chat.db_item.partition_key, &chat.db_item.sort_key, &chat)
+    }
+
+    async fn clear_chat_history(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        cleared_before: &str,
+        hide: bool,
+    ) -> Result<(), RepositoryError> {
+        let mut chat: ChatItem = match self.get_private_chat(owner_sub, other_sub).await? {
+            Some(chat) => chat,
+            None => return Ok(()),
+        };
+
+        chat.cleared_before = Some(cleared_before.to_owned());
+        chat.unread_messages = 0;
+        chat.last_message.preview = String::new();
+
+        if hide {
+            chat.hidden = true;
+        }
+
+        self.put(&chat.db_item.partition_key, &chat.db_item.sort_key, &chat)
+    }
+
+    async fn replace_chat_participant(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        replacement: &User,
+    ) -> Result<(), RepositoryError> {
+        let mut chat: ChatItem = match self.get_private_chat(owner_sub, other_sub).await? {
+            Some(chat) => chat,
+            None => return Ok(()),
+        };
+
+        chat.title = replacement.name.to_owned();
+
+        if chat.last_message.user_sub == other_sub {
+            chat.last_message.user_name = replacement.name.to_owned();
+            chat.last_message.user_sub = replacement.sub.to_owned();
+        }
+
+        self.put(&chat.db_item.partition_key, &chat.db_item.sort_key, &chat)
+    }
+
+    async fn delete_chat_summary(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+    ) -> Result<(), RepositoryError> {
+        self.delete(
+            &keys::user_key(owner_sub),
+            &keys::chat_with_user_key(other_sub),
+        );
+
+        Ok(())
+    }
+
+    async fn save_profile_update(&self, update: &ProfileUpdate) -> Result<(), RepositoryError> {
+        self.put(
+            &update.db_item.partition_key,
+            &update.db_item.sort_key,
+            update,
+        )
+    }
+
+    async fn delete_profile_updates(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete_prefix(&keys::user_key(user_sub), keys::PROFILE_UPDATE_PREFIX);
+
+        Ok(())
+    }
+
+    async fn update_chat_profile(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        profile: &User,
+    ) -> Result<(), RepositoryError> {
+        let mut chat: ChatItem = match self.get_private_chat(owner_sub, other_sub).await? {
+            Some(chat) => chat,
+            None => return Ok(()),
+        };
+
+        if profile.sub == owner_sub {
+            chat.user = profile.clone();
+        } else {
+            chat.title = profile.name.to_owned();
+        }
+
+        if chat.last_message.user_sub == profile.sub {
+            chat.last_message.user_name = profile.name.to_owned();
+        }
+
+        self.upsert_chat_summary(&chat).await
+    }
+
+    async fn get_profile(&self, user_sub: &str) -> Result<Option<UserProfile>, RepositoryError> {
+        self.get(&keys::user_key(user_sub), keys::PROFILE_KEY)
+    }
+
+    async fn create_profile(&self, profile: &UserProfile) -> Result<bool, RepositoryError> {
+        if self.get_profile(&profile.sub).await?.is_some() {
+            return Ok(false);
+        }
+
+        self.put(
+            &profile.db_item.partition_key,
+            &profile.db_item.sort_key,
+            profile,
+        )?;
+
+        Ok(true)
+    }
+
+    async fn update_profile(&self, user: &User) -> Result<(), RepositoryError> {
+        let mut profile = match self.get_profile(&user.sub).await? {
+            Some(profile) => profile,
+            None => return Ok(()),
+        };
+
+        profile.name = user.name.to_owned();
+        profile.email = user.email.to_owned();
+        profile.version += 1;
+
+        self.put(
+            &profile.db_item.partition_key,
+            &profile.db_item.sort_key,
+            &profile,
+        )
+    }
+
+    async fn set_avatar_source(
+        &self,
+        user_sub: &str,
+        source: AvatarSource,
+    ) -> Result<(), RepositoryError> {
+        let mut profile = match self.get_profile(user_sub).await? {
+            Some(profile) => profile,
+            None => return Ok(()),
+        };
+
+        profile.avatar_source = source;
+
+        self.put(
+            &profile.db_item.partition_key,
+            &profile.db_item.sort_key,
+            &profile,
+        )
+    }
+
+    async fn delete_profile(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete(&keys::user_key(user_sub), keys::PROFILE_KEY);
+
+        Ok(())
+    }
+
+    async fn get_signup_policy(&self) -> Result<Option<SignupPolicy>, RepositoryError> {
+        self.get(keys::CONFIG_KEY, keys::SIGNUP_POLICY_KEY)
+    }
+
+    async fn get_email_owner(&self, email: &str) -> Result<Option<EmailOwner>, RepositoryError> {
+        self.get(&keys::email_key(email), keys::EMAIL_OWNER_KEY)
+    }
+
+    async fn claim_email(&self, owner: &EmailOwner) -> Result<bool, RepositoryError> {
+        if self.get_email_owner(&owner.email).await?.is_some() {
+            return Ok(false);
+        }
+
+        self.put(&owner.db_item.partition_key, &owner.db_item.sort_key, owner)?;
+
+        Ok(true)
+    }
+
+    async fn release_email(&self, email: &str, user_sub: &str) -> Result<(), RepositoryError> {
+        if let Some(owner) = self.get_email_owner(email).await? {
+            if owner.sub == user_sub {
+                self.delete(&owner.db_item.partition_key, &owner.db_item.sort_key);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn save_login_code(&self, login_code: &LoginCode) -> Result<(), RepositoryError> {
+        self.put(
+            &login_code.db_item.partition_key,
+            &login_code.db_item.sort_key,
+            login_code,
+        )
+    }
+
+    async fn get_login_code(&self, user_sub: &str) -> Result<Option<LoginCode>, RepositoryError> {
+        self.get(&keys::user_key(user_sub), keys::LOGIN_CODE_KEY)
+    }
+
+    async fn record_login_code_attempt(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<u32>, RepositoryError> {
+        let mut login_code = match self.get_login_code(user_sub).await? {
+            Some(login_code) => login_code,
+            None => return Ok(None),
+        };
+
+        login_code.attempts += 1;
+        self.save_login_code(&login_code).await?;
+
+        Ok(Some(login_code.attempts))
+    }
+
+    async fn delete_login_code(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete(&keys::user_key(user_sub), keys::LOGIN_CODE_KEY);
+
+        Ok(())
+    }
+
+    async fn record_login_code_issue(
+        &self,
+        quota: &LoginCodeQuota,
+        max_codes: u32,
+    ) -> Result<bool, RepositoryError> {
+        let mut updated_quota = match self
+            .get::<LoginCodeQuota>(&quota.db_item.partition_key, &quota.db_item.sort_key)?
+        {
+            Some(stored_quota) => stored_quota,
+            None => quota.clone(),
+        };
+
+        if updated_quota.issued >= max_codes {
+            return Ok(false);
+        }
+
+        updated_quota.issued += 1;
+        updated_quota.expires_at = quota.expires_at;
+
+        self.put(
+            &updated_quota.db_item.partition_key,
+            &updated_quota.db_item.sort_key,
+            &updated_quota,
+        )?;
+
+        Ok(true)
+    }
+
+    async fn delete_login_code_quotas(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete_prefix(&keys::user_key(user_sub), keys::LOGIN_QUOTA_PREFIX);
+
+        Ok(())
+    }
+
+    async fn list_invitations(&self, email: &str) -> Result<Vec<Invitation>, RepositoryError> {
+        let partition_key = keys::invite_key(email);
+
+        self.filter(|item| {
+            string_field(item, "partitionKey") == partition_key
+                && string_field(item, "sortKey").starts_with(keys::USER_PREFIX)
+        })
+    }
+
+    async fn list_sent_invitations(
+        &self,
+        inviter_sub: &str,
+    ) -> Result<Vec<Invitation>, RepositoryError> {
+        let partition_key = keys::sent_invites_key(inviter_sub);
+
+        self.filter(|item| {
+            string_field(item, "gsi1PK") == partition_key
+                && string_field(item, "gsi1SK").starts_with(keys::INVITE_PREFIX)
+        })
+    }
+
+    async fn queue_invitation_message(
+        &self,
+        invitation: &Invitation,
+        message: &QueuedMessage,
+        max_messages: usize,
+    ) -> Result<Option<Invitation>, RepositoryError> {
+        let stored_invitation: Option<Invitation> = self.get(
+            &invitation.db_item.partition_key,
+            &invitation.db_item.sort_key,
+        )?;
+
+        let mut updated_invitation = invitation.clone();
+
+        if let Some(stored_invitation) = stored_invitation {
+            updated_invitation.invited_at = stored_invitation.invited_at;
+            updated_invitation.messages = stored_invitation.messages;
+        }
+
+        if updated_invitation.messages.len() >= max_messages {
+            return Ok(None);
+        }
+
+        updated_invitation.messages.push(message.clone());
+
+        self.put(
+            &updated_invitation.db_item.partition_key,
+            &updated_invitation.db_item.sort_key,
+            &updated_invitation,
+        )?;
+
+        Ok(Some(updated_invitation))
+    }
+
+    async fn record_invite(
+        &self,
+        quota: &InviteQuota,
+        max_invites: u32,
+    ) -> Result<bool, RepositoryError> {
+        let mut updated_quota =
+            match self.get::<InviteQuota>(&quota.db_item.partition_key, &quota.db_item.sort_key)? {
+                Some(stored_quota) => stored_quota,
+                None => quota.clone(),
+            };
+
+        if updated_quota.sent >= max_invites {
+            return Ok(false);
+        }
+
+        updated_quota.sent += 1;
+        updated_quota.expires_at = quota.expires_at;
+
+        self.put(
+            &updated_quota.db_item.partition_key,
+            &updated_quota.db_item.sort_key,
+            &updated_quota,
+        )?;
+
+        Ok(true)
+    }
+
+    async fn delete_invite_quotas(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete_prefix(&keys::user_key(user_sub), keys::INVITE_QUOTA_PREFIX);
+
+        Ok(())
+    }
+
+    async fn delete_invitation(
+        &self,
+        email: &str,
+        inviter_sub: &str,
+    ) -> Result<(), RepositoryError> {
+        self.delete(&keys::invite_key(email), &keys::user_key(inviter_sub));
+
+        Ok(())
+    }
+
+    async fn get_legacy_import(
+        &self,
+        legacy_id: &str,
+    ) -> Result<Option<LegacyImport>, RepositoryError> {
+        self.get(&keys::legacy_user_key(legacy_id), keys::LEGACY_IMPORT_KEY)
+    }
+
+    async fn save_legacy_import(&self, import: &LegacyImport) -> Result<(), RepositoryError> {
+        self.put(
+            &import.db_item.partition_key,
+            &import.db_item.sort_key,
+            import,
+        )
+    }
+
+    async fn find_legacy_import(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<LegacyImport>, RepositoryError> {
+        let partition_key = keys::legacy_imports_key(user_sub);
+        let imports: Vec<LegacyImport> =
+            self.filter(|item| string_field(item, "gsi1PK") == partition_key)?;
+
+        Ok(imports.into_iter().next())
+    }
+
+    async fn delete_legacy_import(&self, legacy_id: &str) -> Result<(), RepositoryError> {
+        self.delete(&keys::legacy_user_key(legacy_id), keys::LEGACY_IMPORT_KEY);
+
+        Ok(())
+    }
+
+    async fn save_export_job(&self, job: &ExportJob) -> Result<(), RepositoryError> {
+        self.put(&job.db_item.partition_key, &job.db_item.sort_key, job)
+    }
+
+    async fn list_export_jobs(&self, user_sub: &str) -> Result<Vec<ExportJob>, RepositoryError> {
+        let partition_key = keys::user_key(user_sub);
+
+        self.filter(|item| {
+            string_field(item, "partitionKey") == partition_key
+                && string_field(item, "sortKey").starts_with(keys::EXPORT_PREFIX)
+        })
+    }
+
+    async fn delete_export_job(&self, job: &ExportJob) -> Result<(), RepositoryError> {
+        self.delete(&job.db_item.partition_key, &job.db_item.sort_key);
+
+        Ok(())
+    }
+
+    async fn get_connection(&self, user_sub: &str) -> Result<Option<Connection>, RepositoryError> {
+        self.get(&keys::user_key(user_sub), keys::CONNECTION_KEY)
+    }
+
+    async fn save_connection(&self, connection: &Connection) -> Result<(), RepositoryError> {
+        self.put(
+            &connection.db_item.partition_key,
+            &connection.db_item.sort_key,
+            connection,
+        )
+    }
+
+    async fn delete_connection(&self, user_sub: &str) -> Result<(), RepositoryError> {
+        self.delete(&keys::user_key(user_sub), keys::CONNECTION_KEY);
+
+        Ok(())
+    }
+
+    async fn save_block(&self, block: &Block) -> Result<(), RepositoryError> {
+        self.put(&block.db_item.partition_key, &block.db_item.sort_key, block)
+    }
+
+    async fn list_blocked_by(&self, user_sub: &str) -> Result<Vec<String>, RepositoryError> {
+        let partition_key = keys::user_key(user_sub);
+        let blocks: Vec<Block> = self.filter(|item| {
+            string_field(item, "partitionKey") == partition_key
+                && string_field(item, "sortKey").starts_with(keys::BLOCKED_BY_PREFIX)
+        })?;
+
+        Ok(blocks.into_iter().map(|block| block.blocker_sub).collect())
+    }
+
+    async fn get_deletion_progress(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<DeletionProgress>, RepositoryError> {
+        self.get(&keys::user_key(user_sub), keys::DELETION_KEY)
+    }
+
+    async fn save_deletion_progress(
+        &self,
+        progress: &DeletionProgress,
+    ) -> Result<(), RepositoryError> {
+        self.put(
+            &progress.db_item.partition_key,
+            &progress.db_item.sort_key,
+            progress,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::chat::MessageType;
+
+    const OWNER_SUB: &str = "user-1";
+    const OTHER_SUB: &str = "user-2";
+
+    /// A message from the other participant, with a ULID-like id that sorts
+    /// in the order they're sent.
+    async fn receive(repository: &InMemoryChatRepository, message_id: &str) -> Message {
+        let owner = User::new(OWNER_SUB, "Jane", "");
+        let other = User::new(OTHER_SUB, "John", "");
+        let message = Message::private(
+            &other,
+            OWNER_SUB,
+            message_id,
+            "2023-01-01T00:00:00.000Z",
+            message_id,
+            MessageType::Text,
+        );
+
+        repository.save_message(&message).await.unwrap();
+
+        for (chat_owner, chat_other) in [(&owner, &other), (&other, &owner)] {
+            repository
+                .upsert_chat_summary(&ChatItem::private(chat_owner, chat_other, &message))
+                .await
+                .unwrap();
+        }
+
+        message
+    }
+
+    async fn history_ids(
+        repository: &InMemoryChatRepository,
+        owner_sub: &str,
+        other_sub: &str,
+    ) -> Vec<String> {
+        repository
+            .list_chat_history(owner_sub, other_sub)
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|message| message.message_id().map(|id| id.to_owned()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn history_starts_after_the_cleared_watermark() {
+        let repository = InMemoryChatRepository::new();
+
+        receive(&repository, "01A").await;
+        receive(&repository, "01B").await;
+        repository
+            .clear_chat_history(OWNER_SUB, OTHER_SUB, "01C", false)
+            .await
+            .unwrap();
+        receive(&repository, "01D").await;
+
+        assert_eq!(
+            history_ids(&repository, OWNER_SUB, OTHER_SUB).await,
+            vec!["01D"]
+        );
+        assert_eq!(
+            history_ids(&repository, OTHER_SUB, OWNER_SUB).await,
+            vec!["01A", "01B", "01D"]
+        );
+    }
+
+    #[tokio::test]
+    async fn history_is_whole_until_cleared() {
+        let repository = InMemoryChatRepository::new();
+
+        receive(&repository, "01A").await;
+        receive(&repository, "01B").await;
+
+        assert_eq!(
+            history_ids(&repository, OWNER_SUB, OTHER_SUB).await,
+            vec!["01A", "01B"]
+        );
+    }
+
+    #[tokio::test]
+    async fn clearing_blanks_the_owner_preview_only() {
+        let repository = InMemoryChatRepository::new();
+
+        receive(&repository, "01A").await;
+        repository
+            .clear_chat_history(OWNER_SUB, OTHER_SUB, "01B", true)
+            .await
+            .unwrap();
+
+        let cleared = repository
+            .get_private_chat(OWNER_SUB, OTHER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+        let untouched = repository
+            .get_private_chat(OTHER_SUB, OWNER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(cleared.last_message.preview, "");
+        assert_eq!(cleared.cleared_before.as_deref(), Some("01B"));
+        assert!(cleared.hidden);
+        assert_eq!(untouched.last_message.preview, "01A");
+
+        // The next message brings the preview back
+        receive(&repository, "01C").await;
+
+        let refreshed = repository
+            .get_private_chat(OWNER_SUB, OTHER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(refreshed.last_message.preview, "01C");
+        assert!(!refreshed.hidden);
+    }
+}
@@ -0,0 +1,355 @@
+/*---------- Imports ----------*/
+use crate::models::{
+    block::Block,
+    chat::{ChatItem, ChatSettings, ChatType, ChatView, Message},
+    connection::Connection,
+    deletion::DeletionProgress,
+    export::ExportJob,
+    invite::{Invitation, InviteQuota, QueuedMessage},
+    legacy::LegacyImport,
+    otp::{LoginCode, LoginCodeQuota},
+    signup::{EmailOwner, SignupPolicy},
+    user::{AvatarSource, ProfileUpdate, User, UserProfile},
+};
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine};
+use std::collections::HashMap;
+
+pub mod dynamodb;
+pub mod memory;
+
+/*---------- Enums ----------*/
+#[derive(Debug)]
+pub enum RepositoryError {
+    RequestFailed(String),
+    InvalidItem(String),
+    InvalidCursor,
+}
+
+impl std::fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepositoryError::RequestFailed(message) => {
+                write!(f, "Database request failed: {}", message)
+            }
+            RepositoryError::InvalidItem(message) => {
+                write!(f, "Database item is invalid: {}", message)
+            }
+            RepositoryError::InvalidCursor => write!(f, "Pagination cursor is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {}
+
+/*---------- Structs ----------*/
+#[derive(Debug, Default, Clone)]
+pub struct ChatListQuery {
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+    pub chat_type: Option<ChatType>,
+    pub unread_only: bool,
+    /// RFC 3339 timestamp; only chats with activity at or after it are listed.
+    pub updated_since: Option<String>,
+    pub view: ChatView,
+}
+
+impl ChatListQuery {
+    /// Whether `chat` belongs in the listing. The inbox leaves pinned chats
+    /// out because they are fetched separately and put first.
+    pub fn matches(&self, chat: &ChatItem) -> bool {
+        if chat.hidden && self.view != ChatView::All {
+            return false;
+        }
+
+        let in_view = match self.view {
+            ChatView::Inbox => !chat.settings.archived && !chat.settings.pinned,
+            ChatView::Archived => chat.settings.archived,
+            ChatView::Pinned => chat.settings.pinned && !chat.settings.archived,
+            ChatView::All => true,
+        };
+
+        in_view
+            && self
+                .chat_type
+                .is_none_or(|chat_type| chat.chat_type == chat_type)
+            && (!self.unread_only || chat.unread_messages > 0)
+            && self
+                .updated_since
+                .as_ref()
+                .is_none_or(|since| &chat.last_message.timestamp >= since)
+    }
+
+    /// The pinned chats shown ahead of the inbox's first page.
+    pub fn pinned(&self) -> Option<ChatListQuery> {
+        if self.view != ChatView::Inbox || self.cursor.is_some() {
+            return None;
+        }
+
+        Some(ChatListQuery {
+            view: ChatView::Pinned,
+            ..self.clone()
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// Turns the key of the last item read into an opaque token clients can
+/// hand back to continue from there. Every key attribute in the table is a
+/// string, so the key is carried as a plain JSON object.
+pub fn encode_cursor(key: &HashMap<String, String>) -> String {
+    let serialized_key = serde_json::to_vec(key).unwrap_or_default();
+
+    general_purpose::URL_SAFE_NO_PAD.encode(serialized_key)
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<HashMap<String, String>, RepositoryError> {
+    let serialized_key = general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|_| RepositoryError::InvalidCursor)?;
+
+    serde_json::from_slice(&serialized_key).map_err(|_| RepositoryError::InvalidCursor)
+}
+
+/*---------- Traits ----------*/
+/// Typed access to the items of the main table.
+#[async_trait]
+pub trait ChatRepository: Send + Sync {
+    async fn save_message(&self, message: &Message) -> Result<(), RepositoryError>;
+
+    /// Stores many messages at once, in as few requests as possible.
+    async fn save_messages(&self, messages: &[Message]) -> Result<(), RepositoryError>;
+
+    /// Messages exchanged between two users, oldest first.
+    async fn list_private_messages(
+        &self,
+        first_sub: &str,
+        second_sub: &str,
+    ) -> Result<Vec<Message>, RepositoryError>;
+
+    /// The private chat history as `owner_sub` sees it, oldest first: only
+    /// the messages sent after the watermark they last cleared it at.
+    async fn list_chat_history(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+    ) -> Result<Vec<Message>, RepositoryError>;
+
+    /// One page of the chat summaries owned by `user_sub`, most recently
+    /// active first. Filters are applied after the page limit, like a
+    /// DynamoDB filter expression, so a page can come back short while still
+    /// carrying a cursor. The first inbox page also starts with every pinned
+    /// chat, which doesn't count towards the limit; the pinned view is never
+    /// paginated.
+    async fn list_chats(
+        &self,
+        user_sub: &str,
+        query: &ChatListQuery,
+    ) -> Result<Page<ChatItem>, RepositoryError>;
+
+    async fn get_private_chat(
+        &self,
+        user_sub: &str,
+        other_sub: &str,
+    ) -> Result<Option<ChatItem>, RepositoryError>;
+
+    /// Creates or refreshes a chat summary and unhides it. The owner's
+    /// settings, the pinned index entry that goes with them, and the cleared
+    /// history watermark are kept as they are.
+    async fn upsert_chat_summary(&self, chat: &ChatItem) -> Result<(), RepositoryError>;
+
+    /// Stores the owner's settings on their copy of a chat. Missing summaries
+    /// are left alone.
+    async fn update_chat_settings(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        settings: &ChatSettings,
+    ) -> Result<(), RepositoryError>;
+
+    /// Clears the owner's history of a chat up to `cleared_before`, blanks
+    /// the preview of the last message, and optionally hides the chat from
+    /// their list. The other participant's copy is untouched. Missing
+    /// summaries are left alone.
+    async fn clear_chat_history(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        cleared_before: &str,
+        hide: bool,
+    ) -> Result<(), RepositoryError>;
+
+    /// Puts `replacement` in place of `other_sub` in the summary `owner_sub`
+    /// keeps of their chat: the title, and the last message author when
+    /// `other_sub` wrote it. Missing summaries are left alone.
+    async fn replace_chat_participant(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        replacement: &User,
+    ) -> Result<(), RepositoryError>;
+
+    async fn delete_chat_summary(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+    ) -> Result<(), RepositoryError>;
+
+    async fn save_profile_update(&self, update: &ProfileUpdate) -> Result<(), RepositoryError>;
+
+    /// Removes every profile update of the user, propagated or not.
+    async fn delete_profile_updates(&self, user_sub: &str) -> Result<(), RepositoryError>;
+
+    /// Rewrites the copies of `profile` held by the summary `owner_sub` keeps
+    /// of their chat with `other_sub`: the `user` field when the profile is
+    /// the owner's, the `title` otherwise, and the last message author name
+    /// when they wrote it. Missing summaries are left alone.
+    async fn update_chat_profile(
+        &self,
+        owner_sub: &str,
+        other_sub: &str,
+        profile: &User,
+    ) -> Result<(), RepositoryError>;
+
+    async fn get_profile(&self, user_sub: &str) -> Result<Option<UserProfile>, RepositoryError>;
+
+    /// Stores the profile unless the user already has one, so replayed
+    /// triggers and repeated backfills are harmless. Returns whether it was
+    /// written.
+    async fn create_profile(&self, profile: &UserProfile) -> Result<bool, RepositoryError>;
+
+    /// Refreshes the name and email of an existing profile item and bumps
+    /// its version. Users without one are left alone.
+    async fn update_profile(&self, user: &User) -> Result<(), RepositoryError>;
+
+    /// Records where the user's current avatar came from. Users without a
+    /// profile item are left alone.
+    async fn set_avatar_source(
+        &self,
+        user_sub: &str,
+        source: AvatarSource,
+    ) -> Result<(), RepositoryError>;
+
+    async fn delete_profile(&self, user_sub: &str) -> Result<(), RepositoryError>;
+
+    async fn get_signup_policy(&self) -> Result<Option<SignupPolicy>, RepositoryError>;
+
+    async fn get_email_owner(&self, email: &str) -> Result<Option<EmailOwner>, RepositoryError>;
+
+    /// Records the owner of a normalized email unless it already has one.
+    /// Returns whether it was written.
+    async fn claim_email(&self, owner: &EmailOwner) -> Result<bool, RepositoryError>;
+
+    /// Removes the owner of a normalized email when it's `user_sub`. An
+    /// address claimed by someone else is left alone.
+    async fn release_email(&self, email: &str, user_sub: &str) -> Result<(), RepositoryError>;
+
+    /// Stores a sign-in code, replacing any previous one of the user.
+    async fn save_login_code(&self, login_code: &LoginCode) -> Result<(), RepositoryError>;
+
+    async fn get_login_code(&self, user_sub: &str) -> Result<Option<LoginCode>, RepositoryError>;
+
+    /// Counts an answer against the user's sign-in code and returns how many
+    /// were given so far, or `None` when there's no code.
+    async fn record_login_code_attempt(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<u32>, RepositoryError>;
+
+    async fn delete_login_code(&self, user_sub: &str) -> Result<(), RepositoryError>;
+
+    /// Counts an emailed sign-in code against `quota`, unless `max_codes`
+    /// were already issued. Returns whether it was counted.
+    async fn record_login_code_issue(
+        &self,
+        quota: &LoginCodeQuota,
+        max_codes: u32,
+    ) -> Result<bool, RepositoryError>;
+
+    /// Removes the user's sign-in code counters of every hour.
+    async fn delete_login_code_quotas(&self, user_sub: &str) -> Result<(), RepositoryError>;
+
+    /// Every pending invitation of a normalized email, whoever sent it.
+    async fn list_invitations(&self, email: &str) -> Result<Vec<Invitation>, RepositoryError>;
+
+    /// Every pending invitation sent by the user, whoever it's for.
+    async fn list_sent_invitations(
+        &self,
+        inviter_sub: &str,
+    ) -> Result<Vec<Invitation>, RepositoryError>;
+
+    /// Appends `message` to `invitation`, creating it when it isn't stored
+    /// yet, unless `max_messages` are already queued. Returns the invitation
+    /// as stored, or `None` when it's full.
+    async fn queue_invitation_message(
+        &self,
+        invitation: &Invitation,
+        message: &QueuedMessage,
+        max_messages: usize,
+    ) -> Result<Option<Invitation>, RepositoryError>;
+
+    /// Counts an invite against `quota`, unless `max_invites` were already
+    /// sent. Returns whether it was counted.
+    async fn record_invite(
+        &self,
+        quota: &InviteQuota,
+        max_invites: u32,
+    ) -> Result<bool, RepositoryError>;
+
+    /// Removes the user's invite counters of every day.
+    async fn delete_invite_quotas(&self, user_sub: &str) -> Result<(), RepositoryError>;
+
+    async fn delete_invitation(
+        &self,
+        email: &str,
+        inviter_sub: &str,
+    ) -> Result<(), RepositoryError>;
+
+    async fn get_legacy_import(
+        &self,
+        legacy_id: &str,
+    ) -> Result<Option<LegacyImport>, RepositoryError>;
+
+    async fn save_legacy_import(&self, import: &LegacyImport) -> Result<(), RepositoryError>;
+
+    /// The legacy link of the account `user_sub` was migrated to, if any.
+    async fn find_legacy_import(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<LegacyImport>, RepositoryError>;
+
+    async fn delete_legacy_import(&self, legacy_id: &str) -> Result<(), RepositoryError>;
+
+    /// Stores a data export job, replacing the previous state of the same job.
+    async fn save_export_job(&self, job: &ExportJob) -> Result<(), RepositoryError>;
+
+    async fn list_export_jobs(&self, user_sub: &str) -> Result<Vec<ExportJob>, RepositoryError>;
+
+    async fn delete_export_job(&self, job: &ExportJob) -> Result<(), RepositoryError>;
+
+    async fn get_connection(&self, user_sub: &str) -> Result<Option<Connection>, RepositoryError>;
+
+    async fn save_connection(&self, connection: &Connection) -> Result<(), RepositoryError>;
+
+    async fn delete_connection(&self, user_sub: &str) -> Result<(), RepositoryError>;
+
+    async fn save_block(&self, block: &Block) -> Result<(), RepositoryError>;
+
+    /// Subs of the users who have blocked `user_sub`.
+    async fn list_blocked_by(&self, user_sub: &str) -> Result<Vec<String>, RepositoryError>;
+
+    async fn get_deletion_progress(
+        &self,
+        user_sub: &str,
+    ) -> Result<Option<DeletionProgress>, RepositoryError>;
+
+    async fn save_deletion_progress(
+        &self,
+        progress: &DeletionProgress,
+    ) -> Result<(), RepositoryError>;
+}
@@ -0,0 +1,64 @@
+/*---------- Imports ----------*/
+use super::{ObjectStore, StorageError};
+use async_trait::async_trait;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/*---------- Structs ----------*/
+/// Bucket stand-in keyed by `(bucket, key)`. It can be made unavailable on
+/// demand to exercise the failure paths.
+#[derive(Default)]
+pub struct InMemoryObjectStore {
+    objects: Mutex<BTreeMap<(String, String), Vec<u8>>>,
+    unavailable: AtomicBool,
+}
+
+impl InMemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&self, bucket: &str, key: &str, body: &[u8]) {
+        self.objects
+            .lock()
+            .unwrap()
+            .insert((bucket.to_owned(), key.to_owned()), body.to_vec());
+    }
+
+    /// Every key stored in `bucket`, in order.
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        self.objects
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(object_bucket, _)| object_bucket == bucket)
+            .map(|(_, key)| key.to_owned())
+            .collect()
+    }
+
+    /// While set, every request fails.
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl ObjectStore for InMemoryObjectStore {
+    async fn delete(&self, bucket: &str, key: &str) -> Result<(), StorageError> {
+        if self.unavailable.load(Ordering::SeqCst) {
+            return Err(StorageError::RequestFailed("unavailable".to_owned()));
+        }
+
+        self.objects
+            .lock()
+            .unwrap()
+            .remove(&(bucket.to_owned(), key.to_owned()));
+
+        Ok(())
+    }
+}
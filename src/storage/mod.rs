@@ -0,0 +1,31 @@
+/*---------- Imports ----------*/
+use async_trait::async_trait;
+
+pub mod memory;
+pub mod s3;
+
+/*---------- Enums ----------*/
+#[derive(Debug)]
+pub enum StorageError {
+    RequestFailed(String),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::RequestFailed(message) => {
+                write!(f, "The storage request failed: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/*---------- Traits ----------*/
+/// Files kept in the app's buckets, such as avatars and export archives.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Removes an object. Deleting one that doesn't exist succeeds.
+    async fn delete(&self, bucket: &str, key: &str) -> Result<(), StorageError>;
+}
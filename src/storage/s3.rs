@@ -0,0 +1,29 @@
+/*---------- Imports ----------*/
+use super::{ObjectStore, StorageError};
+use async_trait::async_trait;
+
+/*---------- Structs ----------*/
+pub struct S3ObjectStore {
+    client: aws_sdk_s3::Client,
+}
+
+impl S3ObjectStore {
+    pub fn new(client: aws_sdk_s3::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3ObjectStore {
+    async fn delete(&self, bucket: &str, key: &str) -> Result<(), StorageError> {
+        self.client
+            .delete_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|error| StorageError::RequestFailed(error.to_string()))?;
+
+        Ok(())
+    }
+}
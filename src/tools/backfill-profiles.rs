@@ -0,0 +1,73 @@
+/*---------- Imports ----------*/
+use chat_test_infra::{
+    models::{signup::EmailOwner, user::UserProfile},
+    repository::{dynamodb::DynamoChatRepository, ChatRepository},
+    utils::{avatar::Avatar, signup::Signup, user::User},
+};
+use std::{env, process};
+
+/// Creates the `user#<sub>` / `profile` item of every Cognito user missing
+/// one, and claims their normalized email for them. Existing items are left
+/// untouched, so it can be run again safely.
+#[tokio::main]
+async fn main() {
+    let config = aws_config::load_from_env().await;
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let userpool_id = env::var("USERPOOL_ID").expect("USERPOOL_ID must be set");
+    let public_bucket_domain =
+        env::var("PUBLIC_BUCKET_DOMAIN").expect("PUBLIC_BUCKET_DOMAIN must be set");
+    let cognito_client = aws_sdk_cognitoidentityprovider::Client::new(&config);
+    let repository = DynamoChatRepository::new(aws_sdk_dynamodb::Client::new(&config), &table_name);
+
+    let mut pagination_token: Option<String> = None;
+    let mut created_count = 0;
+    let mut skipped_count = 0;
+
+    loop {
+        let (users, next_token) =
+            match User::list_users(&cognito_client, &userpool_id, pagination_token).await {
+                Ok(page) => page,
+                Err(error) => {
+                    eprintln!("Couldn't list users: {}", error);
+                    process::exit(1);
+                }
+            };
+
+        for (user, created_at) in users {
+            let profile = UserProfile::new(
+                &user,
+                &Avatar::url(&public_bucket_domain, &user.sub),
+                &created_at,
+            );
+
+            match repository.create_profile(&profile).await {
+                Ok(true) => created_count += 1,
+                Ok(false) => skipped_count += 1,
+                Err(error) => {
+                    eprintln!("Couldn't create the profile of {}: {}", user.sub, error);
+                    process::exit(1);
+                }
+            }
+
+            if let Some(email) = Signup::normalize_email(&user.email) {
+                if let Err(error) = repository
+                    .claim_email(&EmailOwner::new(&email, &user.sub))
+                    .await
+                {
+                    eprintln!("Couldn't claim the email of {}: {}", user.sub, error);
+                    process::exit(1);
+                }
+            }
+        }
+
+        match next_token {
+            Some(token) => pagination_token = Some(token),
+            None => break,
+        }
+    }
+
+    println!(
+        "Created {} profiles, {} already existed",
+        created_count, skipped_count
+    );
+}
@@ -0,0 +1,41 @@
+/*---------- Imports ----------*/
+use chat_test_infra::api::spec::{self, render, ASYNCAPI_PATH, OPENAPI_PATH};
+use std::{env, fs, path::Path, process};
+
+/// Writes the specs, or with `--check` only compares them with the
+/// checked-in files and fails when they differ.
+fn main() {
+    let check_only = env::args().any(|arg| arg == "--check");
+    let documents = [
+        (OPENAPI_PATH, render(&spec::openapi())),
+        (ASYNCAPI_PATH, render(&spec::asyncapi())),
+    ];
+
+    let mut outdated: Vec<&str> = vec![];
+
+    for (path, rendered) in documents.iter() {
+        if check_only {
+            if fs::read_to_string(path).ok().as_deref() != Some(rendered.as_str()) {
+                outdated.push(path);
+            }
+
+            continue;
+        }
+
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent).expect("Couldn't create the docs directory");
+        }
+
+        fs::write(path, rendered).expect("Couldn't write the spec");
+        println!("Wrote {}", path);
+    }
+
+    if !outdated.is_empty() {
+        eprintln!(
+            "Outdated specs: {}. Run `make specs` and commit the result.",
+            outdated.join(", ")
+        );
+
+        process::exit(1);
+    }
+}
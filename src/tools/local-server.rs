@@ -0,0 +1,598 @@
+//! Runs the whole app on a laptop: the REST API over plain HTTP, the
+//! WebSocket API with API Gateway's `$connect`/`$disconnect`/action routing,
+//! the `@connections` management API the handlers post frames through, and
+//! a poller feeding the table's stream to the stream processor handlers.
+//!
+//! The table lives in DynamoDB Local (`DYNAMODB_ENDPOINT`) and must already
+//! exist with its stream enabled. Everything else, like the user pool and
+//! the buckets, is read from the same variables as in the deployed stack.
+//! Tokens are decoded but not verified, as API Gateway would have done that.
+//!
+//! Only DynamoDB and API Gateway are stood in for. User lookups, search and
+//! account deletion still call Cognito, avatars and exports go to S3 and
+//! invitations are sent through SES, so those routes need AWS credentials
+//! and a deployed user pool, buckets and verified sender to work.
+
+/*---------- Imports ----------*/
+use aws_config::SdkConfig;
+use aws_lambda_events::query_map::QueryMap;
+use aws_sdk_dynamodb::model::AttributeValue as DynamoAttributeValue;
+use aws_sdk_dynamodbstreams::model::{
+    AttributeValue as StreamAttributeValue, Record, ShardIteratorType,
+};
+use chat_test_infra::{
+    api::{
+        router::Router,
+        routes::{self, ApiState},
+    },
+    handlers,
+    models::{
+        common::to_event_attribute_map,
+        connection::Connection,
+        stream::{StreamChange, StreamRecord},
+        user::User,
+    },
+    notifier::apigateway::ApiGatewayNotifier,
+    repository::{dynamodb::DynamoChatRepository, ChatRepository},
+    utils::{aws::AwsClients, jwt::Jwt},
+    websocket,
+};
+use futures_util::{SinkExt, StreamExt};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Method, StatusCode,
+};
+use lambda_http::{Body, Request, RequestExt};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    env,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, UnboundedSender},
+};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{
+        ErrorResponse, Request as HandshakeRequest, Response as HandshakeResponse,
+    },
+    Message as Frame,
+};
+use ulid::Ulid;
+
+/*---------- Constants ----------*/
+const DEFAULT_HTTP_PORT: u16 = 3000;
+const DEFAULT_WEBSOCKET_PORT: u16 = 3001;
+const CONNECTIONS_PATH: &str = "/@connections/";
+const SEND_MESSAGE_ROUTE: &str = "send-message";
+const STREAM_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/*---------- Structs ----------*/
+struct LocalServer {
+    config: SdkConfig,
+    repository: DynamoChatRepository,
+    notifier: ApiGatewayNotifier,
+    router: Router<ApiState>,
+    /// Outgoing frames of every open WebSocket, by connection id.
+    connections: Mutex<HashMap<String, UnboundedSender<String>>>,
+}
+
+fn port_from_env(name: &str, default: u16) -> u16 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/*---------- REST API ----------*/
+fn hyper_response(status: StatusCode, body: Value) -> hyper::Response<hyper::Body> {
+    let mut response = hyper::Response::new(hyper::Body::from(body.to_string()));
+    *response.status_mut() = status;
+
+    response
+}
+
+/// Turns a plain HTTP request into what the Lambda runtime would hand the
+/// router, query string parameters included.
+async fn to_lambda_request(request: hyper::Request<hyper::Body>) -> Result<Request, hyper::Error> {
+    let (parts, body) = request.into_parts();
+    let body_bytes = hyper::body::to_bytes(body).await?;
+    let query_map: QueryMap = parts
+        .uri
+        .query()
+        .unwrap_or_default()
+        .parse()
+        .unwrap_or_default();
+
+    let body = if body_bytes.is_empty() {
+        Body::Empty
+    } else {
+        Body::Binary(body_bytes.to_vec())
+    };
+
+    Ok(Request::from_parts(parts, body).with_query_string_parameters(query_map))
+}
+
+fn to_hyper_response(response: lambda_http::Response<Body>) -> hyper::Response<hyper::Body> {
+    let (parts, body) = response.into_parts();
+    let body = match body {
+        Body::Empty => hyper::Body::empty(),
+        Body::Text(text) => hyper::Body::from(text),
+        Body::Binary(bytes) => hyper::Body::from(bytes),
+    };
+
+    hyper::Response::from_parts(parts, body)
+}
+
+/// `POST` and `DELETE` on `/@connections/{id}`, answering like the
+/// management API so the SDK maps a closed connection to `GoneException`.
+async fn handle_connection_request(
+    server: &LocalServer,
+    method: &Method,
+    connection_id: &str,
+    body: hyper::Body,
+) -> hyper::Response<hyper::Body> {
+    let gone_response = || {
+        let mut response = hyper_response(
+            StatusCode::GONE,
+            json!({ "message": format!("Connection {} is gone", connection_id) }),
+        );
+
+        response.headers_mut().insert(
+            "x-amzn-errortype",
+            hyper::header::HeaderValue::from_static("GoneException"),
+        );
+
+        response
+    };
+
+    match *method {
+        Method::POST => {
+            let frame = match hyper::body::to_bytes(body).await {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(_) => return hyper_response(StatusCode::BAD_REQUEST, json!({})),
+            };
+
+            let connections = server.connections.lock().unwrap();
+
+            match connections.get(connection_id) {
+                Some(sender) if sender.send(frame).is_ok() => {
+                    hyper_response(StatusCode::OK, json!({}))
+                }
+                _ => gone_response(),
+            }
+        }
+        // Dropping the sender ends the writer, which closes the socket
+        Method::DELETE => match server.connections.lock().unwrap().remove(connection_id) {
+            Some(_) => hyper_response(StatusCode::NO_CONTENT, json!({})),
+            None => gone_response(),
+        },
+        _ => hyper_response(StatusCode::METHOD_NOT_ALLOWED, json!({})),
+    }
+}
+
+async fn handle_http(
+    server: Arc<LocalServer>,
+    request: hyper::Request<hyper::Body>,
+) -> Result<hyper::Response<hyper::Body>, Infallible> {
+    if let Some(connection_id) = request.uri().path().strip_prefix(CONNECTIONS_PATH) {
+        let connection_id = connection_id.to_owned();
+        let method = request.method().clone();
+
+        return Ok(handle_connection_request(
+            &server,
+            &method,
+            &connection_id,
+            request.into_body(),
+        )
+        .await);
+    }
+
+    let lambda_request = match to_lambda_request(request).await {
+        Ok(lambda_request) => lambda_request,
+        Err(_) => return Ok(hyper_response(StatusCode::BAD_REQUEST, json!({}))),
+    };
+
+    Ok(to_hyper_response(
+        server.router.handle(lambda_request).await,
+    ))
+}
+
+async fn serve_http(server: Arc<LocalServer>, port: u16) {
+    let make_service = make_service_fn(move |_| {
+        let server = server.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_http(server.clone(), request)
+            }))
+        }
+    });
+
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+
+    println!("REST API listening on http://{}", address);
+
+    if let Err(error) = hyper::Server::bind(&address).serve(make_service).await {
+        eprintln!("HTTP server stopped: {}", error);
+    }
+}
+
+/*---------- WebSocket API ----------*/
+/// What the authorizer does on `$connect`: the `idToken` query parameter
+/// must hold a user, or the upgrade is refused.
+fn authorize(request: &HandshakeRequest) -> Option<User> {
+    let query_map: QueryMap = request
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .parse()
+        .unwrap_or_default();
+
+    Jwt::decode_payload::<User>(query_map.first("idToken")?).ok()
+}
+
+/// Routes a frame on its `action`, like the API's route selection
+/// expression. Actions without a route get API Gateway's `Forbidden`.
+async fn handle_frame(server: &LocalServer, connection_id: &str, user: &User, frame: String) {
+    let action = serde_json::from_str::<Value>(&frame)
+        .ok()
+        .and_then(|body| body["action"].as_str().map(|action| action.to_owned()));
+
+    match action.as_deref() {
+        Some(SEND_MESSAGE_ROUTE) => {
+            if let Err(error) = websocket::handle_send_message(
+                &server.repository,
+                &server.notifier,
+                connection_id,
+                Some(user.clone()),
+                Some(frame),
+            )
+            .await
+            {
+                eprintln!("send-message failed on {}: {}", connection_id, error);
+            }
+        }
+        _ => {
+            let forbidden = json!({
+                "message": "Forbidden",
+                "connectionId": connection_id,
+                "requestId": Ulid::new().to_string(),
+            });
+
+            if let Some(sender) = server.connections.lock().unwrap().get(connection_id) {
+                sender.send(forbidden.to_string()).ok();
+            }
+        }
+    }
+}
+
+async fn handle_websocket(server: Arc<LocalServer>, stream: TcpStream) {
+    let mut connected_user: Option<User> = None;
+    // The error type is fixed by tungstenite's handshake callback.
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &HandshakeRequest, response: HandshakeResponse| {
+        connected_user = authorize(request);
+
+        match connected_user {
+            Some(_) => Ok(response),
+            None => {
+                let mut error_response = ErrorResponse::new(Some("Unauthorized".to_owned()));
+                *error_response.status_mut() = StatusCode::UNAUTHORIZED;
+
+                Err(error_response)
+            }
+        }
+    };
+
+    let websocket_stream = match tokio_tungstenite::accept_hdr_async(stream, callback).await {
+        Ok(websocket_stream) => websocket_stream,
+        Err(_) => return,
+    };
+
+    let user = match connected_user {
+        Some(user) => user,
+        None => return,
+    };
+
+    // $connect
+    let connection_id = Ulid::new().to_string();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+
+    if let Err(error) = server
+        .repository
+        .save_connection(&Connection::new(&user.sub, &connection_id))
+        .await
+    {
+        eprintln!("Couldn't save connection {}: {}", connection_id, error);
+        return;
+    }
+
+    server
+        .connections
+        .lock()
+        .unwrap()
+        .insert(connection_id.to_owned(), sender);
+
+    let (mut frame_sink, mut frame_stream) = websocket_stream.split();
+
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = receiver.recv().await {
+            if frame_sink.send(Frame::Text(frame)).await.is_err() {
+                return;
+            }
+        }
+
+        frame_sink.send(Frame::Close(None)).await.ok();
+    });
+
+    while let Some(Ok(frame)) = frame_stream.next().await {
+        match frame {
+            Frame::Text(text) => handle_frame(&server, &connection_id, &user, text).await,
+            Frame::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    // $disconnect
+    server.connections.lock().unwrap().remove(&connection_id);
+    server.repository.delete_connection(&user.sub).await.ok();
+    writer.abort();
+}
+
+async fn serve_websocket(server: Arc<LocalServer>, port: u16) {
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    let listener = TcpListener::bind(address)
+        .await
+        .expect("Failed to bind the WebSocket port");
+
+    println!("WebSocket API listening on ws://{}", address);
+
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(handle_websocket(server.clone(), stream));
+    }
+}
+
+/*---------- Stream processing ----------*/
+fn to_dynamodb_attribute(attribute: &StreamAttributeValue) -> Option<DynamoAttributeValue> {
+    let converted = match attribute {
+        StreamAttributeValue::S(value) => DynamoAttributeValue::S(value.to_owned()),
+        StreamAttributeValue::N(value) => DynamoAttributeValue::N(value.to_owned()),
+        StreamAttributeValue::B(value) => {
+            DynamoAttributeValue::B(aws_sdk_dynamodb::types::Blob::new(value.as_ref()))
+        }
+        StreamAttributeValue::Ss(values) => DynamoAttributeValue::Ss(values.to_owned()),
+        StreamAttributeValue::Ns(values) => DynamoAttributeValue::Ns(values.to_owned()),
+        StreamAttributeValue::Bs(values) => DynamoAttributeValue::Bs(
+            values
+                .iter()
+                .map(|value| aws_sdk_dynamodb::types::Blob::new(value.as_ref()))
+                .collect(),
+        ),
+        StreamAttributeValue::M(values) => DynamoAttributeValue::M(to_dynamodb_item(values)?),
+        StreamAttributeValue::L(values) => DynamoAttributeValue::L(
+            values
+                .iter()
+                .map(to_dynamodb_attribute)
+                .collect::<Option<Vec<DynamoAttributeValue>>>()?,
+        ),
+        StreamAttributeValue::Null(value) => DynamoAttributeValue::Null(*value),
+        StreamAttributeValue::Bool(value) => DynamoAttributeValue::Bool(*value),
+        _ => return None,
+    };
+
+    Some(converted)
+}
+
+fn to_dynamodb_item(
+    item: &HashMap<String, StreamAttributeValue>,
+) -> Option<HashMap<String, DynamoAttributeValue>> {
+    item.iter()
+        .map(|(key, value)| Some((key.to_owned(), to_dynamodb_attribute(value)?)))
+        .collect()
+}
+
+/// Builds the record Lambda would deliver for a change read from the stream.
+fn to_event_record(record: &Record) -> Option<StreamRecord> {
+    let change = record.dynamodb()?;
+    let to_image = |image: Option<&HashMap<String, StreamAttributeValue>>| match image {
+        Some(image) => to_event_attribute_map(&to_dynamodb_item(image)?).ok(),
+        None => Some(HashMap::new()),
+    };
+
+    Some(StreamRecord {
+        event_id: record.event_id().unwrap_or_default().to_owned(),
+        event_name: record.event_name()?.as_str().to_owned(),
+        change: StreamChange {
+            keys: to_image(change.keys())?,
+            new_image: to_image(change.new_image())?,
+            old_image: to_image(change.old_image())?,
+            sequence_number: change.sequence_number().map(|number| number.to_owned()),
+        },
+    })
+}
+
+/// Reads every shard of the table's stream and runs the stream processor
+/// handlers on each record, one at a time like a batch size of one. Shards
+/// open at startup are read from their end, so the changes already in the
+/// table aren't replayed; shards found later are read from their start. A
+/// shard that fails to read is reopened right after the last record it
+/// handled, rather than from its start again.
+async fn poll_stream(server: Arc<LocalServer>, streams_client: aws_sdk_dynamodbstreams::Client) {
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let dynamodb_client = AwsClients::dynamodb(&server.config);
+
+    let stream_arn = dynamodb_client
+        .describe_table()
+        .table_name(&table_name)
+        .send()
+        .await
+        .expect("Failed to describe the table, is DynamoDB Local running?")
+        .table()
+        .and_then(|table| table.latest_stream_arn())
+        .map(|arn| arn.to_owned())
+        .expect("The table must have its stream enabled");
+
+    let mut shard_iterators: HashMap<String, String> = HashMap::new();
+    let mut finished_shards: HashSet<String> = HashSet::new();
+    let mut last_sequence_numbers: HashMap<String, String> = HashMap::new();
+    // Where each shard was first opened, for reopening one that failed
+    // before any record was handled
+    let mut start_positions: HashMap<String, ShardIteratorType> = HashMap::new();
+    let mut iterator_type = ShardIteratorType::Latest;
+
+    println!("Polling stream {}", stream_arn);
+
+    loop {
+        match streams_client
+            .describe_stream()
+            .stream_arn(&stream_arn)
+            .send()
+            .await
+        {
+            Ok(output) => {
+                let shards = output
+                    .stream_description()
+                    .and_then(|description| description.shards())
+                    .unwrap_or_default();
+
+                for shard_id in shards.iter().filter_map(|shard| shard.shard_id()) {
+                    if shard_iterators.contains_key(shard_id) || finished_shards.contains(shard_id)
+                    {
+                        continue;
+                    }
+
+                    let iterator_request = streams_client
+                        .get_shard_iterator()
+                        .stream_arn(&stream_arn)
+                        .shard_id(shard_id);
+
+                    let iterator_result = match last_sequence_numbers.get(shard_id) {
+                        Some(sequence_number) => {
+                            iterator_request
+                                .shard_iterator_type(ShardIteratorType::AfterSequenceNumber)
+                                .sequence_number(sequence_number)
+                                .send()
+                                .await
+                        }
+                        None => {
+                            let start_position = start_positions
+                                .entry(shard_id.to_owned())
+                                .or_insert_with(|| iterator_type.clone());
+
+                            iterator_request
+                                .shard_iterator_type(start_position.clone())
+                                .send()
+                                .await
+                        }
+                    };
+
+                    match iterator_result
+                        .ok()
+                        .and_then(|output| output.shard_iterator().map(|id| id.to_owned()))
+                    {
+                        Some(iterator) => {
+                            shard_iterators.insert(shard_id.to_owned(), iterator);
+                        }
+                        None => eprintln!("Couldn't open shard {}", shard_id),
+                    }
+                }
+            }
+            Err(error) => eprintln!("Couldn't describe the stream: {}", error),
+        }
+
+        iterator_type = ShardIteratorType::TrimHorizon;
+
+        for (shard_id, iterator) in shard_iterators.clone() {
+            let records_output = match streams_client
+                .get_records()
+                .shard_iterator(iterator)
+                .send()
+                .await
+            {
+                Ok(output) => output,
+                Err(error) => {
+                    eprintln!("Couldn't read shard {}: {}", shard_id, error);
+                    shard_iterators.remove(&shard_id);
+                    continue;
+                }
+            };
+
+            for record in records_output.records().unwrap_or_default() {
+                match to_event_record(record) {
+                    Some(event_record) => {
+                        handlers::handle_record(&event_record, &server.config).await;
+                        handlers::handle_export_record(&event_record, &server.config).await;
+                    }
+                    None => eprintln!("Skipping unreadable stream record {:?}", record.event_id()),
+                }
+
+                if let Some(sequence_number) = record
+                    .dynamodb()
+                    .and_then(|change| change.sequence_number())
+                {
+                    last_sequence_numbers.insert(shard_id.to_owned(), sequence_number.to_owned());
+                }
+            }
+
+            // Closed shards have no next iterator once they are drained
+            match records_output.next_shard_iterator() {
+                Some(next_iterator) => {
+                    shard_iterators.insert(shard_id, next_iterator.to_owned());
+                }
+                None => {
+                    shard_iterators.remove(&shard_id);
+                    last_sequence_numbers.remove(&shard_id);
+                    start_positions.remove(&shard_id);
+                    finished_shards.insert(shard_id);
+                }
+            }
+        }
+
+        tokio::time::sleep(STREAM_POLL_INTERVAL).await;
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let config = aws_config::load_from_env().await;
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let dynamodb_endpoint = env::var("DYNAMODB_ENDPOINT").expect("DYNAMODB_ENDPOINT must be set");
+    // Must point at this server, e.g. http://localhost:3000, so every
+    // handler posts its frames here
+    let websocket_mgmt_api =
+        env::var("WEBSOCKET_MGMT_API").expect("WEBSOCKET_MGMT_API must be set");
+    let http_port = port_from_env("LOCAL_HTTP_PORT", DEFAULT_HTTP_PORT);
+    let websocket_port = port_from_env("LOCAL_WEBSOCKET_PORT", DEFAULT_WEBSOCKET_PORT);
+
+    let streams_client_config = aws_sdk_dynamodbstreams::config::Builder::from(&config)
+        .endpoint_resolver(aws_sdk_dynamodbstreams::Endpoint::immutable(
+            dynamodb_endpoint
+                .parse()
+                .expect("Failed to parse DynamoDB endpoint"),
+        ))
+        .build();
+
+    let streams_client = aws_sdk_dynamodbstreams::Client::from_conf(streams_client_config);
+
+    let server = Arc::new(LocalServer {
+        repository: DynamoChatRepository::new(AwsClients::dynamodb(&config), &table_name),
+        notifier: ApiGatewayNotifier::new(AwsClients::websocket_management(
+            &config,
+            &websocket_mgmt_api,
+        )),
+        router: routes::router(ApiState::from_env(&config)),
+        connections: Mutex::new(HashMap::new()),
+        config,
+    });
+
+    tokio::join!(
+        serve_http(server.clone(), http_port),
+        serve_websocket(server.clone(), websocket_port),
+        poll_stream(server, streams_client)
+    );
+}
@@ -0,0 +1,132 @@
+/*---------- Imports ----------*/
+use aws_lambda_events::s3::{S3Event, S3EventRecord};
+use chat_test_infra::{
+    models::user::AvatarSource,
+    repository::{dynamodb::DynamoChatRepository, ChatRepository},
+    utils::{
+        avatar::{Avatar, AvatarConfig, AvatarError, MAX_UPLOAD_BYTES},
+        aws::AwsClients,
+    },
+};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use std::env;
+use tracing::error;
+
+/*---------- Structs ----------*/
+struct Context<'a> {
+    repository: &'a DynamoChatRepository,
+    s3_client: &'a aws_sdk_s3::Client,
+    public_bucket_name: &'a str,
+    avatar_config: &'a AvatarConfig,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let public_bucket_name = env::var("PUBLIC_BUCKET").expect("PUBLIC_BUCKET must be set");
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let repository = DynamoChatRepository::new(AwsClients::dynamodb(&config), &table_name);
+    let s3_client = aws_sdk_s3::Client::new(&config);
+    let avatar_config = AvatarConfig::from_env();
+
+    let context = Context {
+        repository: &repository,
+        s3_client: &s3_client,
+        public_bucket_name: &public_bucket_name,
+        avatar_config: &avatar_config,
+    };
+
+    let handler = service_fn(|event| handler_fn(&context, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+/// Upload keys look like `avatar/<sub>/<ulid>`.
+fn parse_upload_key(key: &str) -> Option<&str> {
+    let (user_sub, _) = key.strip_prefix("avatar/")?.split_once('/')?;
+
+    if user_sub.is_empty() {
+        return None;
+    }
+
+    Some(user_sub)
+}
+
+async fn process_upload(
+    context: &Context<'_>,
+    bucket_name: &str,
+    key: &str,
+    record: &S3EventRecord,
+) -> Result<(), AvatarError> {
+    let user_sub = parse_upload_key(key).ok_or(AvatarError::InvalidImage)?;
+
+    match record.s3.object.size {
+        Some(size) if size <= MAX_UPLOAD_BYTES => {}
+        _ => return Err(AvatarError::InvalidImage),
+    }
+
+    let get_object_result = context
+        .s3_client
+        .get_object()
+        .bucket(bucket_name)
+        .key(key)
+        .send()
+        .await
+        .map_err(|_| AvatarError::InvalidImage)?;
+
+    let image_bytes = get_object_result
+        .body
+        .collect()
+        .await
+        .map_err(|_| AvatarError::InvalidImage)?
+        .into_bytes();
+
+    let avatar_image = Avatar::normalize(context.avatar_config, &image_bytes)?;
+
+    Avatar::upload(
+        context.s3_client,
+        context.public_bucket_name,
+        context.avatar_config,
+        user_sub,
+        &avatar_image,
+    )
+    .await?;
+
+    // Keeps name changes from drawing initials over the upload
+    if let Err(error) = context
+        .repository
+        .set_avatar_source(user_sub, AvatarSource::Custom)
+        .await
+    {
+        error!("Couldn't mark avatar of {} as custom: {}", user_sub, error);
+    }
+
+    Ok(())
+}
+
+async fn handler_fn(context: &Context<'_>, event: LambdaEvent<S3Event>) -> Result<(), Error> {
+    for record in event.payload.records.iter() {
+        let (bucket_name, key) = match (&record.s3.bucket.name, &record.s3.object.key) {
+            (Some(bucket_name), Some(key)) => (bucket_name, key),
+            _ => continue,
+        };
+
+        if let Err(error) = process_upload(context, bucket_name, key, record).await {
+            error!("Couldn't process avatar upload {}: {}", key, error);
+        }
+
+        // Uploads are single use, valid or not
+        context
+            .s3_client
+            .delete_object()
+            .bucket(bucket_name)
+            .key(key)
+            .send()
+            .await
+            .ok();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,281 @@
+/*---------- Imports ----------*/
+use aws_lambda_events::cognito::{
+    CognitoEventUserPoolsCreateAuthChallenge, CognitoEventUserPoolsCreateAuthChallengeRequest,
+};
+use chat_test_infra::{
+    mail::{ses::SesMailSender, MailSender, OutgoingMail},
+    repository::{dynamodb::DynamoChatRepository, ChatRepository},
+    utils::{
+        avatar::Avatar,
+        email::{Email, EmailKind, EmailVariables, Locale},
+        otp::LoginCodes,
+    },
+};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use std::{collections::HashMap, env};
+
+/*---------- Constants ----------*/
+const CUSTOM_CHALLENGE: &str = "CUSTOM_CHALLENGE";
+const DEFAULT_APP_NAME: &str = "ChatApp";
+
+/*---------- Structs ----------*/
+struct TriggerContext<R: ChatRepository, M: MailSender> {
+    repository: R,
+    mail_sender: M,
+    app_name: String,
+    public_bucket_domain: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let from_address = env::var("MAIL_FROM").expect("MAIL_FROM must be set");
+    let context = TriggerContext {
+        repository: DynamoChatRepository::new(aws_sdk_dynamodb::Client::new(&config), &table_name),
+        mail_sender: SesMailSender::new(aws_sdk_sesv2::Client::new(&config), &from_address),
+        app_name: env::var("APP_NAME").unwrap_or(DEFAULT_APP_NAME.to_owned()),
+        public_bucket_domain: env::var("PUBLIC_BUCKET_DOMAIN")
+            .expect("PUBLIC_BUCKET_DOMAIN must be set"),
+    };
+    let handler = service_fn(|event| handler_fn(&context, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+/// `jane@example.com` becomes `j***@example.com`, enough for the user to
+/// recognize the address without handing it to whoever typed it.
+fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local_part, domain)) => {
+            let first_character: String = local_part.chars().take(1).collect();
+
+            format!("{}***@{}", first_character, domain)
+        }
+        None => "***".to_owned(),
+    }
+}
+
+/// Renders the sign-in email in the user's language and sends it.
+async fn send_code(
+    context: &TriggerContext<impl ChatRepository, impl MailSender>,
+    request: &CognitoEventUserPoolsCreateAuthChallengeRequest,
+    user_sub: &str,
+    email: &str,
+    code: String,
+) -> Result<(), Error> {
+    let user_attributes = &request.user_attributes;
+
+    let locale = match request.client_metadata.get("locale") {
+        Some(tag) => Locale::from_tag(tag),
+        None => Locale::from_tag(
+            user_attributes
+                .get("locale")
+                .map(String::as_str)
+                .unwrap_or_default(),
+        ),
+    };
+
+    let variables = EmailVariables {
+        app_name: context.app_name.to_owned(),
+        name: user_attributes
+            .get("name")
+            .cloned()
+            .unwrap_or(email.to_owned()),
+        avatar_url: Avatar::url(&context.public_bucket_domain, user_sub),
+        code,
+        username: email.to_owned(),
+        link: None,
+    };
+
+    let rendered_email = Email::render(EmailKind::LoginCode, locale, &variables);
+
+    context
+        .mail_sender
+        .send(&OutgoingMail {
+            to: email.to_owned(),
+            subject: rendered_email.subject,
+            html: rendered_email.html,
+            text: rendered_email.text,
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Emails a new code on the first challenge of a session. Later challenges
+/// of the same session are retries of that code, nothing is sent again.
+async fn handler_fn(
+    context: &TriggerContext<impl ChatRepository, impl MailSender>,
+    mut event: LambdaEvent<CognitoEventUserPoolsCreateAuthChallenge>,
+) -> Result<CognitoEventUserPoolsCreateAuthChallenge, Error> {
+    let request = &event.payload.request;
+
+    if request.challenge_name.as_deref() != Some(CUSTOM_CHALLENGE) {
+        return Ok(event.payload);
+    }
+
+    let user_attributes = &request.user_attributes;
+    let email = user_attributes.get("email").cloned().unwrap_or_default();
+    let is_retry = request
+        .session
+        .iter()
+        .flatten()
+        .any(|challenge| challenge.challenge_name.as_deref() == Some(CUSTOM_CHALLENGE));
+
+    // Unknown users have no attributes, and users past the hourly limit get
+    // no new code. Both get the same answer as everyone else
+    if let (Some(user_sub), false) = (user_attributes.get("sub"), is_retry) {
+        if let Some(code) = LoginCodes::issue(&context.repository, user_sub).await? {
+            send_code(context, request, user_sub, &email, code).await?;
+        }
+    }
+
+    let response = &mut event.payload.response;
+
+    response.public_challenge_parameters = HashMap::from([
+        ("delivery".to_owned(), "EMAIL".to_owned()),
+        ("destination".to_owned(), mask_email(&email)),
+    ]);
+    response.challenge_metadata = Some("LOGIN_CODE".to_owned());
+
+    Ok(event.payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_test_infra::{
+        mail::memory::InMemoryMailSender,
+        repository::memory::InMemoryChatRepository,
+        utils::otp::{MAX_LOGIN_CODES_PER_HOUR, MAX_LOGIN_CODE_ATTEMPTS},
+    };
+    use lambda_runtime::Context;
+    use serde_json::{json, Value};
+
+    const USER_SUB: &str = "sub";
+    const EMAIL: &str = "jane@example.com";
+
+    fn context() -> TriggerContext<InMemoryChatRepository, InMemoryMailSender> {
+        TriggerContext {
+            repository: InMemoryChatRepository::new(),
+            mail_sender: InMemoryMailSender::new(),
+            app_name: DEFAULT_APP_NAME.to_owned(),
+            public_bucket_domain: "bucket.example.com".to_owned(),
+        }
+    }
+
+    async fn create_challenge(
+        context: &TriggerContext<InMemoryChatRepository, InMemoryMailSender>,
+        session: Value,
+    ) -> CognitoEventUserPoolsCreateAuthChallenge {
+        let payload = serde_json::from_value(json!({
+            "version": "1",
+            "triggerSource": "CreateAuthChallenge_Authentication",
+            "region": "us-east-1",
+            "userPoolId": "us-east-1_pool",
+            "userName": USER_SUB,
+            "callerContext": { "awsSdkVersion": "1", "clientId": "client" },
+            "request": {
+                "userAttributes": { "sub": USER_SUB, "email": EMAIL, "name": "Jane" },
+                "challengeName": CUSTOM_CHALLENGE,
+                "session": session,
+            },
+            "response": {},
+        }))
+        .unwrap();
+
+        handler_fn(context, LambdaEvent::new(payload, Context::default()))
+            .await
+            .unwrap()
+    }
+
+    /// Reads the code back from the plain text part of the last email.
+    fn sent_code(context: &TriggerContext<InMemoryChatRepository, InMemoryMailSender>) -> String {
+        let mail = context.mail_sender.last_sent_to(EMAIL).unwrap();
+
+        mail.text
+            .split_whitespace()
+            .find(|word| word.len() == 6 && word.chars().all(|c| c.is_ascii_digit()))
+            .unwrap()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn the_emailed_code_signs_the_user_in() {
+        let context = context();
+        let event = create_challenge(&context, json!([])).await;
+
+        assert_eq!(
+            event.response.public_challenge_parameters["destination"],
+            "j***@example.com"
+        );
+
+        let code = sent_code(&context);
+
+        assert!(LoginCodes::verify(&context.repository, USER_SUB, &code)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn retries_reuse_the_first_code() {
+        let context = context();
+
+        create_challenge(&context, json!([])).await;
+        create_challenge(
+            &context,
+            json!([{ "challengeName": CUSTOM_CHALLENGE, "challengeResult": false }]),
+        )
+        .await;
+
+        assert_eq!(context.mail_sender.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn no_code_is_emailed_past_the_hourly_limit() {
+        let context = context();
+
+        for _ in 0..MAX_LOGIN_CODES_PER_HOUR {
+            create_challenge(&context, json!([])).await;
+        }
+
+        let code = sent_code(&context);
+        let event = create_challenge(&context, json!([])).await;
+
+        assert_eq!(
+            context.mail_sender.sent().len(),
+            MAX_LOGIN_CODES_PER_HOUR as usize
+        );
+        assert_eq!(
+            event.response.public_challenge_parameters["destination"],
+            "j***@example.com"
+        );
+        assert!(LoginCodes::verify(&context.repository, USER_SUB, &code)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn the_emailed_code_stops_working_after_the_attempt_limit() {
+        let context = context();
+
+        create_challenge(&context, json!([])).await;
+
+        let code = sent_code(&context);
+
+        for _ in 0..MAX_LOGIN_CODE_ATTEMPTS {
+            assert!(
+                !LoginCodes::verify(&context.repository, USER_SUB, "000000x")
+                    .await
+                    .unwrap()
+            );
+        }
+
+        assert!(!LoginCodes::verify(&context.repository, USER_SUB, &code)
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,156 @@
+/*---------- Imports ----------*/
+use aws_lambda_events::cognito::CognitoEventUserPoolsCustomMessage;
+use chat_test_infra::utils::{
+    avatar::Avatar,
+    email::{Email, EmailKind, EmailVariables, Locale},
+};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use serde_json::Value;
+use std::env;
+
+/*---------- Constants ----------*/
+const DEFAULT_APP_NAME: &str = "ChatApp";
+
+/*---------- Structs ----------*/
+struct TriggerContext {
+    app_name: String,
+    public_bucket_domain: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let context = TriggerContext {
+        app_name: env::var("APP_NAME").unwrap_or(DEFAULT_APP_NAME.to_owned()),
+        public_bucket_domain: env::var("PUBLIC_BUCKET_DOMAIN")
+            .expect("PUBLIC_BUCKET_DOMAIN must be set"),
+    };
+    let handler = service_fn(|event| handler_fn(&context, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+/// Replaces Cognito's default verification, password reset and invitation
+/// messages with the branded templates, in the language the client asked
+/// for through the `locale` client metadata or the user's `locale`
+/// attribute.
+async fn handler_fn(
+    context: &TriggerContext,
+    mut event: LambdaEvent<CognitoEventUserPoolsCustomMessage>,
+) -> Result<CognitoEventUserPoolsCustomMessage, Error> {
+    let trigger_source = event
+        .payload
+        .cognito_event_user_pools_header
+        .trigger_source
+        .as_deref()
+        .unwrap_or_default();
+
+    let email_kind = match EmailKind::from_trigger_source(trigger_source) {
+        Some(email_kind) => email_kind,
+        None => return Ok(event.payload),
+    };
+
+    let request = &event.payload.request;
+    let attribute = |name: &str| {
+        request
+            .user_attributes
+            .get(name)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_owned()
+    };
+
+    let locale = match request.client_metadata.get("locale") {
+        Some(tag) => Locale::from_tag(tag),
+        None => Locale::from_tag(&attribute("locale")),
+    };
+
+    let name = match attribute("name") {
+        name if !name.trim().is_empty() => name,
+        _ => attribute("email"),
+    };
+
+    let variables = EmailVariables {
+        app_name: context.app_name.to_owned(),
+        name,
+        avatar_url: Avatar::url(&context.public_bucket_domain, &attribute("sub")),
+        code: request.code_parameter.to_owned().unwrap_or_default(),
+        username: request.username_parameter.to_owned().unwrap_or_default(),
+        link: None,
+    };
+
+    let email = Email::render(email_kind, locale, &variables);
+    let response = &mut event.payload.response;
+
+    response.email_subject = Some(email.subject);
+    response.email_message = Some(email.html);
+    response.sms_message = Some(email.text);
+
+    Ok(event.payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_runtime::Context;
+    use serde_json::json;
+
+    async fn render(
+        trigger_source: &str,
+        client_metadata: Value,
+    ) -> CognitoEventUserPoolsCustomMessage {
+        let context = TriggerContext {
+            app_name: DEFAULT_APP_NAME.to_owned(),
+            public_bucket_domain: "bucket.example.com".to_owned(),
+        };
+        let payload = serde_json::from_value(json!({
+            "version": "1",
+            "triggerSource": trigger_source,
+            "region": "us-east-1",
+            "userPoolId": "us-east-1_pool",
+            "userName": "sub",
+            "callerContext": { "awsSdkVersion": "1", "clientId": "client" },
+            "request": {
+                "userAttributes": { "sub": "sub", "name": "Jane", "locale": "fr" },
+                "codeParameter": "{####}",
+                "usernameParameter": "{username}",
+                "clientMetadata": client_metadata,
+            },
+            "response": {},
+        }))
+        .unwrap();
+
+        handler_fn(&context, LambdaEvent::new(payload, Context::default()))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cognito_placeholders_reach_the_message() {
+        let event = render("CustomMessage_AdminCreateUser", json!({})).await;
+        let message = event.response.email_message.unwrap();
+
+        assert!(message.contains("{####}"));
+        assert!(message.contains("{username}"));
+        assert!(message.contains("lang=\"en\""));
+    }
+
+    #[tokio::test]
+    async fn client_metadata_picks_the_locale() {
+        let event = render("CustomMessage_SignUp", json!({ "locale": "es-AR" })).await;
+
+        assert!(event
+            .response
+            .email_message
+            .unwrap()
+            .contains("lang=\"es\""));
+    }
+
+    #[tokio::test]
+    async fn other_triggers_keep_the_default_message() {
+        let event = render("CustomMessage_Authentication", json!({})).await;
+
+        assert_eq!(event.response.email_message, None);
+    }
+}
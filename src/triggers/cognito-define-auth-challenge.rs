@@ -0,0 +1,52 @@
+/*---------- Imports ----------*/
+use aws_lambda_events::cognito::CognitoEventUserPoolsDefineAuthChallenge;
+use chat_test_infra::utils::otp::MAX_LOGIN_CODE_ATTEMPTS;
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+
+/*---------- Constants ----------*/
+const CUSTOM_CHALLENGE: &str = "CUSTOM_CHALLENGE";
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let handler = service_fn(handler_fn);
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+/// Drives the passwordless flow: keep asking for the emailed code until
+/// it's answered right, and give up after as many wrong answers as a code
+/// accepts. Sessions that started with anything but the custom challenge
+/// are refused, passwords go through the regular flows.
+async fn handler_fn(
+    mut event: LambdaEvent<CognitoEventUserPoolsDefineAuthChallenge>,
+) -> Result<CognitoEventUserPoolsDefineAuthChallenge, Error> {
+    let request = &event.payload.request;
+    let challenges: Vec<_> = request.session.iter().flatten().collect();
+
+    let is_custom =
+        |challenge_name: &Option<String>| challenge_name.as_deref() == Some(CUSTOM_CHALLENGE);
+
+    let failed_answers = challenges
+        .iter()
+        .filter(|challenge| is_custom(&challenge.challenge_name) && !challenge.challenge_result)
+        .count() as u32;
+
+    let (challenge_name, issue_tokens, fail_authentication) = match challenges.last() {
+        Some(last) if !is_custom(&last.challenge_name) => (None, false, true),
+        Some(last) if last.challenge_result => (None, true, false),
+        _ if failed_answers >= MAX_LOGIN_CODE_ATTEMPTS => (None, false, true),
+        // Unknown users get a challenge too, one they can't pass, so the flow
+        // doesn't tell which emails have an account
+        _ => (Some(CUSTOM_CHALLENGE.to_owned()), false, false),
+    };
+
+    let response = &mut event.payload.response;
+
+    response.challenge_name = challenge_name;
+    response.issue_tokens = issue_tokens;
+    response.fail_authentication = fail_authentication;
+
+    Ok(event.payload)
+}
@@ -1,66 +1,176 @@
 /*---------- Imports ----------*/
 use aws_lambda_events::cognito::CognitoEventUserPoolsPostConfirmation;
-use aws_sdk_s3::types::ByteStream;
-use image::ImageOutputFormat;
-use initials_revamped::{AvatarBuilder, AvatarResult};
+use chat_test_infra::{
+    models::{
+        signup::EmailOwner,
+        user::{User, UserProfile},
+    },
+    repository::{dynamodb::DynamoChatRepository, ChatRepository, RepositoryError},
+    utils::{
+        avatar::{Avatar, AvatarConfig, AvatarError},
+        signup::Signup,
+        welcome::{Welcome, WelcomeConfig},
+    },
+};
+use chrono::{SecondsFormat, Utc};
 use lambda_runtime::{service_fn, Error, LambdaEvent};
-use std::{env, io::Cursor};
+use std::env;
+use tracing::error;
+use ulid::Ulid;
 
 /*---------- Constants ----------*/
-const IMAGE_SIZE: u32 = 512;
-const FONT_COLOR: &str = "#ffffff";
-const FONT_SCALE: f32 = 250.0;
+const CONFIRM_SIGNUP_TRIGGER: &str = "PostConfirmation_ConfirmSignUp";
+
+/*---------- Structs ----------*/
+struct TriggerContext {
+    s3_client: aws_sdk_s3::Client,
+    repository: DynamoChatRepository,
+    public_bucket_name: String,
+    public_bucket_domain: String,
+    avatar_config: AvatarConfig,
+    welcome_config: Option<WelcomeConfig>,
+}
+
+/// Hands the messages people left for this address to the new user, as if
+/// they had been sent after the signup. Message ids were fixed when they
+/// were queued and invitations are only deleted once delivered, so a retried
+/// invocation can't duplicate anything.
+async fn deliver_invitations(
+    repository: &impl ChatRepository,
+    user: &User,
+) -> Result<(), RepositoryError> {
+    let email = match Signup::normalize_email(&user.email) {
+        Some(email) => email,
+        None => return Ok(()),
+    };
+
+    for invitation in repository.list_invitations(&email).await? {
+        for message in invitation.to_messages(&user.sub) {
+            repository.save_message(&message).await?;
+        }
+
+        repository
+            .delete_invitation(&email, &invitation.inviter.sub)
+            .await?;
+    }
+
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let config = aws_config::load_from_env().await;
-    let public_bucket_name = env::var("PUBLIC_BUCKET").expect("PUBLIC_BUCKET must be set");
-    let s3_client = aws_sdk_s3::Client::new(&config);
-    let handler = service_fn(|event| handler_fn(&s3_client, &public_bucket_name, event));
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let context = TriggerContext {
+        s3_client: aws_sdk_s3::Client::new(&config),
+        repository: DynamoChatRepository::new(aws_sdk_dynamodb::Client::new(&config), &table_name),
+        public_bucket_name: env::var("PUBLIC_BUCKET").expect("PUBLIC_BUCKET must be set"),
+        public_bucket_domain: env::var("PUBLIC_BUCKET_DOMAIN")
+            .expect("PUBLIC_BUCKET_DOMAIN must be set"),
+        avatar_config: AvatarConfig::from_env(),
+        welcome_config: WelcomeConfig::from_env(),
+    };
+    let handler = service_fn(|event| handler_fn(&context, event));
 
     lambda_runtime::run(handler).await?;
 
     Ok(())
 }
 
-fn avatar(user_name: &str) -> AvatarResult {
-    AvatarBuilder::new(user_name)
-        .with_font_scale(FONT_SCALE)?
-        .with_font_color(FONT_COLOR)?
-        .with_width(IMAGE_SIZE)?
-        .with_height(IMAGE_SIZE)
-}
-
 async fn handler_fn(
-    s3_client: &aws_sdk_s3::Client,
-    bucket_name: &str,
+    context: &TriggerContext,
     event: LambdaEvent<CognitoEventUserPoolsPostConfirmation>,
 ) -> Result<CognitoEventUserPoolsPostConfirmation, Error> {
-    let user_sub = event.payload.request.user_attributes.get("sub");
-    let user_name = event.payload.request.user_attributes.get("name");
+    let user_attributes = &event.payload.request.user_attributes;
+
+    let (user_sub, user_name) = match (user_attributes.get("sub"), user_attributes.get("name")) {
+        (Some(sub_value), Some(name_value)) => (sub_value, name_value),
+        _ => return Ok(event.payload),
+    };
+
+    let user = User::new(
+        user_sub,
+        user_name,
+        user_attributes
+            .get("email")
+            .map(String::as_str)
+            .unwrap_or_default(),
+    );
 
-    if let (Some(sub_value), Some(name_value)) = (user_sub, user_name) {
-        let avatar = avatar(name_value);
+    // The put is conditional, so a retried invocation or a later password
+    // reset confirmation keeps the original profile
+    let profile = UserProfile::new(
+        &user,
+        &Avatar::url(&context.public_bucket_domain, user_sub),
+        &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+    );
 
-        if let Ok(avatar_result) = avatar {
-            let image_key = format!("user/{}.png", sub_value);
-            let avatar_image = avatar_result.draw();
+    let profile_created = context.repository.create_profile(&profile).await?;
+    let confirmed_signup = event
+        .payload
+        .cognito_event_user_pools_header
+        .trigger_source
+        .as_deref()
+        == Some(CONFIRM_SIGNUP_TRIGGER);
+
+    // Password reset confirmations and retries would otherwise draw over a
+    // custom avatar the user uploaded since
+    if confirmed_signup && profile_created {
+        match Avatar::upload_initials(
+            &context.s3_client,
+            &context.public_bucket_name,
+            &context.avatar_config,
+            user_sub,
+            user_name,
+        )
+        .await
+        {
+            Ok(()) => {}
+            // A name the builder can't render shouldn't block the signup
+            Err(AvatarError::RenderFailed) => {}
+            // Neither should a failed upload now that the profile exists, the
+            // user can still redraw it by resetting their avatar
+            Err(error) => error!("Couldn't upload the avatar of {}: {}", user_sub, error),
+        }
+    }
 
-            let mut image_bytes: Vec<u8> = Vec::new();
-            let mut image_cursor = Cursor::new(&mut image_bytes);
+    // Only greet users whose profile was just written, so retries and
+    // password resets don't send the welcome again
+    if let (true, Some(welcome_config)) = (profile_created, &context.welcome_config) {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
 
-            avatar_image.write_to(&mut image_cursor, ImageOutputFormat::Png)?;
+        if let Some(message) =
+            Welcome::message(welcome_config, &user, &Ulid::new().to_string(), &timestamp)
+        {
+            if let Err(error) = context.repository.save_message(&message).await {
+                error!(
+                    "Couldn't send the welcome message to {}: {}",
+                    user.sub, error
+                );
+            }
+        }
+    }
 
-            s3_client
-                .put_object()
-                .bucket(bucket_name)
-                .key(image_key)
-                .content_type("image/png")
-                .body(ByteStream::from(image_bytes))
-                .send()
-                .await?;
+    // Keeps other aliases of the mailbox from signing up again. The claim is
+    // conditional too, so retries leave the first owner in place
+    if let Some(email) = Signup::normalize_email(&user.email) {
+        if let Err(error) = context
+            .repository
+            .claim_email(&EmailOwner::new(&email, &user.sub))
+            .await
+        {
+            error!("Couldn't claim the email of {}: {}", user.sub, error);
         }
     }
 
+    // Not gated on the profile being new: a retry must finish a delivery
+    // that failed halfway
+    if let Err(error) = deliver_invitations(&context.repository, &user).await {
+        error!(
+            "Couldn't deliver the invitations of {}: {}",
+            user.sub, error
+        );
+    }
+
     Ok(event.payload)
 }
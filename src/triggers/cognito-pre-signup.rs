@@ -0,0 +1,103 @@
+/*---------- Imports ----------*/
+use aws_lambda_events::cognito::CognitoEventUserPoolsPreSignup;
+use chat_test_infra::{
+    models::signup::SignupPolicy,
+    repository::{dynamodb::DynamoChatRepository, ChatRepository},
+    utils::signup::{Signup, SignupRejection},
+};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use std::{
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+/*---------- Constants ----------*/
+/// How long a loaded policy is reused before the item is read again. Edits
+/// to the rules take effect within this window.
+const POLICY_TTL: Duration = Duration::from_secs(60);
+
+/*---------- Structs ----------*/
+struct TriggerContext {
+    repository: DynamoChatRepository,
+    cached_policy: Mutex<Option<(Instant, SignupPolicy)>>,
+}
+
+impl TriggerContext {
+    async fn policy(&self) -> Result<SignupPolicy, SignupRejection> {
+        let cached_policy = self.cached_policy.lock().unwrap().clone();
+
+        if let Some((loaded_at, policy)) = &cached_policy {
+            if loaded_at.elapsed() < POLICY_TTL {
+                return Ok(policy.clone());
+            }
+        }
+
+        // A missing item means no custom rules. A failed read must not open
+        // sign-ups to everyone though, so the last known rules are kept
+        // however old they are, and without any the sign-up is refused
+        let policy = match self.repository.get_signup_policy().await {
+            Ok(policy) => policy.unwrap_or_default(),
+            Err(error) => {
+                error!("Couldn't load the sign-up policy: {}", error);
+
+                return cached_policy
+                    .map(|(_, policy)| policy)
+                    .ok_or(SignupRejection::Unavailable);
+            }
+        };
+
+        *self.cached_policy.lock().unwrap() = Some((Instant::now(), policy.clone()));
+
+        Ok(policy)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let context = TriggerContext {
+        repository: DynamoChatRepository::new(aws_sdk_dynamodb::Client::new(&config), &table_name),
+        cached_policy: Mutex::new(None),
+    };
+    let handler = service_fn(|event| handler_fn(&context, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+/// Returning an error rejects the sign-up, and Cognito shows its message to
+/// the user.
+async fn handler_fn(
+    context: &TriggerContext,
+    event: LambdaEvent<CognitoEventUserPoolsPreSignup>,
+) -> Result<CognitoEventUserPoolsPreSignup, Error> {
+    let email = event
+        .payload
+        .request
+        .user_attributes
+        .get("email")
+        .ok_or(SignupRejection::InvalidEmail)?;
+
+    let policy = context.policy().await?;
+    let normalized_email = Signup::check(&policy, email)?;
+
+    // Aliases like `jane.doe+chat@gmail.com` reach the same mailbox as an
+    // existing account, so they get the same answer as the address itself
+    match context.repository.get_email_owner(&normalized_email).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return Err(SignupRejection::AlreadyRegistered.into()),
+        Err(error) => {
+            error!(
+                "Couldn't look up the owner of {}: {}",
+                normalized_email, error
+            );
+            return Err(SignupRejection::Unavailable.into());
+        }
+    }
+
+    Ok(event.payload)
+}
@@ -0,0 +1,92 @@
+/*---------- Imports ----------*/
+use aws_lambda_events::cognito::{ClaimsOverrideDetails, CognitoEventUserPoolsPreTokenGen};
+use chat_test_infra::{
+    repository::{dynamodb::DynamoChatRepository, ChatRepository},
+    utils::avatar::Avatar,
+};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use std::{collections::HashMap, env};
+use tracing::error;
+
+/*---------- Constants ----------*/
+/// Role of users who don't belong to any Cognito group.
+const DEFAULT_ROLE: &str = "user";
+const TENANT_ATTRIBUTE: &str = "custom:tenant";
+
+/*---------- Structs ----------*/
+struct TriggerContext {
+    repository: DynamoChatRepository,
+    public_bucket_domain: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let context = TriggerContext {
+        repository: DynamoChatRepository::new(aws_sdk_dynamodb::Client::new(&config), &table_name),
+        public_bucket_domain: env::var("PUBLIC_BUCKET_DOMAIN")
+            .expect("PUBLIC_BUCKET_DOMAIN must be set"),
+    };
+    let handler = service_fn(|event| handler_fn(&context, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+/// Adds `avatar_url`, `roles`, `tenant` and `profile_version` to the ID
+/// token, so the APIs can read them off the token instead of looking them
+/// up. Every claim value has to be a string, lists are comma separated.
+async fn handler_fn(
+    context: &TriggerContext,
+    mut event: LambdaEvent<CognitoEventUserPoolsPreTokenGen>,
+) -> Result<CognitoEventUserPoolsPreTokenGen, Error> {
+    let request = &event.payload.request;
+
+    let user_sub = match request.user_attributes.get("sub") {
+        Some(sub) => sub.to_owned(),
+        None => return Ok(event.payload),
+    };
+
+    // A missing profile only means the backfill hasn't reached this user, the
+    // token can still be issued without a version
+    let profile = match context.repository.get_profile(&user_sub).await {
+        Ok(profile) => profile,
+        Err(error) => {
+            error!("Couldn't load the profile of {}: {}", user_sub, error);
+            None
+        }
+    };
+
+    let roles = match request.group_configuration.groups_to_override.is_empty() {
+        true => DEFAULT_ROLE.to_owned(),
+        false => request.group_configuration.groups_to_override.join(","),
+    };
+
+    let avatar_url = match &profile {
+        Some(profile) => profile.avatar_url.to_owned(),
+        None => Avatar::url(&context.public_bucket_domain, &user_sub),
+    };
+
+    let mut claims = HashMap::from([
+        ("avatar_url".to_owned(), avatar_url),
+        ("roles".to_owned(), roles),
+    ]);
+
+    if let Some(tenant) = request.user_attributes.get(TENANT_ATTRIBUTE) {
+        claims.insert("tenant".to_owned(), tenant.to_owned());
+    }
+
+    if let Some(profile) = &profile {
+        claims.insert("profile_version".to_owned(), profile.version.to_string());
+    }
+
+    event.payload.response.claims_override_details = Some(ClaimsOverrideDetails {
+        group_override_details: request.group_configuration.clone(),
+        claims_to_add_or_override: claims,
+        claims_to_suppress: vec![],
+    });
+
+    Ok(event.payload)
+}
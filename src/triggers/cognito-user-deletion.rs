@@ -0,0 +1,62 @@
+/*---------- Imports ----------*/
+use chat_test_infra::repository::dynamodb::DynamoChatRepository;
+use chat_test_infra::storage::s3::S3ObjectStore;
+use chat_test_infra::utils::{
+    account::{Account, AccountDeletion},
+    avatar::AvatarConfig,
+    aws::AwsClients,
+};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/*---------- Structs ----------*/
+/// Payload delivered by the EventBridge rule watching Cognito user deletions.
+/// The rule's input transformer reduces the CloudTrail event to the sub.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UserDeletionEvent {
+    sub: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let public_bucket_name = env::var("PUBLIC_BUCKET").expect("PUBLIC_BUCKET must be set");
+    let export_bucket_name = env::var("EXPORT_BUCKET").expect("EXPORT_BUCKET must be set");
+    let userpool_id = env::var("USERPOOL_ID").expect("USERPOOL_ID must be set");
+    let repository = DynamoChatRepository::new(AwsClients::dynamodb(&config), &table_name);
+    let object_store = S3ObjectStore::new(aws_sdk_s3::Client::new(&config));
+    let cognito_client = aws_sdk_cognitoidentityprovider::Client::new(&config);
+    let avatar_config = AvatarConfig::from_env();
+
+    let deletion_options = AccountDeletion {
+        repository: &repository,
+        object_store: &object_store,
+        cognito_client: &cognito_client,
+        public_bucket_name: &public_bucket_name,
+        export_bucket_name: &export_bucket_name,
+        userpool_id: &userpool_id,
+        avatar_config: &avatar_config,
+    };
+
+    let handler = service_fn(|event| handler_fn(&deletion_options, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+async fn handler_fn(
+    deletion_options: &AccountDeletion<'_>,
+    event: LambdaEvent<UserDeletionEvent>,
+) -> Result<(), Error> {
+    // The Cognito account is already gone, so only the data cascade runs.
+    // Returning the error makes Lambda retry, which resumes from the last
+    // completed step.
+    match Account::delete(deletion_options, &event.payload.sub, None).await {
+        Ok(_) => Ok(()),
+        Err((_, error)) => Err(error.into()),
+    }
+}
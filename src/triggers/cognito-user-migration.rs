@@ -0,0 +1,620 @@
+/*---------- Imports ----------*/
+use async_trait::async_trait;
+use aws_lambda_events::cognito::{
+    CognitoEventUserPoolsMigrateUser, CognitoEventUserPoolsPostAuthentication,
+};
+use chat_test_infra::{
+    legacy::{
+        json::JsonLegacyUserStore, LegacyConversation, LegacyMessage, LegacyStoreError, LegacyUser,
+        LegacyUserStore, LEGACY_ID_ATTRIBUTE,
+    },
+    models::{
+        chat::{ChatItem, Message, MessageType},
+        legacy::LegacyImport,
+        signup::EmailOwner,
+        user::{User, UserProfile},
+    },
+    repository::{dynamodb::DynamoChatRepository, ChatRepository},
+    utils::{
+        avatar::{Avatar, AvatarConfig, AvatarError},
+        signup::Signup,
+    },
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use serde_json::Value;
+use std::{env, sync::Arc};
+use tokio::sync::OnceCell;
+use tracing::{error, warn};
+use ulid::Ulid;
+
+/*---------- Constants ----------*/
+/// Shown by Cognito when the legacy credentials don't match, same as for a
+/// wrong password on a regular account.
+const INVALID_CREDENTIALS: &str = "Incorrect username or password.";
+
+/*---------- Structs ----------*/
+/// Generic over the legacy store so the trigger can be pointed at another
+/// source than the JSON export.
+struct TriggerContext<S: LegacyUserStore> {
+    s3_client: aws_sdk_s3::Client,
+    repository: Arc<dyn ChatRepository>,
+    legacy_store: S,
+    public_bucket_name: String,
+    public_bucket_domain: String,
+    avatar_config: AvatarConfig,
+}
+
+/// The JSON export in S3, downloaded the first time it's read. Most sign-ins
+/// are by users who never had a legacy account or whose history is already
+/// imported, and they never wait for it.
+struct LazyExportStore {
+    s3_client: aws_sdk_s3::Client,
+    bucket_name: String,
+    key: String,
+    store: OnceCell<JsonLegacyUserStore>,
+}
+
+impl LazyExportStore {
+    async fn store(&self) -> Result<&JsonLegacyUserStore, LegacyStoreError> {
+        // A failed download isn't kept, the next invocation tries again
+        self.store
+            .get_or_try_init(|| load_export(&self.s3_client, &self.bucket_name, &self.key))
+            .await
+    }
+}
+
+#[async_trait]
+impl LegacyUserStore for LazyExportStore {
+    async fn find_user(&self, login: &str) -> Result<Option<LegacyUser>, LegacyStoreError> {
+        self.store().await?.find_user(login).await
+    }
+
+    async fn authenticate(
+        &self,
+        login: &str,
+        password: &str,
+    ) -> Result<Option<LegacyUser>, LegacyStoreError> {
+        self.store().await?.authenticate(login, password).await
+    }
+
+    async fn list_conversations(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<LegacyConversation>, LegacyStoreError> {
+        self.store().await?.list_conversations(user_id).await
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let s3_client = aws_sdk_s3::Client::new(&config);
+
+    // PostAuthentication runs on every sign-in, so the export is only read
+    // for accounts that were migrated from it
+    let legacy_store = LazyExportStore {
+        s3_client: s3_client.clone(),
+        bucket_name: env::var("LEGACY_EXPORT_BUCKET").expect("LEGACY_EXPORT_BUCKET must be set"),
+        key: env::var("LEGACY_EXPORT_KEY").expect("LEGACY_EXPORT_KEY must be set"),
+        store: OnceCell::new(),
+    };
+
+    let context = TriggerContext {
+        s3_client,
+        repository: Arc::new(DynamoChatRepository::new(
+            aws_sdk_dynamodb::Client::new(&config),
+            &table_name,
+        )),
+        legacy_store,
+        public_bucket_name: env::var("PUBLIC_BUCKET").expect("PUBLIC_BUCKET must be set"),
+        public_bucket_domain: env::var("PUBLIC_BUCKET_DOMAIN")
+            .expect("PUBLIC_BUCKET_DOMAIN must be set"),
+        avatar_config: AvatarConfig::from_env(),
+    };
+    let handler = service_fn(|event| handler_fn(&context, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+async fn load_export(
+    s3_client: &aws_sdk_s3::Client,
+    bucket_name: &str,
+    key: &str,
+) -> Result<JsonLegacyUserStore, LegacyStoreError> {
+    let export_bytes = s3_client
+        .get_object()
+        .bucket(bucket_name)
+        .key(key)
+        .send()
+        .await
+        .map_err(|error| LegacyStoreError::Unavailable(error.to_string()))?
+        .body
+        .collect()
+        .await
+        .map_err(|error| LegacyStoreError::Unavailable(error.to_string()))?
+        .into_bytes();
+
+    JsonLegacyUserStore::from_slice(&export_bytes)
+}
+
+/// Handles both the UserMigration trigger, which creates the account from
+/// the legacy credentials, and the PostAuthentication one, which copies the
+/// conversations over on the first sign-in. The sub only exists once the
+/// migration has returned, so the import can't happen any earlier.
+async fn handler_fn(
+    context: &TriggerContext<impl LegacyUserStore>,
+    event: LambdaEvent<Value>,
+) -> Result<Value, Error> {
+    let trigger_source = event
+        .payload
+        .get("triggerSource")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_owned();
+
+    if trigger_source.starts_with("UserMigration_") {
+        let migrate_event = serde_json::from_value(event.payload)?;
+
+        return Ok(serde_json::to_value(
+            migrate_user(context, &trigger_source, migrate_event).await?,
+        )?);
+    }
+
+    // A failed import must not fail the sign-in itself
+    if trigger_source.starts_with("PostAuthentication_") {
+        let authentication_event = serde_json::from_value(event.payload.clone())?;
+
+        if let Err(error) = import_history(context, &authentication_event).await {
+            error!("Couldn't import the legacy history: {}", error);
+        }
+    }
+
+    Ok(event.payload)
+}
+
+async fn migrate_user(
+    context: &TriggerContext<impl LegacyUserStore>,
+    trigger_source: &str,
+    mut event: CognitoEventUserPoolsMigrateUser,
+) -> Result<CognitoEventUserPoolsMigrateUser, Error> {
+    let login = event
+        .cognito_event_user_pools_header
+        .user_name
+        .to_owned()
+        .unwrap_or_default();
+    let request = &event.cognito_event_user_pools_migrate_user_request;
+
+    let legacy_user = match (trigger_source, &request.password) {
+        ("UserMigration_Authentication", Some(password)) => {
+            context.legacy_store.authenticate(&login, password).await?
+        }
+        ("UserMigration_ForgotPassword", _) => context.legacy_store.find_user(&login).await?,
+        _ => None,
+    };
+
+    let legacy_user = match legacy_user {
+        Some(legacy_user) => legacy_user,
+        None => return Err(INVALID_CREDENTIALS.into()),
+    };
+
+    let response = &mut event.cognito_event_user_pools_migrate_user_response;
+
+    response.user_attributes = legacy_user.pool_attributes();
+    response.message_action = Some("SUPPRESS".to_owned());
+
+    // On a password reset Cognito sends a code and the user picks a new
+    // password, the old one is never checked
+    if trigger_source == "UserMigration_Authentication" {
+        response.final_user_status = Some("CONFIRMED".to_owned());
+    }
+
+    Ok(event)
+}
+
+async fn import_history(
+    context: &TriggerContext<impl LegacyUserStore>,
+    event: &CognitoEventUserPoolsPostAuthentication,
+) -> Result<(), Error> {
+    let user_attributes = &event.request.user_attributes;
+
+    let (legacy_id, user_sub) = match (
+        user_attributes.get(LEGACY_ID_ATTRIBUTE),
+        user_attributes.get("sub"),
+    ) {
+        (Some(legacy_id), Some(user_sub)) => (legacy_id, user_sub),
+        _ => return Ok(()),
+    };
+
+    if let Some(LegacyImport {
+        imported_at: Some(_),
+        ..
+    }) = context.repository.get_legacy_import(legacy_id).await?
+    {
+        return Ok(());
+    }
+
+    // The link is stored first, so a partner signing in while the copy runs
+    // already finds this sub and imports the conversation from their side
+    let mut legacy_import = LegacyImport::new(legacy_id, user_sub);
+
+    context
+        .repository
+        .save_legacy_import(&legacy_import)
+        .await?;
+
+    let user = User::new(
+        user_sub,
+        user_attributes
+            .get("name")
+            .map(String::as_str)
+            .unwrap_or_default(),
+        user_attributes
+            .get("email")
+            .map(String::as_str)
+            .unwrap_or_default(),
+    );
+
+    create_profile(context, &user).await?;
+
+    // The import is left unmarked on failure, so it's retried on the next
+    // sign-in. Message ids are derived from the legacy ones, retries
+    // overwrite instead of duplicating.
+    import_conversations(context, legacy_id, &user).await?;
+
+    legacy_import.imported_at = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true));
+    context
+        .repository
+        .save_legacy_import(&legacy_import)
+        .await?;
+
+    Ok(())
+}
+
+/// Migrated users skip PostConfirmation, so they get their profile, email
+/// claim and avatar here.
+async fn create_profile(
+    context: &TriggerContext<impl LegacyUserStore>,
+    user: &User,
+) -> Result<(), Error> {
+    if let Some(email) = Signup::normalize_email(&user.email) {
+        context
+            .repository
+            .claim_email(&EmailOwner::new(&email, &user.sub))
+            .await?;
+    }
+
+    let profile = UserProfile::new(
+        user,
+        &Avatar::url(&context.public_bucket_domain, &user.sub),
+        &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+    );
+
+    if !context.repository.create_profile(&profile).await? {
+        return Ok(());
+    }
+
+    match Avatar::upload_initials(
+        &context.s3_client,
+        &context.public_bucket_name,
+        &context.avatar_config,
+        &user.sub,
+        &user.name,
+    )
+    .await
+    {
+        Ok(()) | Err(AvatarError::RenderFailed) => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn import_conversations(
+    context: &TriggerContext<impl LegacyUserStore>,
+    legacy_id: &str,
+    user: &User,
+) -> Result<(), Error> {
+    let conversations = context.legacy_store.list_conversations(legacy_id).await?;
+
+    for conversation in conversations.iter() {
+        let other_id = match conversation.other_participant(legacy_id) {
+            Some(other_id) => other_id,
+            None => continue,
+        };
+
+        // Partners who haven't signed in yet import it themselves later
+        let other_sub = match context.repository.get_legacy_import(other_id).await? {
+            Some(other_import) => other_import.sub,
+            None => continue,
+        };
+
+        let other_user = match context.repository.get_profile(&other_sub).await? {
+            Some(profile) => User::new(&profile.sub, &profile.name, &profile.email),
+            None => continue,
+        };
+
+        import_conversation(context, conversation, legacy_id, user, &other_user).await?;
+    }
+
+    Ok(())
+}
+
+/// Writes the messages in batches, then both summaries once from the latest
+/// message. The history is old news, so neither side gets unread messages.
+async fn import_conversation(
+    context: &TriggerContext<impl LegacyUserStore>,
+    conversation: &LegacyConversation,
+    legacy_id: &str,
+    user: &User,
+    other_user: &User,
+) -> Result<(), Error> {
+    let mut messages: Vec<Message> = vec![];
+
+    for legacy_message in conversation.messages.iter() {
+        let (sender, receiver) = match legacy_message.sender_id == legacy_id {
+            true => (user, other_user),
+            false => (other_user, user),
+        };
+
+        let (message_id, timestamp) = match message_id_and_timestamp(legacy_message) {
+            Some(converted) => converted,
+            None => {
+                warn!("Skipping legacy message {}", legacy_message.id);
+                continue;
+            }
+        };
+
+        messages.push(Message::imported(
+            sender,
+            &receiver.sub,
+            &message_id,
+            &timestamp,
+            &legacy_message.text,
+            MessageType::Text,
+        ));
+    }
+
+    context.repository.save_messages(&messages).await?;
+
+    let last_message = match messages
+        .iter()
+        .max_by(|first, second| first.db_item.sort_key.cmp(&second.db_item.sort_key))
+    {
+        Some(last_message) => last_message,
+        None => return Ok(()),
+    };
+
+    for (owner, other) in [(user, other_user), (other_user, user)] {
+        let mut chat = ChatItem::private(owner, other, last_message);
+
+        chat.unread_messages = 0;
+        context.repository.upsert_chat_summary(&chat).await?;
+    }
+
+    Ok(())
+}
+
+/// A ULID carrying the original send time, with the random part derived
+/// from the legacy id so the same message always maps to the same key.
+fn message_id_and_timestamp(legacy_message: &LegacyMessage) -> Option<(String, String)> {
+    let sent_at = DateTime::parse_from_rfc3339(&legacy_message.sent_at)
+        .ok()?
+        .with_timezone(&Utc);
+
+    let legacy_hash = legacy_message
+        .id
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+    let message_id = Ulid::from_parts(sent_at.timestamp_millis() as u64, legacy_hash as u128);
+
+    Some((
+        message_id.to_string(),
+        sent_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chat_test_infra::repository::memory::InMemoryChatRepository;
+
+    /// A single legacy account with a plain text password.
+    struct SingleUserStore {
+        user: LegacyUser,
+        password: &'static str,
+    }
+
+    #[async_trait]
+    impl LegacyUserStore for SingleUserStore {
+        async fn find_user(&self, login: &str) -> Result<Option<LegacyUser>, LegacyStoreError> {
+            Ok((login == self.user.username).then(|| self.user.clone()))
+        }
+
+        async fn authenticate(
+            &self,
+            login: &str,
+            password: &str,
+        ) -> Result<Option<LegacyUser>, LegacyStoreError> {
+            Ok(self
+                .find_user(login)
+                .await?
+                .filter(|_| password == self.password))
+        }
+
+        async fn list_conversations(
+            &self,
+            _user_id: &str,
+        ) -> Result<Vec<LegacyConversation>, LegacyStoreError> {
+            Ok(vec![])
+        }
+    }
+
+    fn context() -> TriggerContext<SingleUserStore> {
+        TriggerContext {
+            s3_client: aws_sdk_s3::Client::from_conf(aws_sdk_s3::Config::builder().build()),
+            repository: Arc::new(InMemoryChatRepository::new()),
+            legacy_store: SingleUserStore {
+                user: LegacyUser {
+                    id: "legacy-1".to_owned(),
+                    username: "janedoe".to_owned(),
+                    email: "jane@example.com".to_owned(),
+                    display_name: Some("Jane".to_owned()),
+                    email_verified: true,
+                },
+                password: "correct horse",
+            },
+            public_bucket_name: "bucket".to_owned(),
+            public_bucket_domain: "bucket.example.com".to_owned(),
+            avatar_config: AvatarConfig::default(),
+        }
+    }
+
+    fn migrate_event(login: &str, password: Option<&str>) -> CognitoEventUserPoolsMigrateUser {
+        let mut event = CognitoEventUserPoolsMigrateUser::default();
+
+        event.cognito_event_user_pools_header.user_name = Some(login.to_owned());
+        event.cognito_event_user_pools_migrate_user_request.password = password.map(str::to_owned);
+
+        event
+    }
+
+    fn legacy_message(id: &str, sent_at: &str) -> LegacyMessage {
+        LegacyMessage {
+            id: id.to_owned(),
+            sender_id: "legacy-1".to_owned(),
+            text: "Hi".to_owned(),
+            sent_at: sent_at.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn authentication_confirms_the_migrated_user() {
+        let event = migrate_user(
+            &context(),
+            "UserMigration_Authentication",
+            migrate_event("janedoe", Some("correct horse")),
+        )
+        .await
+        .unwrap();
+
+        let response = event.cognito_event_user_pools_migrate_user_response;
+
+        assert_eq!(response.final_user_status.as_deref(), Some("CONFIRMED"));
+        assert_eq!(response.message_action.as_deref(), Some("SUPPRESS"));
+        assert_eq!(response.user_attributes[LEGACY_ID_ATTRIBUTE], "legacy-1");
+    }
+
+    #[tokio::test]
+    async fn forgotten_passwords_migrate_without_confirming() {
+        let event = migrate_user(
+            &context(),
+            "UserMigration_ForgotPassword",
+            migrate_event("janedoe", None),
+        )
+        .await
+        .unwrap();
+
+        let response = event.cognito_event_user_pools_migrate_user_response;
+
+        assert_eq!(response.final_user_status, None);
+        assert_eq!(response.user_attributes["name"], "Jane");
+    }
+
+    #[tokio::test]
+    async fn wrong_credentials_are_rejected_like_a_regular_account() {
+        for (login, password) in [("janedoe", "wrong"), ("johndoe", "correct horse")] {
+            let error = migrate_user(
+                &context(),
+                "UserMigration_Authentication",
+                migrate_event(login, Some(password)),
+            )
+            .await
+            .unwrap_err();
+
+            assert_eq!(error.to_string(), INVALID_CREDENTIALS);
+        }
+    }
+
+    #[tokio::test]
+    async fn conversations_are_imported_with_one_summary_per_participant() {
+        let context = context();
+        let jane = User::new("jane", "Jane", "jane@example.com");
+        let john = User::new("john", "John", "john@example.com");
+        let conversation = LegacyConversation {
+            participants: ["legacy-1".to_owned(), "legacy-2".to_owned()],
+            messages: vec![
+                legacy_message("a", "2020-05-04T08:00:00Z"),
+                LegacyMessage {
+                    sender_id: "legacy-2".to_owned(),
+                    text: "Hello".to_owned(),
+                    ..legacy_message("b", "2020-05-04T08:01:00Z")
+                },
+                legacy_message("c", "not a date"),
+            ],
+        };
+
+        for _ in 0..2 {
+            import_conversation(&context, &conversation, "legacy-1", &jane, &john)
+                .await
+                .unwrap();
+        }
+
+        let messages = context
+            .repository
+            .list_private_messages("jane", "john")
+            .await
+            .unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert!(messages
+            .iter()
+            .all(|message| message.db_item.entity_type == "importedMessage"));
+        assert_eq!(messages[1].user.sub, "john");
+
+        for (owner_sub, other_sub, title) in [("jane", "john", "John"), ("john", "jane", "Jane")] {
+            let chat = context
+                .repository
+                .get_private_chat(owner_sub, other_sub)
+                .await
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(chat.title, title);
+            assert_eq!(chat.unread_messages, 0);
+            assert_eq!(chat.last_message.preview, "Hello");
+            assert_eq!(chat.last_message.timestamp, "2020-05-04T08:01:00.000Z");
+        }
+    }
+
+    #[test]
+    fn message_ids_are_the_same_on_every_import() {
+        let message = legacy_message("legacy-message-1", "2020-05-04T10:00:00+02:00");
+
+        let first_import = message_id_and_timestamp(&message).unwrap();
+        let second_import = message_id_and_timestamp(&message).unwrap();
+
+        assert_eq!(first_import, second_import);
+        assert_eq!(first_import.1, "2020-05-04T08:00:00.000Z");
+        assert_eq!(
+            Ulid::from_string(&first_import.0).unwrap().timestamp_ms(),
+            1588579200000
+        );
+    }
+
+    #[test]
+    fn message_ids_differ_per_legacy_message_and_keep_the_send_order() {
+        let first = message_id_and_timestamp(&legacy_message("a", "2020-05-04T08:00:00Z")).unwrap();
+        let same_time =
+            message_id_and_timestamp(&legacy_message("b", "2020-05-04T08:00:00Z")).unwrap();
+        let later = message_id_and_timestamp(&legacy_message("a", "2020-05-04T08:00:01Z")).unwrap();
+
+        assert_ne!(first.0, same_time.0);
+        assert!(first.0 < later.0);
+        assert!(message_id_and_timestamp(&legacy_message("c", "yesterday")).is_none());
+    }
+}
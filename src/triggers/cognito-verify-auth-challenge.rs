@@ -0,0 +1,37 @@
+/*---------- Imports ----------*/
+use aws_lambda_events::cognito::CognitoEventUserPoolsVerifyAuthChallenge;
+use chat_test_infra::{repository::dynamodb::DynamoChatRepository, utils::otp::LoginCodes};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+use serde_json::Value;
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let table_name = env::var("TABLE_NAME").expect("TABLE_NAME must be set");
+    let repository = DynamoChatRepository::new(aws_sdk_dynamodb::Client::new(&config), &table_name);
+    let handler = service_fn(|event| handler_fn(&repository, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+async fn handler_fn(
+    repository: &DynamoChatRepository,
+    mut event: LambdaEvent<CognitoEventUserPoolsVerifyAuthChallenge>,
+) -> Result<CognitoEventUserPoolsVerifyAuthChallenge, Error> {
+    let request = &event.payload.request;
+
+    let answer_correct = match (
+        request.user_attributes.get("sub"),
+        request.challenge_answer.as_ref().and_then(Value::as_str),
+    ) {
+        (Some(user_sub), Some(answer)) => LoginCodes::verify(repository, user_sub, answer).await?,
+        _ => false,
+    };
+
+    event.payload.response.answer_correct = answer_correct;
+
+    Ok(event.payload)
+}
@@ -0,0 +1,29 @@
+/*---------- Imports ----------*/
+use aws_config::SdkConfig;
+use aws_lambda_events::event::streams::DynamoDbEventResponse;
+use chat_test_infra::{handlers, models::stream::StreamEvent};
+use lambda_runtime::{service_fn, Error, LambdaEvent};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    let handler = service_fn(|event| handler_fn(&config, event));
+
+    lambda_runtime::run(handler).await?;
+
+    Ok(())
+}
+
+async fn handler_fn(
+    config: &SdkConfig,
+    event: LambdaEvent<StreamEvent>,
+) -> Result<DynamoDbEventResponse, Error> {
+    // The event source only forwards inserted export items
+    for record in event.payload.records.iter() {
+        handlers::handle_export_record(record, config).await;
+    }
+
+    Ok(DynamoDbEventResponse {
+        batch_item_failures: vec![],
+    })
+}
@@ -0,0 +1,658 @@
+/*---------- Imports ----------*/
+use crate::models::{
+    chat::ChatView,
+    deletion::{DeletionProgress, DeletionStatus, DeletionStep},
+    user::User as UserModel,
+};
+use crate::repository::{ChatListQuery, ChatRepository};
+use crate::storage::ObjectStore;
+use crate::utils::{
+    avatar::{Avatar, AvatarConfig},
+    signup::Signup,
+};
+use aws_sdk_cognitoidentityprovider::types::SdkError;
+use chrono::{SecondsFormat, Utc};
+
+/*---------- Constants ----------*/
+pub const DELETED_USER_SUB: &str = "deleted-user";
+pub const DELETED_USER_NAME: &str = "Deleted user";
+
+/*---------- Enums ----------*/
+#[derive(Debug)]
+pub enum DeletionError {
+    ProgressUnavailable,
+    StepFailed(DeletionStep),
+}
+
+impl std::fmt::Display for DeletionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeletionError::ProgressUnavailable => {
+                write!(f, "Couldn't read or store the deletion progress")
+            }
+            DeletionError::StepFailed(step) => write!(f, "Deletion step \"{}\" failed", step),
+        }
+    }
+}
+
+impl std::error::Error for DeletionError {}
+
+/*---------- Structs ----------*/
+/// Stores, clients and resource names needed to run the deletion cascade.
+pub struct AccountDeletion<'a> {
+    pub repository: &'a dyn ChatRepository,
+    pub object_store: &'a dyn ObjectStore,
+    pub cognito_client: &'a aws_sdk_cognitoidentityprovider::Client,
+    pub public_bucket_name: &'a str,
+    pub export_bucket_name: &'a str,
+    pub userpool_id: &'a str,
+    pub avatar_config: &'a AvatarConfig,
+}
+
+pub struct Account;
+
+fn anonymized_user() -> UserModel {
+    UserModel::new(DELETED_USER_SUB, DELETED_USER_NAME, "")
+}
+
+/// Lists the subs of everyone the user has a private chat summary with,
+/// hidden and archived chats included.
+async fn list_chat_partners(options: &AccountDeletion<'_>, sub: &str) -> Result<Vec<String>, ()> {
+    let mut partners: Vec<String> = vec![];
+    let mut query = ChatListQuery {
+        view: ChatView::All,
+        ..ChatListQuery::default()
+    };
+
+    loop {
+        let page = options
+            .repository
+            .list_chats(sub, &query)
+            .await
+            .map_err(|_| ())?;
+
+        partners.extend(
+            page.items
+                .iter()
+                .filter_map(|chat| chat.other_sub())
+                .map(|other_sub| other_sub.to_owned()),
+        );
+
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => return Ok(partners),
+        }
+    }
+}
+
+/// Replaces the embedded profile on every message the user authored, so the
+/// other participant keeps the conversation without the personal data.
+async fn anonymize_messages(options: &AccountDeletion<'_>, sub: &str) -> Result<(), ()> {
+    for partner_sub in list_chat_partners(options, sub).await? {
+        let messages = options
+            .repository
+            .list_private_messages(sub, &partner_sub)
+            .await
+            .map_err(|_| ())?;
+
+        for mut message in messages
+            .into_iter()
+            .filter(|message| message.user.sub == sub)
+        {
+            message.user = anonymized_user();
+
+            options
+                .repository
+                .save_message(&message)
+                .await
+                .map_err(|_| ())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Anonymizes the copies of the user's profile embedded in the other
+/// participants' chat summaries, then removes the user's own summaries.
+async fn remove_chat_summaries(options: &AccountDeletion<'_>, sub: &str) -> Result<(), ()> {
+    for partner_sub in list_chat_partners(options, sub).await? {
+        options
+            .repository
+            .replace_chat_participant(&partner_sub, sub, &anonymized_user())
+            .await
+            .map_err(|_| ())?;
+
+        options
+            .repository
+            .delete_chat_summary(sub, &partner_sub)
+            .await
+            .map_err(|_| ())?;
+    }
+
+    Ok(())
+}
+
+/// Removes every export archive before the job pointing to it, so a retry
+/// after a partial failure can still find the archives left.
+async fn delete_exports(options: &AccountDeletion<'_>, sub: &str) -> Result<(), ()> {
+    let jobs = options
+        .repository
+        .list_export_jobs(sub)
+        .await
+        .map_err(|_| ())?;
+
+    for job in jobs {
+        options
+            .object_store
+            .delete(options.export_bucket_name, &job.archive_key())
+            .await
+            .map_err(|_| ())?;
+
+        options
+            .repository
+            .delete_export_job(&job)
+            .await
+            .map_err(|_| ())?;
+    }
+
+    Ok(())
+}
+
+/// The code goes first, the counters only slow down new sign-ins.
+async fn delete_login_code(options: &AccountDeletion<'_>, sub: &str) -> Result<(), ()> {
+    options
+        .repository
+        .delete_login_code(sub)
+        .await
+        .map_err(|_| ())?;
+
+    options
+        .repository
+        .delete_login_code_quotas(sub)
+        .await
+        .map_err(|_| ())
+}
+
+/// Invitations the user sent and nobody accepted yet. They carry the user's
+/// name and email, and would deliver messages from a deleted account.
+async fn delete_invitations(options: &AccountDeletion<'_>, sub: &str) -> Result<(), ()> {
+    let invitations = options
+        .repository
+        .list_sent_invitations(sub)
+        .await
+        .map_err(|_| ())?;
+
+    for invitation in invitations {
+        options
+            .repository
+            .delete_invitation(&invitation.email, sub)
+            .await
+            .map_err(|_| ())?;
+    }
+
+    Ok(())
+}
+
+async fn delete_legacy_link(options: &AccountDeletion<'_>, sub: &str) -> Result<(), ()> {
+    let legacy_import = options
+        .repository
+        .find_legacy_import(sub)
+        .await
+        .map_err(|_| ())?;
+
+    if let Some(legacy_import) = legacy_import {
+        options
+            .repository
+            .delete_legacy_import(&legacy_import.legacy_id)
+            .await
+            .map_err(|_| ())?;
+    }
+
+    Ok(())
+}
+
+async fn delete_avatar(options: &AccountDeletion<'_>, sub: &str) -> Result<(), ()> {
+    for avatar_key in Avatar::keys(options.avatar_config, sub) {
+        options
+            .object_store
+            .delete(options.public_bucket_name, &avatar_key)
+            .await
+            .map_err(|_| ())?;
+    }
+
+    Ok(())
+}
+
+/// Releases the user's email, read from the profile that's removed in the
+/// next step. Someone else owning the address is fine, there's nothing to
+/// release then.
+async fn release_email(options: &AccountDeletion<'_>, sub: &str) -> Result<(), ()> {
+    let profile = options.repository.get_profile(sub).await.map_err(|_| ())?;
+
+    let email = profile.and_then(|profile| Signup::normalize_email(&profile.email));
+
+    if let Some(email) = email {
+        options
+            .repository
+            .release_email(&email, sub)
+            .await
+            .map_err(|_| ())?;
+    }
+
+    Ok(())
+}
+
+async fn delete_cognito_user(options: &AccountDeletion<'_>, username: &str) -> Result<(), ()> {
+    let delete_result = options
+        .cognito_client
+        .admin_delete_user()
+        .user_pool_id(options.userpool_id)
+        .username(username)
+        .send()
+        .await;
+
+    match delete_result {
+        Ok(_) => Ok(()),
+        Err(SdkError::ServiceError { err, .. }) if err.is_user_not_found_exception() => Ok(()),
+        Err(_) => Err(()),
+    }
+}
+
+async fn run_step(
+    options: &AccountDeletion<'_>,
+    step: DeletionStep,
+    sub: &str,
+    username: Option<&str>,
+) -> Result<(), ()> {
+    let repository = options.repository;
+
+    match step {
+        DeletionStep::Connection => repository.delete_connection(sub).await.map_err(|_| ()),
+        DeletionStep::Messages => anonymize_messages(options, sub).await,
+        DeletionStep::ChatSummaries => remove_chat_summaries(options, sub).await,
+        DeletionStep::Exports => delete_exports(options, sub).await,
+        DeletionStep::LoginCode => delete_login_code(options, sub).await,
+        DeletionStep::ProfileUpdates => {
+            repository.delete_profile_updates(sub).await.map_err(|_| ())
+        }
+        DeletionStep::InviteQuota => repository.delete_invite_quotas(sub).await.map_err(|_| ()),
+        DeletionStep::Invitations => delete_invitations(options, sub).await,
+        DeletionStep::LegacyLink => delete_legacy_link(options, sub).await,
+        DeletionStep::Avatar => delete_avatar(options, sub).await,
+        DeletionStep::EmailClaim => release_email(options, sub).await,
+        DeletionStep::Profile => repository.delete_profile(sub).await.map_err(|_| ()),
+        DeletionStep::Account => match username {
+            Some(username_value) => delete_cognito_user(options, username_value).await,
+            None => Ok(()),
+        },
+    }
+}
+
+impl Account {
+    /// Runs the deletion cascade for `sub`, skipping the steps a previous run
+    /// already completed. Progress is stored on the `user#<sub>` / `deletion`
+    /// item after every step, so a failed run can simply be retried.
+    ///
+    /// When `username` is `None` the Cognito account is assumed to be gone
+    /// already and the `Account` step is recorded without calling Cognito.
+    pub async fn delete(
+        options: &AccountDeletion<'_>,
+        sub: &str,
+        username: Option<&str>,
+    ) -> Result<DeletionProgress, (DeletionProgress, DeletionError)> {
+        let mut progress = match options.repository.get_deletion_progress(sub).await {
+            Ok(progress) => progress.unwrap_or_else(|| DeletionProgress::new(sub)),
+            Err(_) => {
+                return Err((
+                    DeletionProgress::new(sub),
+                    DeletionError::ProgressUnavailable,
+                ))
+            }
+        };
+
+        for step in DeletionStep::ALL {
+            if progress.completed_steps.contains(&step) {
+                continue;
+            }
+
+            if run_step(options, step, sub, username).await.is_err() {
+                return Err((progress, DeletionError::StepFailed(step)));
+            }
+
+            progress.completed_steps.push(step);
+            progress.updated_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+            if progress.completed_steps.len() == DeletionStep::ALL.len() {
+                progress.status = DeletionStatus::Completed;
+            }
+
+            if options
+                .repository
+                .save_deletion_progress(&progress)
+                .await
+                .is_err()
+            {
+                return Err((progress, DeletionError::ProgressUnavailable));
+            }
+        }
+
+        Ok(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        chat::{ChatItem, Message, MessageType},
+        connection::Connection,
+        export::ExportJob,
+        invite::{Invitation, InviteQuota, QueuedMessage},
+        keys,
+        legacy::LegacyImport,
+        otp::{LoginCode, LoginCodeQuota},
+        signup::EmailOwner,
+        user::{ProfileUpdate, UserProfile},
+    };
+    use crate::repository::memory::InMemoryChatRepository;
+    use crate::storage::memory::InMemoryObjectStore;
+
+    const USER_SUB: &str = "user-1";
+    const PARTNER_SUB: &str = "user-2";
+    const PUBLIC_BUCKET: &str = "public";
+    const EXPORT_BUCKET: &str = "exports";
+    const TIMESTAMP: &str = "2023-01-01T00:00:00.000Z";
+
+    struct Fixture {
+        repository: InMemoryChatRepository,
+        object_store: InMemoryObjectStore,
+        cognito_client: aws_sdk_cognitoidentityprovider::Client,
+        avatar_config: AvatarConfig,
+    }
+
+    impl Fixture {
+        /// A user with a chat in both directions and one of every item the
+        /// cascade removes.
+        async fn new() -> Self {
+            let repository = InMemoryChatRepository::new();
+            let object_store = InMemoryObjectStore::new();
+            let avatar_config = AvatarConfig::default();
+            let user = UserModel::new(USER_SUB, "Jane", "Jane.Doe+chat@gmail.com");
+            let partner = UserModel::new(PARTNER_SUB, "John", "john@example.com");
+
+            let sent = Message::private(
+                &user,
+                PARTNER_SUB,
+                "01A",
+                TIMESTAMP,
+                "Hi",
+                MessageType::Text,
+            );
+            let received = Message::private(
+                &partner,
+                USER_SUB,
+                "01B",
+                TIMESTAMP,
+                "Hey",
+                MessageType::Text,
+            );
+
+            repository.save_message(&sent).await.unwrap();
+            repository.save_message(&received).await.unwrap();
+            repository
+                .upsert_chat_summary(&ChatItem::private(&user, &partner, &sent))
+                .await
+                .unwrap();
+            repository
+                .upsert_chat_summary(&ChatItem::private(&partner, &user, &sent))
+                .await
+                .unwrap();
+
+            repository
+                .create_profile(&UserProfile::new(&user, "", TIMESTAMP))
+                .await
+                .unwrap();
+            repository
+                .claim_email(&EmailOwner::new("janedoe@gmail.com", USER_SUB))
+                .await
+                .unwrap();
+            repository
+                .save_connection(&Connection::new(USER_SUB, "connection-1"))
+                .await
+                .unwrap();
+            repository
+                .save_login_code(&LoginCode::new(USER_SUB, "hash", 0))
+                .await
+                .unwrap();
+            repository
+                .record_login_code_issue(&LoginCodeQuota::new(USER_SUB, "2023-01-01T10", 0), 5)
+                .await
+                .unwrap();
+            repository
+                .save_profile_update(&ProfileUpdate::new(&user, "01C", TIMESTAMP))
+                .await
+                .unwrap();
+            repository
+                .record_invite(&InviteQuota::new(USER_SUB, "2023-01-01", 0), 10)
+                .await
+                .unwrap();
+            repository
+                .save_legacy_import(&LegacyImport::new("legacy-1", USER_SUB))
+                .await
+                .unwrap();
+
+            for inviter in [&user, &partner] {
+                repository
+                    .queue_invitation_message(
+                        &Invitation::new("friend@example.com", inviter, TIMESTAMP),
+                        &QueuedMessage {
+                            message_id: "01E".to_owned(),
+                            content: "Join us".to_owned(),
+                            timestamp: TIMESTAMP.to_owned(),
+                        },
+                        20,
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            let job = ExportJob::new(USER_SUB, "01D", TIMESTAMP);
+
+            repository.save_export_job(&job).await.unwrap();
+            object_store.put(EXPORT_BUCKET, &job.archive_key(), b"zip");
+
+            for avatar_key in Avatar::keys(&avatar_config, USER_SUB) {
+                object_store.put(PUBLIC_BUCKET, &avatar_key, b"image");
+            }
+
+            Self {
+                repository,
+                object_store,
+                cognito_client: aws_sdk_cognitoidentityprovider::Client::from_conf(
+                    aws_sdk_cognitoidentityprovider::Config::builder().build(),
+                ),
+                avatar_config,
+            }
+        }
+
+        async fn delete(&self) -> Result<DeletionProgress, (DeletionProgress, DeletionError)> {
+            let options = AccountDeletion {
+                repository: &self.repository,
+                object_store: &self.object_store,
+                cognito_client: &self.cognito_client,
+                public_bucket_name: PUBLIC_BUCKET,
+                export_bucket_name: EXPORT_BUCKET,
+                userpool_id: "userpool",
+                avatar_config: &self.avatar_config,
+            };
+
+            Account::delete(&options, USER_SUB, None).await
+        }
+
+        /// Sort keys of everything left under `user#<sub>`.
+        fn user_sort_keys(&self) -> Vec<String> {
+            let partition_key = keys::user_key(USER_SUB);
+
+            self.repository
+                .items()
+                .iter()
+                .filter(|item| item["partitionKey"] == partition_key.as_str())
+                .filter_map(|item| item["sortKey"].as_str().map(|key| key.to_owned()))
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn removes_everything_the_user_owns() {
+        let fixture = Fixture::new().await;
+        let progress = fixture.delete().await.unwrap();
+
+        assert_eq!(progress.status, DeletionStatus::Completed);
+        assert_eq!(progress.completed_steps, DeletionStep::ALL.to_vec());
+        assert_eq!(fixture.user_sort_keys(), vec![keys::DELETION_KEY]);
+        assert!(fixture
+            .repository
+            .get_email_owner("janedoe@gmail.com")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(fixture
+            .repository
+            .get_legacy_import("legacy-1")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(fixture.object_store.keys(PUBLIC_BUCKET).is_empty());
+        assert!(fixture.object_store.keys(EXPORT_BUCKET).is_empty());
+
+        // Someone else's invitation to the same address stays
+        let invitations = fixture
+            .repository
+            .list_invitations("friend@example.com")
+            .await
+            .unwrap();
+
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].inviter.sub, PARTNER_SUB);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_conversation_for_the_other_participant() {
+        let fixture = Fixture::new().await;
+
+        fixture.delete().await.unwrap();
+
+        let messages = fixture
+            .repository
+            .list_private_messages(USER_SUB, PARTNER_SUB)
+            .await
+            .unwrap();
+        let authors: Vec<&str> = messages
+            .iter()
+            .map(|message| message.user.sub.as_str())
+            .collect();
+
+        assert_eq!(authors, vec![DELETED_USER_SUB, PARTNER_SUB]);
+        assert_eq!(messages[0].user.email, "");
+
+        let chat = fixture
+            .repository
+            .get_private_chat(PARTNER_SUB, USER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(chat.title, DELETED_USER_NAME);
+        assert_eq!(chat.last_message.user_name, DELETED_USER_NAME);
+        assert_eq!(chat.last_message.user_sub, DELETED_USER_SUB);
+    }
+
+    #[tokio::test]
+    async fn leaves_an_email_claimed_by_someone_else() {
+        let fixture = Fixture::new().await;
+
+        fixture
+            .repository
+            .release_email("janedoe@gmail.com", USER_SUB)
+            .await
+            .unwrap();
+        fixture
+            .repository
+            .claim_email(&EmailOwner::new("janedoe@gmail.com", "user-3"))
+            .await
+            .unwrap();
+
+        fixture.delete().await.unwrap();
+
+        let owner = fixture
+            .repository
+            .get_email_owner("janedoe@gmail.com")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(owner.sub, "user-3");
+    }
+
+    #[tokio::test]
+    async fn resumes_after_the_step_that_failed() {
+        let fixture = Fixture::new().await;
+
+        fixture.object_store.set_unavailable(true);
+
+        let (progress, error) = fixture.delete().await.unwrap_err();
+
+        assert!(matches!(
+            error,
+            DeletionError::StepFailed(DeletionStep::Exports)
+        ));
+        assert_eq!(progress.status, DeletionStatus::InProgress);
+        assert_eq!(
+            progress.completed_steps,
+            vec![
+                DeletionStep::Connection,
+                DeletionStep::Messages,
+                DeletionStep::ChatSummaries,
+            ]
+        );
+
+        let stored_progress = fixture
+            .repository
+            .get_deletion_progress(USER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(stored_progress.completed_steps, progress.completed_steps);
+
+        // Completed steps don't run again, so a connection opened in between
+        // is left alone
+        fixture
+            .repository
+            .save_connection(&Connection::new(USER_SUB, "connection-2"))
+            .await
+            .unwrap();
+        fixture.object_store.set_unavailable(false);
+
+        let progress = fixture.delete().await.unwrap();
+
+        assert_eq!(progress.status, DeletionStatus::Completed);
+        assert_eq!(
+            fixture.user_sort_keys(),
+            vec![keys::CONNECTION_KEY, keys::DELETION_KEY]
+        );
+        assert!(fixture.object_store.keys(EXPORT_BUCKET).is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_completed_deletion_is_not_run_again() {
+        let fixture = Fixture::new().await;
+
+        fixture.delete().await.unwrap();
+        fixture.object_store.set_unavailable(true);
+
+        let progress = fixture.delete().await.unwrap();
+
+        assert_eq!(progress.status, DeletionStatus::Completed);
+    }
+}
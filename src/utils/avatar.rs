@@ -0,0 +1,519 @@
+/*---------- Imports ----------*/
+use aws_sdk_s3::types::ByteStream;
+use image::{
+    codecs::webp::WebPEncoder, imageops::FilterType, io::Reader as ImageReader, ColorType,
+    DynamicImage, ImageOutputFormat,
+};
+use initials_revamped::{AvatarBuilder, AvatarResult};
+use std::{env, io::Cursor};
+
+/*---------- Constants ----------*/
+/// Uploads bigger than this are rejected before being downloaded.
+pub const MAX_UPLOAD_BYTES: i64 = 5 * 1024 * 1024;
+/// Guards against decompression bombs, checked from the header alone.
+const MAX_SOURCE_DIMENSION: u32 = 8192;
+const DEFAULT_SIZES: [u32; 3] = [64, 128, 512];
+const DEFAULT_FONT_COLOR: &str = "#ffffff";
+/// 250px on the original 512px avatar.
+const DEFAULT_FONT_RATIO: f32 = 250.0 / 512.0;
+const DEFAULT_PALETTE: [&str; 8] = [
+    "#e53935", "#d81b60", "#8e24aa", "#3949ab", "#1e88e5", "#00897b", "#43a047", "#f4511e",
+];
+const DEFAULT_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/*---------- Enums ----------*/
+#[derive(Debug)]
+pub enum AvatarError {
+    RenderFailed,
+    InvalidImage,
+    UploadFailed,
+}
+
+impl std::fmt::Display for AvatarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AvatarError::RenderFailed => write!(f, "Couldn't render the avatar"),
+            AvatarError::InvalidImage => write!(f, "The uploaded file isn't a valid image"),
+            AvatarError::UploadFailed => write!(f, "Couldn't upload the avatar"),
+        }
+    }
+}
+
+impl std::error::Error for AvatarError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AvatarFormat {
+    Png,
+    WebP,
+}
+
+impl AvatarFormat {
+    /// Every format written for each size.
+    pub const ALL: [AvatarFormat; 2] = [AvatarFormat::Png, AvatarFormat::WebP];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AvatarFormat::Png => "png",
+            AvatarFormat::WebP => "webp",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            AvatarFormat::Png => "image/png",
+            AvatarFormat::WebP => "image/webp",
+        }
+    }
+}
+
+/*---------- Structs ----------*/
+/// How avatars look and how they are stored.
+#[derive(Debug, Clone)]
+pub struct AvatarConfig {
+    /// Square sizes written for every avatar. The largest one is also the
+    /// canonical `user/<sub>.png`.
+    pub sizes: Vec<u32>,
+    pub font_color: String,
+    /// Font size relative to the side of the image.
+    pub font_ratio: f32,
+    /// Initials backgrounds, one is picked from the user's sub.
+    pub palette: Vec<String>,
+    pub cache_control: String,
+}
+
+impl Default for AvatarConfig {
+    fn default() -> Self {
+        Self {
+            sizes: DEFAULT_SIZES.to_vec(),
+            font_color: DEFAULT_FONT_COLOR.to_owned(),
+            font_ratio: DEFAULT_FONT_RATIO,
+            palette: DEFAULT_PALETTE
+                .iter()
+                .map(|color| color.to_string())
+                .collect(),
+            cache_control: DEFAULT_CACHE_CONTROL.to_owned(),
+        }
+    }
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|entry| entry.trim().to_owned())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+impl AvatarConfig {
+    /// Reads `AVATAR_SIZES` and `AVATAR_PALETTE` (comma separated),
+    /// `AVATAR_FONT_COLOR`, `AVATAR_FONT_RATIO` and `AVATAR_CACHE_CONTROL`,
+    /// falling back to the defaults for anything unset or invalid.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        let sizes: Vec<u32> = env::var("AVATAR_SIZES")
+            .map(|value| {
+                parse_list(&value)
+                    .iter()
+                    .filter_map(|size| size.parse::<u32>().ok())
+                    .filter(|size| (1..=MAX_SOURCE_DIMENSION).contains(size))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let palette = env::var("AVATAR_PALETTE")
+            .map(|value| parse_list(&value))
+            .unwrap_or_default();
+
+        let font_ratio = env::var("AVATAR_FONT_RATIO")
+            .ok()
+            .and_then(|value| value.parse::<f32>().ok())
+            .filter(|ratio| *ratio > 0.0 && *ratio <= 1.0);
+
+        Self {
+            sizes: if sizes.is_empty() {
+                defaults.sizes
+            } else {
+                sizes
+            },
+            font_color: env::var("AVATAR_FONT_COLOR").unwrap_or(defaults.font_color),
+            font_ratio: font_ratio.unwrap_or(defaults.font_ratio),
+            palette: if palette.is_empty() {
+                defaults.palette
+            } else {
+                palette
+            },
+            cache_control: env::var("AVATAR_CACHE_CONTROL").unwrap_or(defaults.cache_control),
+        }
+    }
+
+    pub fn largest_size(&self) -> u32 {
+        self.sizes.iter().copied().max().unwrap_or(DEFAULT_SIZES[2])
+    }
+
+    /// Background for `user_sub`. FNV-1a is used instead of the std hasher
+    /// because its output must not change between builds, or every user
+    /// would get a new color on the next deploy.
+    pub fn background_color(&self, user_sub: &str) -> &str {
+        let hash = user_sub.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+        match self.palette.len() {
+            0 => DEFAULT_PALETTE[0],
+            palette_len => &self.palette[(hash % palette_len as u64) as usize],
+        }
+    }
+}
+
+pub struct Avatar;
+
+fn avatar(config: &AvatarConfig, user_sub: &str, user_name: &str, size: u32) -> AvatarResult {
+    AvatarBuilder::new(user_name)
+        .with_font_scale(size as f32 * config.font_ratio)?
+        .with_font_color(&config.font_color)?
+        .with_background_color(config.background_color(user_sub))?
+        .with_width(size)?
+        .with_height(size)
+}
+
+fn encode(image: &DynamicImage, format: AvatarFormat) -> Result<Vec<u8>, AvatarError> {
+    let mut image_bytes: Vec<u8> = Vec::new();
+
+    match format {
+        AvatarFormat::Png => image
+            .write_to(&mut Cursor::new(&mut image_bytes), ImageOutputFormat::Png)
+            .map_err(|_| AvatarError::RenderFailed)?,
+        AvatarFormat::WebP => {
+            let rgba_image = image.to_rgba8();
+
+            WebPEncoder::new_lossless(&mut image_bytes)
+                .encode(
+                    rgba_image.as_raw(),
+                    rgba_image.width(),
+                    rgba_image.height(),
+                    ColorType::Rgba8,
+                )
+                .map_err(|_| AvatarError::RenderFailed)?
+        }
+    }
+
+    Ok(image_bytes)
+}
+
+impl Avatar {
+    /// S3 key of a user's avatar in the public bucket, at the largest size.
+    pub fn key(user_sub: &str) -> String {
+        format!("user/{}.png", user_sub)
+    }
+
+    /// Public URL of a user's avatar, given the domain of the public bucket.
+    pub fn url(bucket_domain: &str, user_sub: &str) -> String {
+        format!("https://{}/{}", bucket_domain, Self::key(user_sub))
+    }
+
+    /// S3 key of one of the sized copies, e.g. `user/<sub>-128.webp`.
+    pub fn variant_key(user_sub: &str, size: u32, format: AvatarFormat) -> String {
+        format!("user/{}-{}.{}", user_sub, size, format.extension())
+    }
+
+    /// Every key written for a user's avatar, canonical ones first.
+    pub fn keys(config: &AvatarConfig, user_sub: &str) -> Vec<String> {
+        let mut avatar_keys: Vec<String> = AvatarFormat::ALL
+            .iter()
+            .map(|format| format!("user/{}.{}", user_sub, format.extension()))
+            .collect();
+
+        for size in config.sizes.iter() {
+            for format in AvatarFormat::ALL {
+                avatar_keys.push(Self::variant_key(user_sub, *size, format));
+            }
+        }
+
+        avatar_keys
+    }
+
+    /// Renders the initials avatar for `user_name` at `size`, on the
+    /// background picked for `user_sub`.
+    pub fn render_initials(
+        config: &AvatarConfig,
+        user_sub: &str,
+        user_name: &str,
+        size: u32,
+    ) -> Result<DynamicImage, AvatarError> {
+        let avatar_result =
+            avatar(config, user_sub, user_name, size).map_err(|_| AvatarError::RenderFailed)?;
+
+        Ok(DynamicImage::from(avatar_result.draw()))
+    }
+
+    /// Decodes an uploaded picture, center-crops it to a square and scales it
+    /// to the largest configured size.
+    pub fn normalize(
+        config: &AvatarConfig,
+        image_bytes: &[u8],
+    ) -> Result<DynamicImage, AvatarError> {
+        let image_reader = ImageReader::new(Cursor::new(image_bytes))
+            .with_guessed_format()
+            .map_err(|_| AvatarError::InvalidImage)?;
+
+        if image_reader.format().is_none() {
+            return Err(AvatarError::InvalidImage);
+        }
+
+        let (width, height) = image_reader
+            .into_dimensions()
+            .map_err(|_| AvatarError::InvalidImage)?;
+
+        if width == 0 || height == 0 || width.max(height) > MAX_SOURCE_DIMENSION {
+            return Err(AvatarError::InvalidImage);
+        }
+
+        let mut source_image =
+            image::load_from_memory(image_bytes).map_err(|_| AvatarError::InvalidImage)?;
+
+        let side = width.min(height);
+        let cropped_image = source_image.crop((width - side) / 2, (height - side) / 2, side, side);
+        let target_size = config.largest_size();
+
+        Ok(cropped_image.resize_exact(target_size, target_size, FilterType::Lanczos3))
+    }
+
+    /// Writes every size of the avatar in every format. The largest one is
+    /// also stored under the canonical `user/<sub>.<ext>` keys.
+    async fn upload_renditions(
+        s3_client: &aws_sdk_s3::Client,
+        bucket_name: &str,
+        config: &AvatarConfig,
+        user_sub: &str,
+        renditions: Vec<(u32, DynamicImage)>,
+    ) -> Result<(), AvatarError> {
+        let largest_size = config.largest_size();
+
+        for (size, image) in renditions {
+            for format in AvatarFormat::ALL {
+                let image_bytes = encode(&image, format)?;
+                let mut keys = vec![Self::variant_key(user_sub, size, format)];
+
+                if size == largest_size {
+                    keys.push(format!("user/{}.{}", user_sub, format.extension()));
+                }
+
+                for key in keys {
+                    s3_client
+                        .put_object()
+                        .bucket(bucket_name)
+                        .key(key)
+                        .content_type(format.content_type())
+                        .cache_control(&config.cache_control)
+                        .body(ByteStream::from(image_bytes.clone()))
+                        .send()
+                        .await
+                        .map_err(|_| AvatarError::UploadFailed)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scales `image` down to every configured size and uploads the result.
+    pub async fn upload(
+        s3_client: &aws_sdk_s3::Client,
+        bucket_name: &str,
+        config: &AvatarConfig,
+        user_sub: &str,
+        image: &DynamicImage,
+    ) -> Result<(), AvatarError> {
+        let renditions = config
+            .sizes
+            .iter()
+            .map(|size| {
+                (
+                    *size,
+                    image.resize_exact(*size, *size, FilterType::Lanczos3),
+                )
+            })
+            .collect();
+
+        Self::upload_renditions(s3_client, bucket_name, config, user_sub, renditions).await
+    }
+
+    /// Renders the initials avatar at every size and uploads it, replacing
+    /// any custom picture the user had. Each size is drawn on its own so the
+    /// small ones stay crisp.
+    pub async fn upload_initials(
+        s3_client: &aws_sdk_s3::Client,
+        bucket_name: &str,
+        config: &AvatarConfig,
+        user_sub: &str,
+        user_name: &str,
+    ) -> Result<(), AvatarError> {
+        let mut renditions: Vec<(u32, DynamicImage)> = vec![];
+
+        for size in config.sizes.iter() {
+            renditions.push((
+                *size,
+                Self::render_initials(config, user_sub, user_name, *size)?,
+            ));
+        }
+
+        Self::upload_renditions(s3_client, bucket_name, config, user_sub, renditions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, ImageFormat};
+
+    #[test]
+    fn background_color_is_stable_per_sub() {
+        let config = AvatarConfig::default();
+        let color = config.background_color("a1b2c3d4-0000-4000-8000-000000000000");
+
+        for _ in 0..10 {
+            assert_eq!(
+                config.background_color("a1b2c3d4-0000-4000-8000-000000000000"),
+                color
+            );
+        }
+
+        // Pinned so a change of hash shows up before every user gets a new
+        // color
+        assert_eq!(color, DEFAULT_PALETTE[5]);
+    }
+
+    #[test]
+    fn background_color_spreads_over_the_palette() {
+        let config = AvatarConfig::default();
+        let mut used_colors: Vec<&str> = (0..200)
+            .map(|index| config.background_color(&format!("user-{}", index)))
+            .collect();
+
+        used_colors.sort_unstable();
+        used_colors.dedup();
+
+        assert_eq!(used_colors.len(), DEFAULT_PALETTE.len());
+    }
+
+    #[test]
+    fn background_color_falls_back_on_an_empty_palette() {
+        let config = AvatarConfig {
+            palette: vec![],
+            ..AvatarConfig::default()
+        };
+
+        assert_eq!(config.background_color("any"), DEFAULT_PALETTE[0]);
+    }
+
+    #[test]
+    fn initials_are_rendered_at_every_size() {
+        let config = AvatarConfig::default();
+
+        for size in config.sizes.iter() {
+            let image = Avatar::render_initials(&config, "sub", "Jane Doe", *size).unwrap();
+
+            assert_eq!(image.dimensions(), (*size, *size));
+        }
+
+        assert_eq!(config.largest_size(), 512);
+    }
+
+    #[test]
+    fn keys_cover_every_size_and_format() {
+        let config = AvatarConfig {
+            sizes: vec![32, 256],
+            ..AvatarConfig::default()
+        };
+
+        assert_eq!(
+            Avatar::keys(&config, "sub"),
+            vec![
+                "user/sub.png",
+                "user/sub.webp",
+                "user/sub-32.png",
+                "user/sub-32.webp",
+                "user/sub-256.png",
+                "user/sub-256.webp",
+            ]
+        );
+    }
+
+    #[test]
+    fn renditions_are_encoded_as_png_and_webp() {
+        let config = AvatarConfig::default();
+        let image = Avatar::render_initials(&config, "sub", "Jane Doe", 64).unwrap();
+
+        for (format, image_format) in [
+            (AvatarFormat::Png, ImageFormat::Png),
+            (AvatarFormat::WebP, ImageFormat::WebP),
+        ] {
+            let image_bytes = encode(&image, format).unwrap();
+
+            assert_eq!(image::guess_format(&image_bytes).unwrap(), image_format);
+            assert_eq!(
+                image::load_from_memory_with_format(&image_bytes, image_format)
+                    .unwrap()
+                    .dimensions(),
+                (64, 64)
+            );
+        }
+    }
+
+    #[test]
+    fn renditions_match_the_golden_images() {
+        // Set UPDATE_AVATAR_FIXTURES=1 to rewrite the fixtures after an
+        // intended change of the rendering
+        let fixtures_dir =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/avatars");
+        let update_fixtures = env::var("UPDATE_AVATAR_FIXTURES").is_ok();
+        let config = AvatarConfig::default();
+
+        for size in DEFAULT_SIZES {
+            let image = Avatar::render_initials(
+                &config,
+                "a1b2c3d4-0000-4000-8000-000000000000",
+                "Jane Doe",
+                size,
+            )
+            .unwrap();
+
+            for format in AvatarFormat::ALL {
+                let fixture_path = fixtures_dir.join(format!("{}.{}", size, format.extension()));
+                let image_bytes = encode(&image, format).unwrap();
+
+                if update_fixtures {
+                    std::fs::create_dir_all(&fixtures_dir).unwrap();
+                    std::fs::write(&fixture_path, &image_bytes).unwrap();
+                    continue;
+                }
+
+                let golden_bytes = std::fs::read(&fixture_path).unwrap();
+                let golden_image = image::load_from_memory(&golden_bytes).unwrap();
+                let rendered_image = image::load_from_memory(&image_bytes).unwrap();
+
+                assert!(
+                    rendered_image.to_rgba8() == golden_image.to_rgba8(),
+                    "{} differs from the rendered avatar",
+                    fixture_path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uploads_are_cropped_to_a_square_of_the_largest_size() {
+        let config = AvatarConfig::default();
+        let source_image = DynamicImage::new_rgb8(300, 200);
+        let source_bytes = encode(&source_image, AvatarFormat::Png).unwrap();
+
+        let image = Avatar::normalize(&config, &source_bytes).unwrap();
+
+        assert_eq!(image.dimensions(), (512, 512));
+        assert!(matches!(
+            Avatar::normalize(&config, b"not an image"),
+            Err(AvatarError::InvalidImage)
+        ));
+    }
+}
@@ -0,0 +1,41 @@
+/*---------- Imports ----------*/
+use aws_config::SdkConfig;
+use std::env;
+
+pub struct AwsClients;
+
+impl AwsClients {
+    /// DynamoDB client for the main table. `DYNAMODB_ENDPOINT` points it
+    /// somewhere else than the regional endpoint, like DynamoDB Local, while
+    /// every other client keeps talking to AWS.
+    pub fn dynamodb(config: &SdkConfig) -> aws_sdk_dynamodb::Client {
+        let endpoint = match env::var("DYNAMODB_ENDPOINT") {
+            Ok(endpoint) => endpoint,
+            Err(_) => return aws_sdk_dynamodb::Client::new(config),
+        };
+
+        let dynamodb_config = aws_sdk_dynamodb::config::Builder::from(config)
+            .endpoint_resolver(aws_sdk_dynamodb::Endpoint::immutable(
+                endpoint.parse().expect("Failed to parse DynamoDB endpoint"),
+            ))
+            .build();
+
+        aws_sdk_dynamodb::Client::from_conf(dynamodb_config)
+    }
+
+    /// Client of the `@connections` API of a WebSocket stage.
+    pub fn websocket_management(
+        config: &SdkConfig,
+        endpoint: &str,
+    ) -> aws_sdk_apigatewaymanagement::Client {
+        let apigtw_client_config = aws_sdk_apigatewaymanagement::config::Builder::from(config)
+            .endpoint_resolver(aws_sdk_apigatewaymanagement::Endpoint::immutable(
+                endpoint
+                    .parse()
+                    .expect("Failed to parse WebSocket endpoint"),
+            ))
+            .build();
+
+        aws_sdk_apigatewaymanagement::Client::from_conf(apigtw_client_config)
+    }
+}
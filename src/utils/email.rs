@@ -0,0 +1,345 @@
+/*---------- Imports ----------*/
+use serde::Deserialize;
+
+/*---------- Constants ----------*/
+const HTML_LAYOUT: &str = include_str!("emails/layout.html");
+const TEXT_LAYOUT: &str = include_str!("emails/layout.txt");
+const CODE_SNIPPET: &str = include_str!("emails/code.html");
+const LINK_SNIPPET: &str = include_str!("emails/link.html");
+const EN_STRINGS: &str = include_str!("emails/en.json");
+const ES_STRINGS: &str = include_str!("emails/es.json");
+
+/*---------- Enums ----------*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailKind {
+    Verification,
+    PasswordReset,
+    Invitation,
+    LoginCode,
+    /// Sent by the app when someone writes to an address without an account.
+    ChatInvite,
+}
+
+impl EmailKind {
+    /// The email sent for a CustomMessage trigger source, if it's one we
+    /// brand. Anything else keeps Cognito's default message.
+    pub fn from_trigger_source(trigger_source: &str) -> Option<Self> {
+        match trigger_source {
+            "CustomMessage_SignUp" | "CustomMessage_ResendCode" => Some(Self::Verification),
+            "CustomMessage_ForgotPassword" => Some(Self::PasswordReset),
+            "CustomMessage_AdminCreateUser" => Some(Self::Invitation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl Locale {
+    /// Picks the locale from a tag such as `es`, `es-AR` or `es_ES`, falling
+    /// back to English for anything without a translation.
+    pub fn from_tag(tag: &str) -> Self {
+        let language = tag
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+
+        match language.as_str() {
+            "es" => Self::Es,
+            _ => Self::En,
+        }
+    }
+
+    pub fn language(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Es => "es",
+        }
+    }
+
+    fn strings(&self) -> LocaleStrings {
+        let source = match self {
+            Self::En => EN_STRINGS,
+            Self::Es => ES_STRINGS,
+        };
+
+        serde_json::from_str(source).expect("Bundled email strings must be valid")
+    }
+}
+
+/*---------- Structs ----------*/
+#[derive(Deserialize, Debug)]
+struct EmailStrings {
+    subject: String,
+    heading: String,
+    body: String,
+    /// Label of the button, for emails carrying a link.
+    #[serde(default)]
+    action: String,
+    footer: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LocaleStrings {
+    verification: EmailStrings,
+    password_reset: EmailStrings,
+    invitation: EmailStrings,
+    login_code: EmailStrings,
+    chat_invite: EmailStrings,
+}
+
+/// Values filled into the templates. For the CustomMessage trigger `code`
+/// and `username` are the placeholders Cognito hands over, it swaps them for
+/// the real values after rendering and rejects messages missing them.
+#[derive(Debug, Clone)]
+pub struct EmailVariables {
+    pub app_name: String,
+    pub name: String,
+    pub avatar_url: String,
+    pub code: String,
+    pub username: String,
+    /// Shown as a button in place of the code when set.
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+pub struct Email;
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Replaces every `{{key}}` in `template` in a single pass, so values
+/// that happen to contain a placeholder are left as they are. Unknown keys
+/// are kept, escaping is up to the caller.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let key_end = match rest.find("}}") {
+            Some(key_end) => key_end,
+            None => break,
+        };
+
+        let key = &rest[2..key_end];
+
+        match values.iter().find(|(value_key, _)| *value_key == key) {
+            Some((_, value)) => filled.push_str(value),
+            None => filled.push_str(&rest[..key_end + 2]),
+        }
+
+        rest = &rest[key_end + 2..];
+    }
+
+    filled.push_str(rest);
+    filled
+}
+
+impl Email {
+    pub fn render(kind: EmailKind, locale: Locale, variables: &EmailVariables) -> RenderedEmail {
+        let locale_strings = locale.strings();
+        let strings = match kind {
+            EmailKind::Verification => &locale_strings.verification,
+            EmailKind::PasswordReset => &locale_strings.password_reset,
+            EmailKind::Invitation => &locale_strings.invitation,
+            EmailKind::LoginCode => &locale_strings.login_code,
+            EmailKind::ChatInvite => &locale_strings.chat_invite,
+        };
+
+        let plain_values = [
+            ("app_name", variables.app_name.as_str()),
+            ("name", variables.name.as_str()),
+            ("username", variables.username.as_str()),
+        ];
+
+        let escaped_app_name = escape_html(&variables.app_name);
+        let escaped_name = escape_html(&variables.name);
+        let html_values = [
+            ("app_name", escaped_app_name.as_str()),
+            ("name", escaped_name.as_str()),
+            // Cognito replaces the placeholder after rendering, escaping it
+            // would leave it in place
+            ("username", variables.username.as_str()),
+        ];
+
+        let subject = fill(&strings.subject, &plain_values);
+
+        let escaped_subject = escape_html(&subject);
+        let escaped_avatar_url = escape_html(&variables.avatar_url);
+        let html_heading = fill(&strings.heading, &html_values);
+        let html_body = fill(&strings.body, &html_values);
+        let html_footer = fill(&strings.footer, &html_values);
+
+        let (html_highlight, text_highlight) = match &variables.link {
+            Some(link) => {
+                let escaped_link = escape_html(link);
+                let html_action = fill(&strings.action, &html_values);
+                let html_highlight = fill(
+                    LINK_SNIPPET.trim_end(),
+                    &[
+                        ("link", escaped_link.as_str()),
+                        ("action", html_action.as_str()),
+                    ],
+                );
+
+                (html_highlight, link.to_owned())
+            }
+            None => (
+                fill(
+                    CODE_SNIPPET.trim_end(),
+                    &[("code", variables.code.as_str())],
+                ),
+                variables.code.to_owned(),
+            ),
+        };
+
+        let html = fill(
+            HTML_LAYOUT,
+            &[
+                ("lang", locale.language()),
+                ("subject", escaped_subject.as_str()),
+                ("avatar_url", escaped_avatar_url.as_str()),
+                ("heading", html_heading.as_str()),
+                ("body", html_body.as_str()),
+                ("footer", html_footer.as_str()),
+                ("highlight", html_highlight.as_str()),
+            ],
+        );
+
+        let text_heading = fill(&strings.heading, &plain_values);
+        let text_body = fill(&strings.body, &plain_values);
+
+        let text = fill(
+            TEXT_LAYOUT.trim_end(),
+            &[
+                ("heading", text_heading.as_str()),
+                ("body", text_body.as_str()),
+                ("highlight", text_highlight.as_str()),
+            ],
+        );
+
+        RenderedEmail {
+            subject,
+            html,
+            text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(name: &str) -> EmailVariables {
+        EmailVariables {
+            app_name: "ChatApp".to_owned(),
+            name: name.to_owned(),
+            avatar_url: "https://bucket.example.com/user/sub.png".to_owned(),
+            code: "{####}".to_owned(),
+            username: "{username}".to_owned(),
+            link: None,
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_everywhere() {
+        let email = Email::render(EmailKind::Verification, Locale::En, &variables("Jane"));
+
+        assert_eq!(email.subject, "Verify your ChatApp account");
+        assert!(email.html.contains("Welcome, Jane!"));
+        assert!(email
+            .html
+            .contains("https://bucket.example.com/user/sub.png"));
+        assert!(email.text.starts_with("Welcome, Jane!"));
+        assert!(!email.html.contains("{{"));
+        assert!(!email.text.contains("{{"));
+    }
+
+    #[test]
+    fn names_are_escaped_in_html_only() {
+        let email = Email::render(
+            EmailKind::ChatInvite,
+            Locale::En,
+            &EmailVariables {
+                link: Some("https://app.example.com/?a=1&b=2".to_owned()),
+                ..variables("<b>Jane</b> & \"co\"")
+            },
+        );
+
+        assert!(email
+            .html
+            .contains("&lt;b&gt;Jane&lt;/b&gt; &amp; &quot;co&quot;"));
+        assert!(!email.html.contains("<b>Jane</b>"));
+        assert!(email.html.contains("https://app.example.com/?a=1&amp;b=2"));
+        assert!(email.text.contains("<b>Jane</b> & \"co\""));
+        assert_eq!(email.subject, "<b>Jane</b> & \"co\" invited you to ChatApp");
+    }
+
+    #[test]
+    fn values_containing_placeholders_are_not_filled_again() {
+        assert_eq!(
+            fill("{{a}} {{b}} {{c}}", &[("a", "{{b}}"), ("b", "x")]),
+            "{{b}} x {{c}}"
+        );
+    }
+
+    #[test]
+    fn cognito_placeholders_survive_rendering() {
+        let email = Email::render(EmailKind::Invitation, Locale::En, &variables("Jane"));
+
+        assert!(email.html.contains("{####}"));
+        assert!(email.html.contains("{username}"));
+        assert!(email.text.contains("{####}"));
+    }
+
+    #[test]
+    fn locale_tags_fall_back_to_english() {
+        assert_eq!(Locale::from_tag("es"), Locale::Es);
+        assert_eq!(Locale::from_tag("es-AR"), Locale::Es);
+        assert_eq!(Locale::from_tag("ES_es"), Locale::Es);
+        assert_eq!(Locale::from_tag("fr-FR"), Locale::En);
+        assert_eq!(Locale::from_tag(""), Locale::En);
+    }
+
+    #[test]
+    fn every_kind_is_translated() {
+        let kinds = [
+            EmailKind::Verification,
+            EmailKind::PasswordReset,
+            EmailKind::Invitation,
+            EmailKind::LoginCode,
+            EmailKind::ChatInvite,
+        ];
+
+        for kind in kinds {
+            let english = Email::render(kind, Locale::En, &variables("Jane"));
+            let spanish = Email::render(kind, Locale::Es, &variables("Jane"));
+
+            assert_ne!(english.subject, spanish.subject);
+            assert!(spanish.html.contains("lang=\"es\""));
+        }
+    }
+}
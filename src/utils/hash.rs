@@ -0,0 +1,25 @@
+/*---------- Imports ----------*/
+use sha2::{Digest, Sha256};
+
+pub struct Hash;
+
+impl Hash {
+    /// Hex encoded SHA-256 of `value`.
+    pub fn sha256_hex(value: &str) -> String {
+        Sha256::digest(value)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Compares every byte so the time taken doesn't tell how much of a
+    /// secret matched.
+    pub fn constant_time_eq(first: &[u8], second: &[u8]) -> bool {
+        first.len() == second.len()
+            && first
+                .iter()
+                .zip(second.iter())
+                .fold(0, |difference, (a, b)| difference | (a ^ b))
+                == 0
+    }
+}
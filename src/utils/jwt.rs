@@ -15,10 +15,14 @@ pub struct Jwt;
 
 impl Jwt {
     pub fn decode_payload<T: DeserializeOwned>(token: &str) -> Result<T, String> {
-        let token_sections: Vec<&str> = token.split(".").collect();
-        let payload_section = token_sections[1];
+        let payload_section = token
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| "Couldn't decode the token".to_owned())?;
 
-        let decoding_result = general_purpose::STANDARD_NO_PAD.decode(payload_section);
+        // JWT sections are base64url, which the standard alphabet rejects as
+        // soon as the payload encodes to a `-` or `_`
+        let decoding_result = general_purpose::URL_SAFE_NO_PAD.decode(payload_section);
 
         if let Ok(decoded) = decoding_result {
             if let Ok(result) = serde_json::from_slice::<T>(&decoded) {
@@ -42,3 +46,46 @@ impl Jwt {
         Self::decode_payload::<User>(&auth_payload.principal_id).ok()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn token(payload: &serde_json::Value) -> String {
+        format!(
+            "header.{}.signature",
+            general_purpose::URL_SAFE_NO_PAD.encode(payload.to_string())
+        )
+    }
+
+    #[test]
+    fn payloads_encoding_to_url_safe_characters_are_decoded() {
+        // `???` encodes to `Pz8_` and `~~~` to `fn5-`, neither of which the
+        // standard alphabet accepts
+        let token = token(&json!({
+            "sub": "user-1",
+            "name": "Jane ??? ~~~",
+            "email": "jane@example.com",
+        }));
+        let payload_section = token.split('.').nth(1).unwrap();
+
+        assert!(payload_section.contains('-') && payload_section.contains('_'));
+        assert_eq!(
+            Jwt::decode_payload::<User>(&token).unwrap(),
+            User::new("user-1", "Jane ??? ~~~", "jane@example.com")
+        );
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in [
+            "",
+            "no-sections",
+            "header.%%%.signature",
+            "header.e30.signature",
+        ] {
+            assert!(Jwt::decode_payload::<User>(token).is_err(), "{:?}", token);
+        }
+    }
+}
@@ -1,3 +1,11 @@
+pub mod account;
+pub mod avatar;
+pub mod aws;
+pub mod email;
+pub mod hash;
 pub mod http;
 pub mod jwt;
+pub mod otp;
+pub mod signup;
 pub mod user;
+pub mod welcome;
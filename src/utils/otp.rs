@@ -0,0 +1,238 @@
+/*---------- Imports ----------*/
+use crate::{
+    models::otp::{LoginCode, LoginCodeQuota},
+    repository::{ChatRepository, RepositoryError},
+    utils::hash::Hash,
+};
+use chrono::Utc;
+use rand::Rng;
+
+/*---------- Constants ----------*/
+/// How long a sign-in code can be used, also mentioned in the email.
+pub const LOGIN_CODE_TTL_SECS: i64 = 5 * 60;
+/// Answers accepted for a single code before it's thrown away.
+pub const MAX_LOGIN_CODE_ATTEMPTS: u32 = 3;
+/// Codes emailed to one user per UTC hour. Anyone who knows the address can
+/// start a sign-in, so this is what keeps them from flooding the mailbox.
+pub const MAX_LOGIN_CODES_PER_HOUR: u32 = 5;
+/// How long an hour's count is kept, past the end of the hour.
+const LOGIN_CODE_QUOTA_TTL_SECS: i64 = 2 * 60 * 60;
+
+pub struct LoginCodes;
+
+impl LoginCodes {
+    /// The sub is part of the hash, so the same code issued to two users
+    /// doesn't hash the same.
+    fn hash(user_sub: &str, code: &str) -> String {
+        Hash::sha256_hex(&format!("{}:{}", user_sub, code))
+    }
+
+    /// Stores a new six digit code for the user and returns it in clear, to
+    /// be sent to them. Any previous code stops working. Returns `None`, and
+    /// leaves the current code alone, once `MAX_LOGIN_CODES_PER_HOUR` were
+    /// issued.
+    pub async fn issue(
+        repository: &impl ChatRepository,
+        user_sub: &str,
+    ) -> Result<Option<String>, RepositoryError> {
+        let now = Utc::now();
+        let quota = LoginCodeQuota::new(
+            user_sub,
+            &now.format("%Y-%m-%dT%H").to_string(),
+            now.timestamp() + LOGIN_CODE_QUOTA_TTL_SECS,
+        );
+
+        if !repository
+            .record_login_code_issue(&quota, MAX_LOGIN_CODES_PER_HOUR)
+            .await?
+        {
+            return Ok(None);
+        }
+
+        let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+        let login_code = LoginCode::new(
+            user_sub,
+            &Self::hash(user_sub, &code),
+            now.timestamp() + LOGIN_CODE_TTL_SECS,
+        );
+
+        repository.save_login_code(&login_code).await?;
+
+        Ok(Some(code))
+    }
+
+    /// Whether `answer` is the user's current code. The attempt is counted
+    /// before comparing, so parallel guesses can't get past the limit, and
+    /// the code is removed once used or out of attempts.
+    pub async fn verify(
+        repository: &impl ChatRepository,
+        user_sub: &str,
+        answer: &str,
+    ) -> Result<bool, RepositoryError> {
+        let login_code = match repository.get_login_code(user_sub).await? {
+            Some(login_code) if login_code.expires_at > Utc::now().timestamp() => login_code,
+            _ => return Ok(false),
+        };
+
+        let attempts = match repository.record_login_code_attempt(user_sub).await? {
+            Some(attempts) => attempts,
+            None => return Ok(false),
+        };
+
+        let is_correct = attempts <= MAX_LOGIN_CODE_ATTEMPTS
+            && Hash::constant_time_eq(
+                Self::hash(user_sub, answer.trim()).as_bytes(),
+                login_code.code_hash.as_bytes(),
+            );
+
+        if is_correct || attempts >= MAX_LOGIN_CODE_ATTEMPTS {
+            repository.delete_login_code(user_sub).await?;
+        }
+
+        Ok(is_correct)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::memory::InMemoryChatRepository;
+
+    const USER_SUB: &str = "sub";
+
+    #[tokio::test]
+    async fn codes_work_once() {
+        let repository = InMemoryChatRepository::new();
+        let code = LoginCodes::issue(&repository, USER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(
+            LoginCodes::verify(&repository, USER_SUB, &format!(" {} ", code))
+                .await
+                .unwrap()
+        );
+        assert!(!LoginCodes::verify(&repository, USER_SUB, &code)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn the_last_attempt_can_still_succeed() {
+        let repository = InMemoryChatRepository::new();
+        let code = LoginCodes::issue(&repository, USER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        for _ in 1..MAX_LOGIN_CODE_ATTEMPTS {
+            assert!(!LoginCodes::verify(&repository, USER_SUB, "wrong")
+                .await
+                .unwrap());
+        }
+
+        assert!(LoginCodes::verify(&repository, USER_SUB, &code)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn codes_are_dropped_once_out_of_attempts() {
+        let repository = InMemoryChatRepository::new();
+        let code = LoginCodes::issue(&repository, USER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        for _ in 0..MAX_LOGIN_CODE_ATTEMPTS {
+            assert!(!LoginCodes::verify(&repository, USER_SUB, "wrong")
+                .await
+                .unwrap());
+        }
+
+        assert!(repository.get_login_code(USER_SUB).await.unwrap().is_none());
+        assert!(!LoginCodes::verify(&repository, USER_SUB, &code)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn expired_codes_are_rejected() {
+        let repository = InMemoryChatRepository::new();
+        let login_code = LoginCode::new(
+            USER_SUB,
+            &LoginCodes::hash(USER_SUB, "123456"),
+            Utc::now().timestamp() - 1,
+        );
+
+        repository.save_login_code(&login_code).await.unwrap();
+
+        assert!(!LoginCodes::verify(&repository, USER_SUB, "123456")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn new_codes_replace_older_ones() {
+        let repository = InMemoryChatRepository::new();
+        let first_code = LoginCodes::issue(&repository, USER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+        let second_code = LoginCodes::issue(&repository, USER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        if first_code != second_code {
+            assert!(!LoginCodes::verify(&repository, USER_SUB, &first_code)
+                .await
+                .unwrap());
+        }
+
+        assert!(LoginCodes::verify(&repository, USER_SUB, &second_code)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn issuing_stops_at_the_hourly_limit() {
+        let repository = InMemoryChatRepository::new();
+        let mut last_code = String::new();
+
+        for _ in 0..MAX_LOGIN_CODES_PER_HOUR {
+            last_code = LoginCodes::issue(&repository, USER_SUB)
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        assert_eq!(
+            LoginCodes::issue(&repository, USER_SUB).await.unwrap(),
+            None
+        );
+        assert!(LoginCodes::issue(&repository, "other-sub")
+            .await
+            .unwrap()
+            .is_some());
+
+        // The code sent last keeps working
+        assert!(LoginCodes::verify(&repository, USER_SUB, &last_code)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn codes_belong_to_a_single_user() {
+        let repository = InMemoryChatRepository::new();
+        let code = LoginCodes::issue(&repository, USER_SUB)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(!LoginCodes::verify(&repository, "other-sub", &code)
+            .await
+            .unwrap());
+    }
+}
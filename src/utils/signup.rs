@@ -0,0 +1,314 @@
+/*---------- Imports ----------*/
+use crate::models::signup::{DomainMode, SignupPolicy};
+
+/*---------- Constants ----------*/
+const DISPOSABLE_DOMAINS: &str = include_str!("disposable-domains.txt");
+
+/// Providers that deliver mail regardless of some variations of the local
+/// part: `(domain, canonical domain, ignores dots)`. All of them drop
+/// anything after a `+`.
+const PROVIDER_RULES: [(&str, &str, bool); 7] = [
+    ("gmail.com", "gmail.com", true),
+    ("googlemail.com", "gmail.com", true),
+    ("outlook.com", "outlook.com", false),
+    ("hotmail.com", "hotmail.com", false),
+    ("live.com", "live.com", false),
+    ("proton.me", "proton.me", false),
+    ("protonmail.com", "proton.me", false),
+];
+
+/*---------- Enums ----------*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum SignupRejection {
+    InvalidEmail,
+    BlockedAddress,
+    DomainNotAllowed,
+    DisposableEmail,
+    AlreadyRegistered,
+    /// The rules couldn't be read, sign-ups are refused rather than let
+    /// through unchecked.
+    Unavailable,
+}
+
+/// Shown to the user as is by the hosted UI and the Amplify clients.
+impl std::fmt::Display for SignupRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignupRejection::InvalidEmail => write!(f, "Please enter a valid email address."),
+            SignupRejection::BlockedAddress => {
+                write!(f, "This email address can't be used to sign up.")
+            }
+            SignupRejection::DomainNotAllowed => {
+                write!(f, "Sign-ups with this email domain aren't allowed.")
+            }
+            SignupRejection::DisposableEmail => write!(
+                f,
+                "Disposable email addresses aren't allowed, please use a permanent one."
+            ),
+            SignupRejection::AlreadyRegistered => {
+                write!(f, "An account already exists for this email address.")
+            }
+            SignupRejection::Unavailable => {
+                write!(
+                    f,
+                    "Sign-ups are unavailable right now, please try again later."
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SignupRejection {}
+
+pub struct Signup;
+
+fn matches_domain(domain: &str, rule: &str) -> bool {
+    let rule = rule.trim().trim_start_matches('@').to_lowercase();
+
+    !rule.is_empty() && (domain == rule || domain.ends_with(&format!(".{}", rule)))
+}
+
+fn is_disposable(domain: &str) -> bool {
+    DISPOSABLE_DOMAINS
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .any(|disposable_domain| matches_domain(domain, disposable_domain))
+}
+
+impl Signup {
+    /// Lowercases the address and, for the providers in `PROVIDER_RULES`,
+    /// removes the parts of the local part they ignore, so every alias of a
+    /// mailbox maps to the same string.
+    pub fn normalize_email(email: &str) -> Option<String> {
+        let email = email.trim().to_lowercase();
+        let (local_part, domain) = email.rsplit_once('@')?;
+
+        if local_part.is_empty() || !domain.contains('.') || domain.starts_with('.') {
+            return None;
+        }
+
+        let provider_rule = PROVIDER_RULES
+            .iter()
+            .find(|(provider_domain, _, _)| *provider_domain == domain);
+
+        let (local_part, domain) = match provider_rule {
+            Some((_, canonical_domain, ignores_dots)) => {
+                let mut local_part = local_part.split('+').next().unwrap_or_default().to_owned();
+
+                if *ignores_dots {
+                    local_part = local_part.replace('.', "");
+                }
+
+                (local_part, canonical_domain.to_string())
+            }
+            None => (local_part.to_owned(), domain.to_owned()),
+        };
+
+        if local_part.is_empty() {
+            return None;
+        }
+
+        Some(format!("{}@{}", local_part, domain))
+    }
+
+    /// Applies `policy` to `email`, returning the normalized address when the
+    /// sign-up can go ahead.
+    pub fn check(policy: &SignupPolicy, email: &str) -> Result<String, SignupRejection> {
+        let normalized_email = Self::normalize_email(email).ok_or(SignupRejection::InvalidEmail)?;
+        let (_, domain) = normalized_email
+            .rsplit_once('@')
+            .ok_or(SignupRejection::InvalidEmail)?;
+
+        let is_blocked = policy.blocked_addresses.iter().any(|blocked_address| {
+            Self::normalize_email(blocked_address).as_deref() == Some(normalized_email.as_str())
+        });
+
+        if is_blocked {
+            return Err(SignupRejection::BlockedAddress);
+        }
+
+        let is_listed = policy
+            .domains
+            .iter()
+            .any(|listed_domain| matches_domain(domain, listed_domain));
+
+        let domain_allowed = match policy.mode {
+            DomainMode::Open => true,
+            DomainMode::Allowlist => is_listed,
+            DomainMode::Denylist => !is_listed,
+        };
+
+        if !domain_allowed {
+            return Err(SignupRejection::DomainNotAllowed);
+        }
+
+        // An explicitly allowed domain wins over the bundled list
+        let explicitly_allowed = policy.mode == DomainMode::Allowlist && is_listed;
+
+        if policy.block_disposable && !explicitly_allowed && is_disposable(domain) {
+            return Err(SignupRejection::DisposableEmail);
+        }
+
+        Ok(normalized_email)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::signup::EmailOwner,
+        repository::{memory::InMemoryChatRepository, ChatRepository},
+    };
+
+    fn policy(mode: DomainMode, domains: &[&str], blocked_addresses: &[&str]) -> SignupPolicy {
+        SignupPolicy {
+            mode,
+            domains: domains.iter().map(|domain| domain.to_string()).collect(),
+            blocked_addresses: blocked_addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect(),
+            ..SignupPolicy::default()
+        }
+    }
+
+    #[test]
+    fn addresses_are_normalized_per_provider() {
+        let cases = [
+            ("Jane.Doe@Example.com", Some("jane.doe@example.com")),
+            ("  jane@example.com ", Some("jane@example.com")),
+            ("jane+chat@example.com", Some("jane+chat@example.com")),
+            ("Jane.Doe+chat@gmail.com", Some("janedoe@gmail.com")),
+            ("j.a.n.e@googlemail.com", Some("jane@gmail.com")),
+            ("jane.doe+chat@outlook.com", Some("jane.doe@outlook.com")),
+            ("jane+chat@protonmail.com", Some("jane@proton.me")),
+            ("jane", None),
+            ("@example.com", None),
+            ("jane@localhost", None),
+            ("jane@.com", None),
+            ("+chat@gmail.com", None),
+            ("...@gmail.com", None),
+            ("", None),
+        ];
+
+        for (email, expected) in cases {
+            assert_eq!(
+                Signup::normalize_email(email).as_deref(),
+                expected,
+                "normalizing {:?}",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn policies_allow_and_deny_addresses() {
+        let open = SignupPolicy::default();
+        let allowlist = policy(
+            DomainMode::Allowlist,
+            &["example.com", "@mailinator.com"],
+            &[],
+        );
+        let denylist = policy(DomainMode::Denylist, &["competitor.com"], &[]);
+        let blocked = policy(DomainMode::Open, &[], &["Jane.Doe@gmail.com"]);
+        let disposable_allowed = SignupPolicy {
+            block_disposable: false,
+            ..SignupPolicy::default()
+        };
+
+        let cases: [(&SignupPolicy, &str, Result<&str, SignupRejection>); 16] = [
+            (&open, "Jane@Example.com", Ok("jane@example.com")),
+            (
+                &open,
+                "jane@yopmail.com",
+                Err(SignupRejection::DisposableEmail),
+            ),
+            (
+                &open,
+                "jane@eu.mailinator.com",
+                Err(SignupRejection::DisposableEmail),
+            ),
+            (&open, "not-an-email", Err(SignupRejection::InvalidEmail)),
+            (&open, "jane@localhost", Err(SignupRejection::InvalidEmail)),
+            (&allowlist, "jane@example.com", Ok("jane@example.com")),
+            (
+                &allowlist,
+                "jane@team.example.com",
+                Ok("jane@team.example.com"),
+            ),
+            (&allowlist, "jane@mailinator.com", Ok("jane@mailinator.com")),
+            (
+                &allowlist,
+                "jane@example.org",
+                Err(SignupRejection::DomainNotAllowed),
+            ),
+            (
+                &allowlist,
+                "jane@notexample.com",
+                Err(SignupRejection::DomainNotAllowed),
+            ),
+            (&denylist, "jane@example.com", Ok("jane@example.com")),
+            (
+                &denylist,
+                "jane@eu.competitor.com",
+                Err(SignupRejection::DomainNotAllowed),
+            ),
+            (
+                &blocked,
+                "janedoe+chat@gmail.com",
+                Err(SignupRejection::BlockedAddress),
+            ),
+            (&blocked, "jane.doe@outlook.com", Ok("jane.doe@outlook.com")),
+            (
+                &disposable_allowed,
+                "jane@yopmail.com",
+                Ok("jane@yopmail.com"),
+            ),
+            (&disposable_allowed, "", Err(SignupRejection::InvalidEmail)),
+        ];
+
+        for (policy, email, expected) in cases {
+            assert_eq!(
+                Signup::check(policy, email),
+                expected.map(str::to_owned),
+                "checking {:?} against {:?}",
+                email,
+                policy
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn aliases_of_a_registered_address_find_its_owner() {
+        let repository = InMemoryChatRepository::new();
+        let policy = SignupPolicy::default();
+
+        repository
+            .claim_email(&EmailOwner::new("janedoe@gmail.com", "jane"))
+            .await
+            .unwrap();
+
+        let cases = [
+            ("janedoe@gmail.com", Some("jane")),
+            ("Jane.Doe@gmail.com", Some("jane")),
+            ("jane.doe+chat@googlemail.com", Some("jane")),
+            ("J.A.N.E.D.O.E@GMAIL.COM", Some("jane")),
+            ("janedoe@example.com", None),
+            ("jane.doe2@gmail.com", None),
+        ];
+
+        for (email, expected_owner) in cases {
+            let normalized_email = Signup::check(&policy, email).unwrap();
+            let owner = repository.get_email_owner(&normalized_email).await.unwrap();
+
+            assert_eq!(
+                owner.map(|owner| owner.sub).as_deref(),
+                expected_owner,
+                "looking up {:?}",
+                email
+            );
+        }
+    }
+}
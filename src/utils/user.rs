@@ -1,18 +1,112 @@
 /*---------- Imports ----------*/
 use crate::models::user::User as UserModel;
-use aws_sdk_cognitoidentityprovider::model::AttributeType;
+use aws_sdk_cognitoidentityprovider::model::{AttributeType, UserType};
+use base64::{engine::general_purpose, Engine};
+use chrono::{SecondsFormat, TimeZone, Utc};
 use serde::de::value::{Error, MapDeserializer};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub struct User;
 
+/*---------- Structs ----------*/
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSearchPage {
+    pub users: Vec<UserModel>,
+    pub next_cursor: Option<String>,
+}
+
+/// Search progress across the two `ListUsers` calls, one per attribute.
+/// A source is exhausted once Cognito stops returning a pagination token.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct SearchCursor {
+    name_token: Option<String>,
+    name_exhausted: bool,
+    email_token: Option<String>,
+    email_exhausted: bool,
+}
+
 /*---------- Enums ----------*/
+#[derive(Debug)]
 pub enum GetUserError {
     NotFound,
     RequestFailed,
     MissingAttributes,
     InvalidUserSchema,
+    InvalidCursor,
+}
+
+impl std::fmt::Display for GetUserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GetUserError::NotFound => write!(f, "User not found"),
+            GetUserError::RequestFailed => write!(f, "Cognito request failed"),
+            GetUserError::MissingAttributes => write!(f, "User has no attributes"),
+            GetUserError::InvalidUserSchema => {
+                write!(f, "User attributes don't match the expected schema")
+            }
+            GetUserError::InvalidCursor => write!(f, "Pagination cursor is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for GetUserError {}
+
+/// Escapes a value for use inside a double-quoted `ListUsers` filter, so it
+/// can't terminate the string and smuggle in its own expression.
+pub fn escape_filter_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn parse_user(user_info: &UserType) -> Result<UserModel, GetUserError> {
+    let user_attributes = match user_info.attributes() {
+        Some(attributes) => attributes,
+        None => return Err(GetUserError::MissingAttributes),
+    };
+
+    let response = generate_attrs_map(user_attributes);
+
+    match UserModel::deserialize(MapDeserializer::<_, Error>::new(response.into_iter())) {
+        Ok(parsed) => Ok(parsed),
+        Err(_) => Err(GetUserError::InvalidUserSchema),
+    }
+}
+
+async fn list_users_by_prefix(
+    cognito_client: &aws_sdk_cognitoidentityprovider::Client,
+    userpool_id: &str,
+    attribute: &str,
+    prefix: &str,
+    limit: i32,
+    pagination_token: Option<String>,
+) -> Result<(Vec<UserModel>, Option<String>), GetUserError> {
+    let filter_query = format!("{} ^= \"{}\"", attribute, escape_filter_value(prefix));
+
+    let list_users_output = cognito_client
+        .list_users()
+        .user_pool_id(userpool_id)
+        .filter(filter_query)
+        .limit(limit)
+        .set_pagination_token(pagination_token)
+        .send()
+        .await
+        .map_err(|_| GetUserError::RequestFailed)?;
+
+    let users = list_users_output
+        .users()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|user_info| parse_user(user_info).ok())
+        .collect();
+
+    Ok((
+        users,
+        list_users_output
+            .pagination_token()
+            .map(|token| token.to_owned()),
+    ))
 }
 
 fn generate_attrs_map(attributes_list: &[AttributeType]) -> HashMap<String, String> {
@@ -47,7 +141,7 @@ impl User {
         userpool_id: &str,
         sub: &str,
     ) -> Result<UserModel, GetUserError> {
-        let filter_query = format!("sub = \"{}\"", sub);
+        let filter_query = format!("sub = \"{}\"", escape_filter_value(sub));
         let list_users_request = cognito_client
             .list_users()
             .user_pool_id(userpool_id)
@@ -61,22 +155,12 @@ impl User {
             Err(_) => return Err(GetUserError::RequestFailed),
         };
 
-        let user_info = match users_list.users().unwrap_or_default().get(0) {
+        let user_info = match users_list.users().unwrap_or_default().first() {
             Some(info) => info,
             None => return Err(GetUserError::NotFound),
         };
 
-        let user_attributes = match user_info.attributes() {
-            Some(attributes) => attributes,
-            None => return Err(GetUserError::MissingAttributes),
-        };
-
-        let response = generate_attrs_map(user_attributes);
-
-        match UserModel::deserialize(MapDeserializer::<_, Error>::new(response.into_iter())) {
-            Ok(parsed) => Ok(parsed),
-            Err(_) => Err(GetUserError::InvalidUserSchema),
-        }
+        parse_user(user_info)
     }
 
     pub async fn get_user_by_email(
@@ -108,4 +192,141 @@ impl User {
             Err(_) => Err(GetUserError::InvalidUserSchema),
         }
     }
+
+    /// Prefix search on name and email. Cognito filters accept a single
+    /// attribute, so both are queried and merged, and the cursor carries the
+    /// pagination token of each one. Emails are only searched with whatever
+    /// room the names leave, so a page never holds more than `limit` users.
+    pub async fn search_users(
+        cognito_client: &aws_sdk_cognitoidentityprovider::Client,
+        userpool_id: &str,
+        query: &str,
+        limit: i32,
+        cursor: Option<&str>,
+    ) -> Result<UserSearchPage, GetUserError> {
+        let mut search_cursor: SearchCursor = match cursor {
+            Some(encoded) => general_purpose::URL_SAFE_NO_PAD
+                .decode(encoded)
+                .ok()
+                .and_then(|decoded| serde_json::from_slice(&decoded).ok())
+                .ok_or(GetUserError::InvalidCursor)?,
+            None => SearchCursor::default(),
+        };
+
+        let mut users: Vec<UserModel> = vec![];
+
+        if !search_cursor.name_exhausted {
+            let (found, token) = list_users_by_prefix(
+                cognito_client,
+                userpool_id,
+                "name",
+                query,
+                limit,
+                search_cursor.name_token.take(),
+            )
+            .await?;
+
+            users.extend(found);
+            search_cursor.name_exhausted = token.is_none();
+            search_cursor.name_token = token;
+        }
+
+        let remaining = limit - users.len() as i32;
+
+        if !search_cursor.email_exhausted && remaining > 0 {
+            let (found, token) = list_users_by_prefix(
+                cognito_client,
+                userpool_id,
+                "email",
+                query,
+                remaining,
+                search_cursor.email_token.take(),
+            )
+            .await?;
+
+            users.extend(found);
+            search_cursor.email_exhausted = token.is_none();
+            search_cursor.email_token = token;
+        }
+
+        let mut seen_subs: Vec<String> = vec![];
+        users.retain(|user| {
+            if seen_subs.contains(&user.sub) {
+                return false;
+            }
+
+            seen_subs.push(user.sub.to_owned());
+            true
+        });
+
+        let next_cursor = if search_cursor.name_exhausted && search_cursor.email_exhausted {
+            None
+        } else {
+            let serialized_cursor = serde_json::to_vec(&search_cursor).unwrap_or_default();
+
+            Some(general_purpose::URL_SAFE_NO_PAD.encode(serialized_cursor))
+        };
+
+        Ok(UserSearchPage { users, next_cursor })
+    }
+
+    /// One page of every user in the pool, each with the RFC 3339 date the
+    /// account was created. Users whose attributes can't be parsed are
+    /// skipped.
+    pub async fn list_users(
+        cognito_client: &aws_sdk_cognitoidentityprovider::Client,
+        userpool_id: &str,
+        pagination_token: Option<String>,
+    ) -> Result<(Vec<(UserModel, String)>, Option<String>), GetUserError> {
+        let list_users_output = cognito_client
+            .list_users()
+            .user_pool_id(userpool_id)
+            .set_pagination_token(pagination_token)
+            .send()
+            .await
+            .map_err(|_| GetUserError::RequestFailed)?;
+
+        let users = list_users_output
+            .users()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|user_info| {
+                let user = parse_user(user_info).ok()?;
+                let created_at = user_info
+                    .user_create_date()
+                    .and_then(|date| Utc.timestamp_opt(date.secs(), date.subsec_nanos()).single())
+                    .unwrap_or_else(Utc::now)
+                    .to_rfc3339_opts(SecondsFormat::Millis, true);
+
+                Some((user, created_at))
+            })
+            .collect();
+
+        Ok((
+            users,
+            list_users_output
+                .pagination_token()
+                .map(|token| token.to_owned()),
+        ))
+    }
+
+    pub async fn update_name(
+        cognito_client: &aws_sdk_cognitoidentityprovider::Client,
+        userpool_id: &str,
+        username: &str,
+        name: &str,
+    ) -> Result<(), GetUserError> {
+        let name_attribute = AttributeType::builder().name("name").value(name).build();
+
+        cognito_client
+            .admin_update_user_attributes()
+            .user_pool_id(userpool_id)
+            .username(username)
+            .user_attributes(name_attribute)
+            .send()
+            .await
+            .map_err(|_| GetUserError::RequestFailed)?;
+
+        Ok(())
+    }
 }
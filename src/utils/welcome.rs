@@ -0,0 +1,72 @@
+/*---------- Imports ----------*/
+use crate::models::{
+    chat::{Message, MessageType},
+    user::User,
+};
+use std::env;
+
+/*---------- Constants ----------*/
+const DEFAULT_BOT_NAME: &str = "ChatApp";
+const DEFAULT_TEMPLATE: &str = "Hi {name}, welcome to ChatApp! Search for people by their email \
+     to start a conversation, and change your name or avatar from your profile whenever you like.";
+
+/*---------- Structs ----------*/
+/// The system account that greets new users, and what it says.
+#[derive(Debug, Clone)]
+pub struct WelcomeConfig {
+    /// Sub of the bot's Cognito user. The stream processor looks both sides
+    /// of a chat up in the pool, so the account has to exist there.
+    pub bot_sub: String,
+    pub bot_name: String,
+    /// Message text, `{name}` is replaced with the new user's name.
+    pub template: String,
+}
+
+impl WelcomeConfig {
+    /// Reads `WELCOME_BOT_SUB`, `WELCOME_BOT_NAME` and `WELCOME_MESSAGE`.
+    /// Without a bot sub, or with an empty one, no welcome is sent.
+    pub fn from_env() -> Option<Self> {
+        let bot_sub = env::var("WELCOME_BOT_SUB")
+            .ok()
+            .filter(|sub| !sub.trim().is_empty())?;
+
+        Some(Self {
+            bot_sub: bot_sub.trim().to_owned(),
+            bot_name: env::var("WELCOME_BOT_NAME").unwrap_or(DEFAULT_BOT_NAME.to_owned()),
+            template: env::var("WELCOME_MESSAGE").unwrap_or(DEFAULT_TEMPLATE.to_owned()),
+        })
+    }
+}
+
+pub struct Welcome;
+
+impl Welcome {
+    pub fn render(config: &WelcomeConfig, user: &User) -> String {
+        config.template.replace("{name}", &user.name)
+    }
+
+    /// The bot's first message to `user`, stored like any other private
+    /// message so the stream processor creates both chat summaries.
+    pub fn message(
+        config: &WelcomeConfig,
+        user: &User,
+        message_id: &str,
+        timestamp: &str,
+    ) -> Option<Message> {
+        // The bot account can go through sign-up itself
+        if user.sub == config.bot_sub {
+            return None;
+        }
+
+        let bot = User::new(&config.bot_sub, &config.bot_name, "");
+
+        Some(Message::private(
+            &bot,
+            &user.sub,
+            message_id,
+            timestamp,
+            &Self::render(config, user),
+            MessageType::Text,
+        ))
+    }
+}
@@ -0,0 +1,468 @@
+/*---------- Imports ----------*/
+use crate::{
+    api::{
+        dto::{MessageStatusUpdate, ReceivedMessage},
+        error::ApiError,
+    },
+    models::{
+        chat::{ChatType, Message, MessagePayload, MessageStatus, MessageType},
+        common::WebSocketEvent,
+        user::User,
+    },
+    notifier::{ConnectionNotifier, NotifyError},
+    repository::ChatRepository,
+};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::{future, str::FromStr};
+use tracing::error;
+use ulid::Ulid;
+
+pub async fn send_websocket_message(
+    notifier: &impl ConnectionNotifier,
+    connection_id: &str,
+    message: Value,
+) -> Result<(), NotifyError> {
+    notifier.send(connection_id, &message).await
+}
+
+async fn save_private_message(
+    repository: &impl ChatRepository,
+    message_payload: &MessagePayload,
+    message_id: &str,
+    message_timestamp: &str,
+    user_info: &User,
+) -> Result<(), ApiError> {
+    let receiver_sub = match &message_payload.user_sub {
+        Some(sub) => sub,
+        None => return Err(ApiError::Validation("userSub is required".to_owned())),
+    };
+
+    let message = Message::private(
+        user_info,
+        receiver_sub,
+        message_id,
+        message_timestamp,
+        &message_payload.content,
+        message_payload.message_type,
+    );
+
+    match repository.save_message(&message).await {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            error!("Couldn't save message {}: {}", message_id, error);
+
+            Err(ApiError::MessageNotSaved)
+        }
+    }
+}
+
+async fn handle_send_private_message(
+    repository: &impl ChatRepository,
+    notifier: &impl ConnectionNotifier,
+    message_payload: &MessagePayload,
+    message_id: &str,
+    message_timestamp: &str,
+    user_info: &User,
+) {
+    let receiver_sub = match &message_payload.user_sub {
+        Some(sub) => sub,
+        None => return,
+    };
+
+    if let Ok(Some(connection)) = repository.get_connection(receiver_sub).await {
+        let message_type = MessageType::Text;
+        let chat_type = ChatType::Private;
+        let message_content = &message_payload.content;
+
+        let message_payload = json!(WebSocketEvent {
+            action: "receive-message".to_owned(),
+            data: ReceivedMessage {
+                timestamp: message_timestamp.to_owned(),
+                message_type,
+                chat_type,
+                content: message_content.to_owned(),
+                message_id: message_id.to_owned(),
+                sender: user_info.clone(),
+            },
+        });
+
+        send_websocket_message(notifier, &connection.connection_id, message_payload)
+            .await
+            .ok();
+    }
+}
+
+/// The `send-message` route: saves the message, forwards it to the receiver
+/// when they are connected and answers the sender with a `message-status`
+/// frame. Failures the client caused are reported in that frame, only a
+/// failed send to the sender's connection is returned.
+pub async fn handle_send_message(
+    repository: &impl ChatRepository,
+    notifier: &impl ConnectionNotifier,
+    connection_id: &str,
+    user_info: Option<User>,
+    body: Option<String>,
+) -> Result<(), NotifyError> {
+    let user_info = match user_info {
+        Some(user) => user,
+        None => {
+            let error_message = ApiError::InvalidToken.to_message_status(None);
+
+            send_websocket_message(notifier, connection_id, error_message).await?;
+
+            return Ok(());
+        }
+    };
+
+    let parsed_body = match Value::from_str(&body.unwrap_or("No body in request".to_owned())) {
+        Ok(parsed_body) => parsed_body,
+        Err(_) => {
+            let error_message = ApiError::InvalidBody.to_message_status(None);
+
+            send_websocket_message(notifier, connection_id, error_message).await?;
+
+            return Ok(());
+        }
+    };
+
+    // Read before validating, so even a rejected message can be matched to
+    // the pending one on the client
+    let raw_temp_id = parsed_body["data"]["tempId"]
+        .as_str()
+        .map(|temp_id| temp_id.to_owned());
+
+    let message_payload =
+        match serde_json::from_value::<WebSocketEvent<MessagePayload>>(parsed_body) {
+            Ok(parsed_body) => parsed_body.data,
+            Err(error) => {
+                let error_message = ApiError::Validation(error.to_string())
+                    .to_message_status(raw_temp_id.as_deref());
+
+                send_websocket_message(notifier, connection_id, error_message).await?;
+
+                return Ok(());
+            }
+        };
+
+    if let Some(receiver_sub) = &message_payload.user_sub {
+        if receiver_sub == &user_info.sub {
+            let error_message =
+                ApiError::SelfMessage.to_message_status(Some(&message_payload.temp_id));
+
+            send_websocket_message(notifier, connection_id, error_message).await?;
+
+            return Ok(());
+        }
+    }
+
+    let message_id = Ulid::new().to_string();
+    let message_status = MessageStatus::Ok;
+    let current_timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+    let (_, send_msg_result) = future::join!(
+        handle_send_private_message(
+            repository,
+            notifier,
+            &message_payload,
+            &message_id,
+            &current_timestamp,
+            &user_info,
+        ),
+        save_private_message(
+            repository,
+            &message_payload,
+            &message_id,
+            &current_timestamp,
+            &user_info
+        )
+    )
+    .await;
+
+    let result_payload = match send_msg_result {
+        Ok(()) => {
+            let success_payload = json!(WebSocketEvent {
+                action: "message-status".to_owned(),
+                data: MessageStatusUpdate {
+                    status: message_status,
+                    temp_id: Some(message_payload.temp_id.to_owned()),
+                    timestamp: Some(current_timestamp.to_owned()),
+                    message_id: Some(message_id.to_owned()),
+                    code: None,
+                    message: None,
+                },
+            });
+
+            success_payload
+        }
+
+        Err(error) => error.to_message_status(Some(&message_payload.temp_id)),
+    };
+
+    send_websocket_message(notifier, connection_id, result_payload).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::connection::Connection, notifier::memory::InMemoryNotifier,
+        repository::memory::InMemoryChatRepository,
+    };
+
+    const SENDER_SUB: &str = "sender";
+    const SENDER_CONNECTION: &str = "sender-connection";
+    const RECEIVER_SUB: &str = "receiver";
+    const RECEIVER_CONNECTION: &str = "receiver-connection";
+
+    fn sender() -> Option<User> {
+        Some(User::new(SENDER_SUB, "Jane", "jane@example.com"))
+    }
+
+    fn send_message_body(receiver_sub: &str) -> Option<String> {
+        Some(
+            json!({
+                "action": "send-message",
+                "data": {
+                    "tempId": "temp-1",
+                    "content": "Hi!",
+                    "chatType": "private",
+                    "userSub": receiver_sub,
+                },
+            })
+            .to_string(),
+        )
+    }
+
+    async fn connected_repository() -> InMemoryChatRepository {
+        let repository = InMemoryChatRepository::new();
+
+        repository
+            .save_connection(&Connection::new(RECEIVER_SUB, RECEIVER_CONNECTION))
+            .await
+            .unwrap();
+
+        repository
+    }
+
+    async fn send(
+        repository: &InMemoryChatRepository,
+        notifier: &InMemoryNotifier,
+        user_info: Option<User>,
+        body: Option<String>,
+    ) -> Result<(), NotifyError> {
+        handle_send_message(repository, notifier, SENDER_CONNECTION, user_info, body).await
+    }
+
+    async fn saved_messages(repository: &InMemoryChatRepository) -> Vec<Message> {
+        repository
+            .list_private_messages(SENDER_SUB, RECEIVER_SUB)
+            .await
+            .unwrap()
+    }
+
+    fn assert_sent_ok(notifier: &InMemoryNotifier) -> Value {
+        let status = notifier.last_frame(SENDER_CONNECTION).unwrap();
+
+        assert_eq!(status["action"], "message-status");
+        assert_eq!(status["data"]["status"], "ok");
+        assert_eq!(status["data"]["tempId"], "temp-1");
+
+        status
+    }
+
+    fn assert_sent_error(notifier: &InMemoryNotifier, code: &str) {
+        let status = notifier.last_frame(SENDER_CONNECTION).unwrap();
+
+        assert_eq!(status["action"], "message-status");
+        assert_eq!(status["data"]["status"], "error");
+        assert_eq!(status["data"]["code"], code);
+    }
+
+    #[tokio::test]
+    async fn connected_receivers_get_the_message() {
+        let repository = connected_repository().await;
+        let notifier = InMemoryNotifier::new();
+
+        send(
+            &repository,
+            &notifier,
+            sender(),
+            send_message_body(RECEIVER_SUB),
+        )
+        .await
+        .unwrap();
+
+        let status = assert_sent_ok(&notifier);
+        let delivered = notifier.frames(RECEIVER_CONNECTION);
+
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0]["action"], "receive-message");
+        assert_eq!(delivered[0]["data"]["content"], "Hi!");
+        assert_eq!(delivered[0]["data"]["sender"]["sub"], SENDER_SUB);
+        assert_eq!(
+            delivered[0]["data"]["messageId"],
+            status["data"]["messageId"]
+        );
+
+        let messages = saved_messages(&repository).await;
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].message_id(),
+            status["data"]["messageId"].as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn offline_receivers_still_get_the_message_saved() {
+        let repository = InMemoryChatRepository::new();
+        let notifier = InMemoryNotifier::new();
+
+        send(
+            &repository,
+            &notifier,
+            sender(),
+            send_message_body(RECEIVER_SUB),
+        )
+        .await
+        .unwrap();
+
+        assert_sent_ok(&notifier);
+        assert_eq!(saved_messages(&repository).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_gone_receiver_doesnt_fail_the_send() {
+        let repository = connected_repository().await;
+        let notifier = InMemoryNotifier::new();
+
+        notifier.close(RECEIVER_CONNECTION);
+
+        send(
+            &repository,
+            &notifier,
+            sender(),
+            send_message_body(RECEIVER_SUB),
+        )
+        .await
+        .unwrap();
+
+        assert_sent_ok(&notifier);
+        assert!(notifier.frames(RECEIVER_CONNECTION).is_empty());
+        assert_eq!(saved_messages(&repository).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_throttled_receiver_doesnt_fail_the_send() {
+        let repository = connected_repository().await;
+        let notifier = InMemoryNotifier::new();
+
+        notifier.throttle(RECEIVER_CONNECTION, 1);
+
+        send(
+            &repository,
+            &notifier,
+            sender(),
+            send_message_body(RECEIVER_SUB),
+        )
+        .await
+        .unwrap();
+
+        assert_sent_ok(&notifier);
+        assert!(notifier.frames(RECEIVER_CONNECTION).is_empty());
+        assert_eq!(saved_messages(&repository).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_gone_sender_is_reported_after_saving() {
+        let repository = connected_repository().await;
+        let notifier = InMemoryNotifier::new();
+
+        notifier.close(SENDER_CONNECTION);
+
+        let send_result = send(
+            &repository,
+            &notifier,
+            sender(),
+            send_message_body(RECEIVER_SUB),
+        )
+        .await;
+
+        assert_eq!(send_result, Err(NotifyError::Gone));
+        assert_eq!(notifier.frames(RECEIVER_CONNECTION).len(), 1);
+        assert_eq!(saved_messages(&repository).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn messages_to_oneself_are_rejected() {
+        let repository = InMemoryChatRepository::new();
+        let notifier = InMemoryNotifier::new();
+
+        send(
+            &repository,
+            &notifier,
+            sender(),
+            send_message_body(SENDER_SUB),
+        )
+        .await
+        .unwrap();
+
+        assert_sent_error(&notifier, "SELF_MESSAGE");
+        assert!(repository
+            .list_private_messages(SENDER_SUB, SENDER_SUB)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_and_malformed_frames_are_rejected() {
+        let repository = connected_repository().await;
+        let notifier = InMemoryNotifier::new();
+
+        send(
+            &repository,
+            &notifier,
+            None,
+            send_message_body(RECEIVER_SUB),
+        )
+        .await
+        .unwrap();
+        assert_sent_error(&notifier, "INVALID_TOKEN");
+
+        send(
+            &repository,
+            &notifier,
+            sender(),
+            Some("not json".to_owned()),
+        )
+        .await
+        .unwrap();
+        assert_sent_error(&notifier, "INVALID_BODY");
+
+        let missing_chat_type = json!({
+            "action": "send-message",
+            "data": { "tempId": "temp-2", "userSub": RECEIVER_SUB },
+        });
+
+        send(
+            &repository,
+            &notifier,
+            sender(),
+            Some(missing_chat_type.to_string()),
+        )
+        .await
+        .unwrap();
+        assert_sent_error(&notifier, "VALIDATION_FAILED");
+        assert_eq!(
+            notifier.last_frame(SENDER_CONNECTION).unwrap()["data"]["tempId"],
+            "temp-2"
+        );
+
+        assert!(notifier.frames(RECEIVER_CONNECTION).is_empty());
+        assert!(saved_messages(&repository).await.is_empty());
+    }
+}